@@ -29,5 +29,9 @@ module hyperperp::account {
     }
 
     // Public getter functions
-    public fun get_collateral(addr: address): u64 acquires Account { borrow_global<Account>(addr).collateral }
+    #[view]
+    public fun get_collateral(addr: address): u64 acquires Account {
+        if (!exists<Account>(addr)) return 0;
+        borrow_global<Account>(addr).collateral
+    }
 }
\ No newline at end of file
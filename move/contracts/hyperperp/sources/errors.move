@@ -29,6 +29,12 @@ module hyperperp::errors {
     /// Batch expired
     const E_BATCH_EXPIRED: u64 = 13;
 
+    /// Batch already applied
+    const E_BATCH_ALREADY_APPLIED: u64 = 14;
+
+    /// Batch argument vectors differ in length
+    const E_BATCH_LENGTH_MISMATCH: u64 = 15;
+
     /// Unknown market
     const E_UNKNOWN_MARKET: u64 = 20;
 
@@ -81,6 +87,10 @@ module hyperperp::errors {
 
     public fun e_batch_expired(): u64 { E_BATCH_EXPIRED }
 
+    public fun e_batch_already_applied(): u64 { E_BATCH_ALREADY_APPLIED }
+
+    public fun e_batch_length_mismatch(): u64 { E_BATCH_LENGTH_MISMATCH }
+
     public fun e_unknown_market(): u64 { E_UNKNOWN_MARKET }
 
     public fun e_position_not_found(): u64 { E_POSITION_NOT_FOUND }
@@ -29,7 +29,7 @@ module hyperperp::events {
 
     /// Funding event
     public struct FundingEvent has copy, drop, store {
-        market_id: u64, rate: u64
+        market_id: u64, rate: u64, longs_pay: bool
     }
 
     /// Liquidation event
@@ -47,6 +47,31 @@ module hyperperp::events {
         user: address, market_id: u64, size_closed: u128, close_price: u64, pnl: u128, is_profit: bool
     }
 
+    /// Net settlement event, one per user and market of a netted batch
+    #[event]
+    public struct NetSettlementEvent has copy, drop, store {
+        batch_id: vector<u8>,
+        user: address,
+        market_id: u64,
+        size: u128,
+        is_long: bool,
+        quote: u128,
+        receives_quote: bool,
+        taker_fee: u64,
+        maker_fee: u64,
+        fill_count: u64
+    }
+
+    /// Liquidation penalty moved from the liquidated account to the insurance account
+    #[event]
+    public struct LiquidationPenaltyEvent has copy, drop, store {
+        liquidation_id: vector<u8>,
+        user: address,
+        insurance: address,
+        market_id: u64,
+        amount: u128
+    }
+
     // Public constructor functions
     public fun new_deposit_event(user: address, amount: u64): DepositEvent {
         DepositEvent { user, amount }
@@ -72,8 +97,33 @@ module hyperperp::events {
         PositionCloseEvent { user, market_id, size_closed, close_price, pnl, is_profit }
     }
 
-    public fun new_funding_event(market_id: u64, rate: u64): FundingEvent {
-        FundingEvent { market_id, rate }
+    public fun new_funding_event(market_id: u64, rate: u64, longs_pay: bool): FundingEvent {
+        FundingEvent { market_id, rate, longs_pay }
+    }
+
+    public fun new_net_settlement_event(
+        batch_id: vector<u8>,
+        user: address,
+        market_id: u64,
+        size: u128,
+        is_long: bool,
+        quote: u128,
+        receives_quote: bool,
+        taker_fee: u64,
+        maker_fee: u64,
+        fill_count: u64
+    ): NetSettlementEvent {
+        NetSettlementEvent { batch_id, user, market_id, size, is_long, quote, receives_quote, taker_fee, maker_fee, fill_count }
+    }
+
+    public fun new_liquidation_penalty_event(
+        liquidation_id: vector<u8>,
+        user: address,
+        insurance: address,
+        market_id: u64,
+        amount: u128
+    ): LiquidationPenaltyEvent {
+        LiquidationPenaltyEvent { liquidation_id, user, insurance, market_id, amount }
     }
 
     public entry fun init_events(admin: &signer) {
@@ -124,4 +174,12 @@ module hyperperp::events {
         let store = borrow_global_mut<EventStore>(addr);
         event::emit_event(&mut store.position_close_events, e)
     }
-}
\ No newline at end of file
+
+    public fun emit_net_settlement(e: NetSettlementEvent) {
+        event::emit(e)
+    }
+
+    public fun emit_liquidation_penalty(e: LiquidationPenaltyEvent) {
+        event::emit(e)
+    }
+}
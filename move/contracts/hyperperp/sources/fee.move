@@ -8,6 +8,7 @@ module hyperperp::fee {
         move_to(admin, Fees { maker_bps, taker_bps, insurance_bps, accrued: 0 });
     }
 
+    #[view]
     public fun params(addr: address): (u64, u64, u64) acquires Fees { let f = borrow_global<Fees>(addr); (f.maker_bps, f.taker_bps, f.insurance_bps) }
     public fun accrue(addr: address, amount: u64) acquires Fees { let f = borrow_global_mut<Fees>(addr); f.accrued += amount }
 }
@@ -27,4 +27,13 @@ module hyperperp::market_registry {
     }
 
     public fun get(admin_addr: address, id: u64): Market acquires Markets { *borrow_global<Markets>(admin_addr).by_id.borrow(id) }
+
+    /// (symbol, imr_bps, mmr_bps, lot_size, tick_size, max_leverage_x) of a market
+    #[view]
+    public fun params(admin_addr: address, id: u64): (vector<u8>, u64, u64, u64, u64, u64) acquires Markets {
+        let reg = borrow_global<Markets>(admin_addr);
+        assert!(reg.by_id.contains(id), errors::e_unknown_market());
+        let m = reg.by_id.borrow(id);
+        (m.symbol, m.imr_bps, m.mmr_bps, m.lot_size, m.tick_size, m.max_leverage_x)
+    }
 }
\ No newline at end of file
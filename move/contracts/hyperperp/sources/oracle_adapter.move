@@ -12,6 +12,15 @@ module hyperperp::oracle_adapter {
         c.by_market.upsert(market_id, Price { px, conf, ts });
     }
 
+    /// (px, conf, ts) last pushed for a market, without the staleness check; all zero if none
+    #[view]
+    public fun price_of(cfg_addr: address, market_id: u64): (u64, u64, u64) acquires Cache {
+        let c = borrow_global<Cache>(cfg_addr);
+        if (!c.by_market.contains(market_id)) return (0, 0, 0);
+        let p = c.by_market.borrow(market_id);
+        (p.px, p.conf, p.ts)
+    }
+
     public fun read_price(cfg_addr: address, market_id: u64, now_ts: u64): Price acquires Cache {
         let c = borrow_global<Cache>(cfg_addr);
         let p = *c.by_market.borrow(market_id);
@@ -1,7 +1,11 @@
 module hyperperp::perp_engine {
+    use std::signer;
+    use std::table;
     use std::vector;
+    use aptos_framework::timestamp;
     use hyperperp::events;
     use hyperperp::errors;
+    use hyperperp::gov;
     use hyperperp::constants;
     use hyperperp::account as acct;
     use hyperperp::positions as pos;
@@ -16,7 +20,8 @@ module hyperperp::perp_engine {
         size: u128, 
         price_x: u64, 
         fee_bps: u64, 
-        ts: u64 
+        ts: u64,
+        taker_is_long: bool
     }
     
     public struct SettlementBatch has drop, store, copy { 
@@ -27,6 +32,35 @@ module hyperperp::perp_engine {
         expiry: u64 
     }
 
+    /// Net position and quote change of one user in one market, produced by off-chain
+    /// netting of a batch. The quote, entry notional and fees are sums of the per-fill
+    /// amounts `apply_batch_at` would move, so both paths settle the same state.
+    public struct NetDelta has drop, store, copy {
+        owner: address,
+        market_id: u64,
+        size: u128,
+        is_long: bool,
+        price_x: u64, // average price of the netted size
+        min_fill_px: u64, // lowest and highest price among the netted fills
+        max_fill_px: u64,
+        entry_notional: u128, // sum of the per-fill notionals
+        quote: u128,
+        receives_quote: bool,
+        taker_fee: u64,
+        maker_fee: u64,
+        fill_count: u64
+    }
+
+    /// Accounts allowed to settle batches on behalf of the admin, stored at the admin address
+    struct Settlers has key {
+        members: vector<address>,
+    }
+
+    /// Off-chain batch ids already applied, keyed to the oracle timestamp they were applied with
+    struct AppliedBatches has key {
+        ids: table::Table<vector<u8>, u64>,
+    }
+
     /// Funding rate information
     public struct FundingRate has drop, store, copy {
         market_id: u64,
@@ -45,21 +79,53 @@ module hyperperp::perp_engine {
 
     // Public constructor functions
     public fun new_batch_fill(taker: address, maker: address, market_id: u64, size: u128, price_x: u64, fee_bps: u64, ts: u64): BatchFill {
-        BatchFill { taker, maker, market_id, size, price_x, fee_bps, ts }
+        new_sided_batch_fill(taker, maker, market_id, size, price_x, fee_bps, ts, true)
+    }
+
+    /// A fill whose taker buys (`taker_is_long`) or sells; the maker takes the other side.
+    public fun new_sided_batch_fill(
+        taker: address, maker: address, market_id: u64, size: u128, price_x: u64, fee_bps: u64, ts: u64, taker_is_long: bool
+    ): BatchFill {
+        BatchFill { taker, maker, market_id, size, price_x, fee_bps, ts, taker_is_long }
     }
     
     public fun new_settlement_batch(fills: vector<BatchFill>, oracle_ts: u64, min_px: u64, max_px: u64, expiry: u64): SettlementBatch {
         SettlementBatch { fills, oracle_ts, min_px, max_px, expiry }
     }
 
+    public fun new_net_delta(
+        owner: address,
+        market_id: u64,
+        size: u128,
+        is_long: bool,
+        price_x: u64,
+        min_fill_px: u64,
+        max_fill_px: u64,
+        entry_notional: u128,
+        quote: u128,
+        receives_quote: bool,
+        taker_fee: u64,
+        maker_fee: u64,
+        fill_count: u64
+    ): NetDelta {
+        NetDelta {
+            owner, market_id, size, is_long, price_x, min_fill_px, max_fill_px, entry_notional,
+            quote, receives_quote, taker_fee, maker_fee, fill_count
+        }
+    }
+
     public fun new_funding_rate(market_id: u64, rate: u64, timestamp: u64, next_funding_time: u64): FundingRate {
         FundingRate { market_id, rate, timestamp, next_funding_time }
     }
 
     /// Apply a batch of fills to update positions and collect fees
     public fun apply_batch(admin: &signer, batch: SettlementBatch, events_addr: address) {
-        let now = batch.oracle_ts; // in MVP we trust provided ts; later use block timestamp
-        assert!(now <= batch.expiry, errors::e_batch_expired());
+        apply_batch_at(signer::address_of(admin), batch, events_addr);
+    }
+
+    fun apply_batch_at(admin_addr: address, batch: SettlementBatch, events_addr: address) {
+        // Block time, not the submitter's oracle timestamp, decides whether the batch expired
+        assert!(timestamp::now_seconds() <= batch.expiry, errors::e_batch_expired());
 
         let n = batch.fills.length();
         let i = 0;
@@ -78,15 +144,19 @@ module hyperperp::perp_engine {
             let fee_calc = calculate_fees(f.size, f.price_x, f.fee_bps);
 
             // Update positions
-            apply_fill(f.taker, f.market_id, f.size, f.price_x, true, events_addr);  // taker goes long
-            apply_fill(f.maker, f.market_id, f.size, f.price_x, false, events_addr); // maker goes short
+            apply_fill(f.taker, f.market_id, f.size, f.price_x, f.taker_is_long, events_addr);
+            apply_fill(f.maker, f.market_id, f.size, f.price_x, !f.taker_is_long, events_addr);
 
-            // Quote settlement within pooled FA ledger (USDC)
+            // Quote settlement within pooled FA ledger (USDC): the buyer pays the seller
             let quote_delta: u128 = (f.size * (f.price_x as u128)) / (constants::px_scale() as u128);
-            vfa::transfer_internal(admin, f.taker, f.maker, quote_delta);
+            if (f.taker_is_long) {
+                vfa::transfer_at(admin_addr, f.taker, f.maker, quote_delta);
+            } else {
+                vfa::transfer_at(admin_addr, f.maker, f.taker, quote_delta);
+            };
 
             // Collect fees to vault (placeholder)
-            collect_fees(admin, f.taker, f.maker, fee_calc, events_addr);
+            collect_fees(admin_addr, f.taker, f.maker, fee_calc, events_addr);
 
             // Emit fill event
             events::emit_fill(events_addr, events::new_fill_event(f.taker, f.maker, f.market_id, f.size, f.price_x, f.fee_bps));
@@ -95,6 +165,240 @@ module hyperperp::perp_engine {
         }
     }
 
+    /// Apply a batch at most once. `batch_id` is the matching engine's batch UUID,
+    /// so a resubmitted batch aborts instead of applying its fills twice.
+    /// `settler` is the admin at `admin_addr` or one of its whitelisted settlers.
+    public fun apply_batch_once(
+        settler: &signer,
+        admin_addr: address,
+        batch_id: vector<u8>,
+        batch: SettlementBatch,
+        events_addr: address
+    ) acquires AppliedBatches, Settlers {
+        record_batch(settler, admin_addr, batch_id, batch.oracle_ts);
+        apply_batch_at(admin_addr, batch, events_addr);
+    }
+
+    /// Apply a netted batch at most once: one position update and one quote movement
+    /// per user and market instead of two per fill. Every netted fill price must lie
+    /// within `min_px`..`max_px`, as in `apply_batch_at`.
+    public fun apply_net_batch_once(
+        settler: &signer,
+        admin_addr: address,
+        batch_id: vector<u8>,
+        deltas: vector<NetDelta>,
+        oracle_ts: u64,
+        min_px: u64,
+        max_px: u64,
+        expiry: u64,
+        events_addr: address
+    ) acquires AppliedBatches, Settlers {
+        assert!(timestamp::now_seconds() <= expiry, errors::e_batch_expired());
+        record_batch(settler, admin_addr, batch_id, oracle_ts);
+
+        let n = deltas.length();
+        let i = 0;
+
+        while (i < n) {
+            let d = deltas[i];
+
+            // Price bound check over the range of the netted fills
+            assert!(
+                d.min_fill_px <= d.max_fill_px && d.min_fill_px >= min_px && d.max_fill_px <= max_px,
+                errors::e_price_out_of_bounds()
+            );
+
+            pos::ensure(d.owner, d.market_id);
+
+            // Offsetting fills still add their notionals, as they do fill by fill
+            if (d.size > 0 || d.entry_notional > 0) {
+                apply_position_change(d.owner, d.market_id, d.size, d.is_long, d.entry_notional, d.price_x, events_addr);
+            };
+
+            // Quote flows net to zero across the batch, so debits and credits balance
+            if (d.quote > 0) {
+                if (d.receives_quote) {
+                    vfa::credit_at(admin_addr, d.owner, d.quote);
+                } else {
+                    vfa::debit_at(admin_addr, d.owner, d.quote);
+                };
+            };
+
+            let fees = FeeCalculation {
+                taker_fee: d.taker_fee,
+                maker_fee: d.maker_fee,
+                protocol_fee: if (d.taker_fee > d.maker_fee) { d.taker_fee - d.maker_fee } else { 0 },
+                total_fee: d.taker_fee,
+            };
+            collect_fees(admin_addr, d.owner, d.owner, fees, events_addr);
+
+            events::emit_net_settlement(events::new_net_settlement_event(
+                batch_id, d.owner, d.market_id, d.size, d.is_long, d.quote, d.receives_quote,
+                d.taker_fee, d.maker_fee, d.fill_count
+            ));
+
+            i += 1;
+        }
+    }
+
+    /// Entry point for settlement signers: `apply_batch_once` with the fills passed
+    /// as parallel vectors, since entry functions cannot take structs.
+    public entry fun apply_batch_once_simple(
+        settler: &signer,
+        admin_addr: address,
+        batch_id: vector<u8>,
+        takers: vector<address>,
+        makers: vector<address>,
+        market_ids: vector<u64>,
+        sizes: vector<u128>,
+        prices_x: vector<u64>,
+        fee_bps: vector<u64>,
+        timestamps: vector<u64>,
+        taker_is_long: vector<bool>,
+        oracle_ts: u64,
+        min_px: u64,
+        max_px: u64,
+        expiry: u64,
+        events_addr: address
+    ) acquires AppliedBatches, Settlers {
+        let n = takers.length();
+        assert!(
+            makers.length() == n && market_ids.length() == n && sizes.length() == n
+                && prices_x.length() == n && fee_bps.length() == n && timestamps.length() == n
+                && taker_is_long.length() == n,
+            errors::e_batch_length_mismatch()
+        );
+
+        let fills = vector::empty<BatchFill>();
+        let i = 0;
+        while (i < n) {
+            fills.push_back(new_sided_batch_fill(
+                takers[i], makers[i], market_ids[i], sizes[i], prices_x[i], fee_bps[i], timestamps[i], taker_is_long[i]
+            ));
+            i += 1;
+        };
+
+        let batch = new_settlement_batch(fills, oracle_ts, min_px, max_px, expiry);
+        apply_batch_once(settler, admin_addr, batch_id, batch, events_addr);
+    }
+
+    /// Entry point for settlement signers: `apply_net_batch_once` with the deltas
+    /// passed as parallel vectors.
+    public entry fun apply_net_batch_once_simple(
+        settler: &signer,
+        admin_addr: address,
+        batch_id: vector<u8>,
+        owners: vector<address>,
+        market_ids: vector<u64>,
+        sizes: vector<u128>,
+        is_long: vector<bool>,
+        prices_x: vector<u64>,
+        min_fill_prices_x: vector<u64>,
+        max_fill_prices_x: vector<u64>,
+        entry_notionals: vector<u128>,
+        quotes: vector<u128>,
+        receives_quote: vector<bool>,
+        taker_fees: vector<u64>,
+        maker_fees: vector<u64>,
+        fill_counts: vector<u64>,
+        oracle_ts: u64,
+        min_px: u64,
+        max_px: u64,
+        expiry: u64,
+        events_addr: address
+    ) acquires AppliedBatches, Settlers {
+        let n = owners.length();
+        assert!(
+            market_ids.length() == n && sizes.length() == n && is_long.length() == n
+                && prices_x.length() == n && min_fill_prices_x.length() == n && max_fill_prices_x.length() == n
+                && entry_notionals.length() == n && quotes.length() == n && receives_quote.length() == n
+                && taker_fees.length() == n && maker_fees.length() == n && fill_counts.length() == n,
+            errors::e_batch_length_mismatch()
+        );
+
+        let deltas = vector::empty<NetDelta>();
+        let i = 0;
+        while (i < n) {
+            deltas.push_back(new_net_delta(
+                owners[i], market_ids[i], sizes[i], is_long[i], prices_x[i], min_fill_prices_x[i],
+                max_fill_prices_x[i], entry_notionals[i], quotes[i], receives_quote[i], taker_fees[i],
+                maker_fees[i], fill_counts[i]
+            ));
+            i += 1;
+        };
+
+        apply_net_batch_once(settler, admin_addr, batch_id, deltas, oracle_ts, min_px, max_px, expiry, events_addr);
+    }
+
+    /// Charge a liquidation penalty at most once: `amount` of the liquidated account's
+    /// vault balance moves to `insurance_addr`. `liquidation_id` is the matching engine's
+    /// liquidation UUID and is recorded with the batch ids, so a resubmission aborts.
+    public entry fun charge_penalty_once(
+        settler: &signer,
+        admin_addr: address,
+        liquidation_id: vector<u8>,
+        owner: address,
+        insurance_addr: address,
+        market_id: u64,
+        amount: u128,
+        timestamp: u64
+    ) acquires AppliedBatches, Settlers {
+        record_batch(settler, admin_addr, liquidation_id, timestamp);
+        vfa::transfer_at(admin_addr, owner, insurance_addr, amount);
+
+        events::emit_liquidation_penalty(events::new_liquidation_penalty_event(
+            liquidation_id, owner, insurance_addr, market_id, amount
+        ));
+    }
+
+    fun record_batch(settler: &signer, admin_addr: address, batch_id: vector<u8>, oracle_ts: u64) acquires AppliedBatches, Settlers {
+        let is_admin = signer::address_of(settler) == admin_addr;
+        assert!(is_admin || is_settler(admin_addr, signer::address_of(settler)), errors::e_unauthorized());
+
+        if (!exists<AppliedBatches>(admin_addr)) {
+            // Only the admin can create the registry under its own account; `set_settler`
+            // creates it before any other settler is allowed
+            assert!(is_admin, errors::e_not_initialized());
+            move_to(settler, AppliedBatches { ids: table::new<vector<u8>, u64>() });
+        };
+
+        let applied = borrow_global_mut<AppliedBatches>(admin_addr);
+        assert!(!applied.ids.contains(batch_id), errors::e_batch_already_applied());
+        applied.ids.add(batch_id, oracle_ts);
+    }
+
+    /// Allow or revoke `who` submitting settlement batches against the admin's ledger.
+    /// Also creates the admin's applied batch registry, which settlers cannot create.
+    public entry fun set_settler(admin: &signer, who: address, enabled: bool) acquires Settlers {
+        assert!(gov::is_admin(admin), errors::e_unauthorized());
+        let admin_addr = signer::address_of(admin);
+        if (!exists<Settlers>(admin_addr)) {
+            move_to(admin, Settlers { members: vector::empty<address>() });
+        };
+        if (!exists<AppliedBatches>(admin_addr)) {
+            move_to(admin, AppliedBatches { ids: table::new<vector<u8>, u64>() });
+        };
+
+        let settlers = borrow_global_mut<Settlers>(admin_addr);
+        let (found, i) = settlers.members.index_of(&who);
+        if (enabled && !found) {
+            settlers.members.push_back(who);
+        } else if (!enabled && found) {
+            settlers.members.swap_remove(i);
+        };
+    }
+
+    #[view]
+    public fun is_settler(admin_addr: address, who: address): bool acquires Settlers {
+        exists<Settlers>(admin_addr) && borrow_global<Settlers>(admin_addr).members.contains(&who)
+    }
+
+    #[view]
+    public fun is_batch_applied(admin_addr: address, batch_id: vector<u8>): bool acquires AppliedBatches {
+        exists<AppliedBatches>(admin_addr)
+            && borrow_global<AppliedBatches>(admin_addr).ids.contains(batch_id)
+    }
+
     public entry fun apply_batch_simple(
         admin: &signer,
         taker: address,
@@ -119,23 +423,19 @@ module hyperperp::perp_engine {
 
     /// Apply a single fill to update a position
     fun apply_fill(owner: address, market_id: u64, size_delta: u128, px: u64, is_long: bool, events_addr: address) {
-        // Get current position
-        let position = pos::get_position(owner, market_id);
-        
         // Calculate notional value
         let notional = (size_delta * (px as u128)) / (constants::px_scale() as u128);
-        
-        // Update position size
-        if (is_long) {
-            pos::add_size(&mut position, size_delta, true);
-        } else {
-            pos::add_size(&mut position, size_delta, false);
-        };
-        
-        // Update entry notional
-        let current_entry = pos::get_entry_notional(&position);
-        pos::update_entry_notional(&mut position, current_entry + notional);
-        
+        apply_position_change(owner, market_id, size_delta, is_long, notional, px, events_addr);
+    }
+
+    /// Add `size_delta` on the `is_long` side and `notional` to the entry notional of a position
+    fun apply_position_change(
+        owner: address, market_id: u64, size_delta: u128, is_long: bool, notional: u128, px: u64, events_addr: address
+    ) {
+        // Get current position
+        let position = pos::get_position(owner, market_id);
+        update_position(&mut position, size_delta, is_long, notional);
+
         // Put position back
         pos::put_position(owner, market_id, position);
         
@@ -143,6 +443,41 @@ module hyperperp::perp_engine {
         events::emit_position_update(events_addr, events::new_position_update_event(owner, market_id, size_delta, is_long, px));
     }
 
+    fun update_position(position: &mut pos::Position, size_delta: u128, is_long: bool, notional: u128) {
+        // Update position size
+        pos::add_size(position, size_delta, is_long);
+
+        // Update entry notional
+        let current_entry = pos::get_entry_notional(position);
+        pos::update_entry_notional(position, current_entry + notional);
+    }
+
+    /// Position of `owner` in `market_id` after its legs of `fills`, updated one fill
+    /// at a time as `apply_batch_at` does
+    public fun position_after_fills(owner: address, market_id: u64, fills: &vector<BatchFill>): pos::Position {
+        let position = pos::get_position(owner, market_id);
+        let i = 0;
+        while (i < fills.length()) {
+            let f = fills[i];
+            if (f.market_id == market_id) {
+                let notional = (f.size * (f.price_x as u128)) / (constants::px_scale() as u128);
+                if (f.taker == owner) update_position(&mut position, f.size, f.taker_is_long, notional);
+                if (f.maker == owner) update_position(&mut position, f.size, !f.taker_is_long, notional);
+            };
+            i += 1;
+        };
+        position
+    }
+
+    /// Position of the delta's owner after the delta, updated as `apply_net_batch_once` does
+    public fun position_after_delta(d: &NetDelta): pos::Position {
+        let position = pos::get_position(d.owner, d.market_id);
+        if (d.size > 0 || d.entry_notional > 0) {
+            update_position(&mut position, d.size, d.is_long, d.entry_notional);
+        };
+        position
+    }
+
     /// Calculate fees for a fill
     fun calculate_fees(size: u128, price_x: u64, fee_bps: u64): FeeCalculation {
         let notional = (size * (price_x as u128)) / (constants::px_scale() as u128);
@@ -162,7 +497,7 @@ module hyperperp::perp_engine {
     }
 
     /// Collect fees and transfer to vault
-    fun collect_fees(admin: &signer, taker: address, maker: address, fees: FeeCalculation, events_addr: address) {
+    fun collect_fees(admin_addr: address, taker: address, maker: address, fees: FeeCalculation, events_addr: address) {
         // In a real implementation, this would:
         // 1. Deduct fees from user accounts
         // 2. Transfer fees to protocol vault
@@ -170,7 +505,7 @@ module hyperperp::perp_engine {
         
         // For MVP, we'll just emit events
         // TODO: Implement actual fee collection with proper account management
-        let _ = admin;
+        let _ = admin_addr;
         let _ = taker;
         let _ = maker;
         let _ = fees;
@@ -193,8 +528,10 @@ module hyperperp::perp_engine {
         }
     }
 
-    /// Apply funding to all positions in a market
-    public fun apply_funding(admin: &signer, market_id: u64, funding_rate: u64, events_addr: address) {
+    /// Apply funding to all positions in a market; `longs_pay` is the direction of the payment
+    public fun apply_funding(admin: &signer, market_id: u64, funding_rate: u64, longs_pay: bool, events_addr: address) {
+        assert!(gov::is_admin(admin), errors::e_unauthorized());
+
         // In a real implementation, this would:
         // 1. Iterate through all positions in the market
         // 2. Calculate funding payment based on position size and funding rate
@@ -202,9 +539,19 @@ module hyperperp::perp_engine {
         // 4. Transfer funding payments between users
         
         // For MVP, we'll just emit a funding event
-        events::emit_funding(events_addr, events::new_funding_event(market_id, funding_rate));
-        
-        let _ = admin;
+        events::emit_funding(events_addr, events::new_funding_event(market_id, funding_rate, longs_pay));
+    }
+
+    /// Entry point for the off-chain funding service.
+    /// `funding_rate` is the absolute rate scaled by `constants::rate_scale()`; `longs_pay` carries its sign.
+    public entry fun apply_funding_simple(
+        admin: &signer,
+        market_id: u64,
+        funding_rate: u64,
+        longs_pay: bool,
+        events_addr: address
+    ) {
+        apply_funding(admin, market_id, funding_rate, longs_pay, events_addr);
     }
 
     /// Close a position completely
@@ -157,6 +157,16 @@ module hyperperp::positions {
         // Explicitly let position go out of scope; no need to call drop in Move
     }
     
+    /// (size, is_long, entry_notional, funding_acc, last_updated) of a position; all zero when there is none
+    #[view]
+    public fun position_of(owner: address, market_id: u64): (u128, bool, u128, u128, u64) acquires PositionRegistry {
+        if (!exists<PositionRegistry>(owner)) return (0, true, 0, 0, 0);
+        let registry = borrow_global<PositionRegistry>(owner);
+        if (!registry.positions.contains(market_id)) return (0, true, 0, 0, 0);
+        let p = registry.positions.borrow(market_id);
+        (p.size, p.is_long, p.entry_notional, p.funding_acc, p.last_updated)
+    }
+
     // Public getter functions
     public fun get_size(pos: &Position): u128 { pos.size }
 
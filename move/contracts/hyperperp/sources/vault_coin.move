@@ -5,6 +5,8 @@ module hyperperp::vault_coin {
     use hyperperp::account;
     use hyperperp::events;
     use aptos_framework::coin;
+
+    friend hyperperp::perp_engine;
     
     struct UsdcLedger has key { 
         balances: table::Table<address, u128> 
@@ -25,7 +27,12 @@ module hyperperp::vault_coin {
     }
 
     public fun credit(admin: &signer, user: address, delta: u128) acquires UsdcLedger {
-        let l = borrow_global_mut<UsdcLedger>(signer::address_of(admin));
+        credit_at(signer::address_of(admin), user, delta);
+    }
+
+    /// Credit the ledger held at `admin_addr`; for settlement by a whitelisted settler
+    public(friend) fun credit_at(admin_addr: address, user: address, delta: u128) acquires UsdcLedger {
+        let l = borrow_global_mut<UsdcLedger>(admin_addr);
         let cur = if (l.balances.contains::<address, u128>(user)) { 
             *l.balances.borrow_mut::<address, u128>(user) 
         } else { 
@@ -39,7 +46,11 @@ module hyperperp::vault_coin {
     }
 
     public fun debit(admin: &signer, user: address, delta: u128) acquires UsdcLedger {
-        let l = borrow_global_mut<UsdcLedger>(signer::address_of(admin));
+        debit_at(signer::address_of(admin), user, delta);
+    }
+
+    public(friend) fun debit_at(admin_addr: address, user: address, delta: u128) acquires UsdcLedger {
+        let l = borrow_global_mut<UsdcLedger>(admin_addr);
         let cur = if (l.balances.contains::<address, u128>(user)) { 
             *l.balances.borrow_mut::<address, u128>(user) 
         } else { 
@@ -54,8 +65,12 @@ module hyperperp::vault_coin {
     }
 
     public fun transfer_internal(admin: &signer, from: address, to: address, amount: u128) acquires UsdcLedger {
-        debit(admin, from, amount);
-        credit(admin, to, amount);
+        transfer_at(signer::address_of(admin), from, to, amount);
+    }
+
+    public(friend) fun transfer_at(admin_addr: address, from: address, to: address, amount: u128) acquires UsdcLedger {
+        debit_at(admin_addr, from, amount);
+        credit_at(admin_addr, to, amount);
     }
 
     public entry fun deposit<CoinType>(user: &signer, admin_addr: address, amount: u128) acquires UsdcLedger {
@@ -287,9 +287,12 @@ module hyperperp::liquidation_tests {
 /// Tests for perp engine (batch processing)
 module hyperperp::engine_tests {
     use std::signer;
+    use aptos_framework::timestamp;
+    use hyperperp::gov;
     use hyperperp::perp_engine as engine;
     use hyperperp::events;
     use hyperperp::positions as pos;
+    use hyperperp::vault_coin as vfa;
 
     #[test(admin = @admin)]  
     public fun test_batch_construction(admin: &signer) {
@@ -316,16 +319,19 @@ module hyperperp::engine_tests {
         engine::apply_batch(admin, batch, admin_addr);
     }
 
-    #[test(admin = @admin)]
-    #[expected_failure] // Should fail on expired batch
-    public fun test_expired_batch(admin: &signer) {
+    #[test(aptos_framework = @aptos_framework, admin = @admin)]
+    #[expected_failure(abort_code = 13, location = hyperperp::perp_engine)] // E_BATCH_EXPIRED
+    public fun test_expired_batch(aptos_framework: &signer, admin: &signer) {
+        timestamp::set_time_has_started_for_testing(aptos_framework);
+        timestamp::update_global_time_for_test_secs(1704067500);
         events::init_events(admin);
         let admin_addr = signer::address_of(admin);
         
+        // The oracle timestamp is before expiry, but the block time is past it
         let fill = engine::new_batch_fill(@user1, @user2, 1, 10, 30000, 10, 1704067200);
         let batch = engine::new_settlement_batch(
             vector<engine::BatchFill>[fill],
-            1704067500, // future oracle timestamp
+            1704067100,
             29000,
             31000, 
             1704067200 // past expiry
@@ -357,4 +363,93 @@ module hyperperp::engine_tests {
         
         engine::apply_batch(admin, batch, admin_addr);
     }
+
+    /// Buyer pays the seller the truncated quote of 2 @ 100.9, 3 @ 110.35 and 1 @ 104.7:
+    /// 201, 331 and 104. 0xa nets flat, 0xb long 1 and 0xc short 1.
+    fun mixed_fills(): vector<engine::BatchFill> {
+        vector<engine::BatchFill>[
+            engine::new_sided_batch_fill(@0xa, @0xb, 1, 2, 10_090_000_000, 10, 1, true),
+            engine::new_sided_batch_fill(@0xa, @0xb, 1, 3, 11_035_000_000, 10, 1, false),
+            engine::new_sided_batch_fill(@0xc, @0xa, 1, 1, 10_470_000_000, 10, 1, false),
+        ]
+    }
+
+    /// `mixed_fills` as the matching engine nets them
+    fun mixed_deltas(): vector<engine::NetDelta> {
+        vector<engine::NetDelta>[
+            engine::new_net_delta(@0xa, 1, 0, true, 10_216_666_667, 10_090_000_000, 11_035_000_000, 636, 26, true, 0, 0, 3),
+            engine::new_net_delta(@0xb, 1, 1, true, 11_035_000_000, 10_090_000_000, 11_035_000_000, 532, 130, false, 0, 0, 2),
+            engine::new_net_delta(@0xc, 1, 1, false, 10_470_000_000, 10_470_000_000, 10_470_000_000, 104, 104, true, 0, 0, 1),
+        ]
+    }
+
+    fun fund(admin: &signer) {
+        vfa::init_ledger(admin);
+        vfa::credit(admin, @0xa, 1_000_000);
+        vfa::credit(admin, @0xb, 1_000_000);
+        vfa::credit(admin, @0xc, 1_000_000);
+    }
+
+    #[test(aptos_framework = @aptos_framework, admin = @admin, net_admin = @0xd)]
+    public fun test_net_and_gross_settlement_match(aptos_framework: &signer, admin: &signer, net_admin: &signer) {
+        timestamp::set_time_has_started_for_testing(aptos_framework);
+        events::init_events(admin);
+        let admin_addr = signer::address_of(admin);
+        let net_addr = signer::address_of(net_admin);
+        // Separate ledgers, so each path starts from the same balances
+        fund(admin);
+        fund(net_admin);
+
+        let fills = mixed_fills();
+        let deltas = mixed_deltas();
+        let batch = engine::new_settlement_batch(fills, 1, 10_000_000_000, 12_000_000_000, 10);
+        engine::apply_batch_once(admin, admin_addr, b"gross", batch, admin_addr);
+        engine::apply_net_batch_once(net_admin, net_addr, b"net", deltas, 1, 10_000_000_000, 12_000_000_000, 10, admin_addr);
+
+        let users = vector<address>[@0xa, @0xb, @0xc];
+        let i = 0;
+        while (i < users.length()) {
+            let user = users[i];
+            assert!(vfa::balance_of(admin_addr, user) == vfa::balance_of(net_addr, user), 1);
+
+            let gross = engine::position_after_fills(user, 1, &fills);
+            let net = engine::position_after_delta(&deltas[i]);
+            assert!(pos::get_size(&gross) == pos::get_size(&net), 2);
+            assert!(pos::get_size(&gross) == 0 || pos::get_is_long(&gross) == pos::get_is_long(&net), 3);
+            assert!(pos::get_entry_notional(&gross) == pos::get_entry_notional(&net), 4);
+            i += 1;
+        };
+        assert!(vfa::balance_of(net_addr, @0xa) == 1_000_026, 5);
+        assert!(vfa::balance_of(net_addr, @0xb) == 999_870, 6);
+    }
+
+    #[test(aptos_framework = @aptos_framework, admin = @admin, settler = @0xe)]
+    public fun test_non_admin_settler_applies_batches(aptos_framework: &signer, admin: &signer, settler: &signer) {
+        timestamp::set_time_has_started_for_testing(aptos_framework);
+        events::init_events(admin);
+        let admin_addr = signer::address_of(admin);
+        gov::init_admins(admin, vector<address>[admin_addr]);
+        fund(admin);
+
+        // The admin never settles itself, so the registry comes from set_settler
+        engine::set_settler(admin, signer::address_of(settler), true);
+        let batch = engine::new_settlement_batch(mixed_fills(), 1, 10_000_000_000, 12_000_000_000, 10);
+        engine::apply_batch_once(settler, admin_addr, b"pool", batch, admin_addr);
+        assert!(engine::is_batch_applied(admin_addr, b"pool"), 1);
+
+        engine::apply_net_batch_once(settler, admin_addr, b"pool-net", mixed_deltas(), 1, 10_000_000_000, 12_000_000_000, 10, admin_addr);
+        assert!(engine::is_batch_applied(admin_addr, b"pool-net"), 2);
+    }
+
+    #[test(aptos_framework = @aptos_framework, admin = @admin)]
+    #[expected_failure(abort_code = 11, location = hyperperp::perp_engine)] // E_PRICE_OUT_OF_BOUNDS
+    public fun test_net_price_bounds(aptos_framework: &signer, admin: &signer) {
+        timestamp::set_time_has_started_for_testing(aptos_framework);
+        events::init_events(admin);
+        let admin_addr = signer::address_of(admin);
+        fund(admin);
+
+        // 0xb's fills reach 110.35, above the 110 bound
+        engine::apply_net_batch_once(admin, admin_addr, b"net", mixed_deltas(), 1, 10_000_000_000, 11_000_000_000, 10, admin_addr);
+    }
 }
@@ -0,0 +1,99 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::{error, info, warn};
+
+use crate::{chain::ChainPosition, margin::PositionRisk, SharedState};
+
+#[derive(Debug, Serialize)]
+pub struct AccountSummaryResponse {
+    pub user_address: String,
+    /// Vault collateral as tracked by the collateral ledger
+    pub collateral: Decimal,
+    /// Margin held for resting orders
+    pub reserved_margin: Decimal,
+    /// Initial margin held by open positions
+    pub position_margin: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub realized_pnl: Decimal,
+    /// Collateral plus unrealized PnL across all positions
+    pub equity: Decimal,
+    pub available_margin: Decimal,
+    /// Margin ratio of the cross account
+    pub margin_ratio: Option<Decimal>,
+    pub positions: Vec<PositionRisk>,
+    /// Positions as recorded by the contract; absent when the chain could not be queried
+    pub chain_positions: Option<Vec<ChainPosition>>,
+}
+
+/// 查询账户概览：抵押品、挂单占用、持仓盈亏、保证金率及强平价
+pub async fn get_account_summary(
+    State(state): State<SharedState>,
+    Path(user_address): Path<String>,
+) -> Result<Json<AccountSummaryResponse>, StatusCode> {
+    info!("Querying account summary for user: {}", user_address);
+
+    // 验证用户地址格式（简单验证）
+    if user_address.is_empty() || user_address.len() < 10 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let collateral = state.collateral_ledger.balance(&user_address).await
+        .map_err(|e| {
+            error!("Failed to get collateral for {}: {}", user_address, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mark_prices: HashMap<u64, Decimal> = state.price_service
+        .get_all_prices()
+        .await
+        .into_iter()
+        .map(|p| (p.market_id, p.mark_price))
+        .collect();
+
+    let margin_engine = state.margin_engine.read().await;
+    let positions = margin_engine.position_risks(&user_address, collateral, &mark_prices);
+    let cross_health = margin_engine.account_health(&user_address, collateral, &mark_prices);
+    let reserved_margin = margin_engine.reserved_margin(&user_address);
+    let position_margin = margin_engine.position_margin(&user_address);
+    let realized_pnl = margin_engine
+        .positions_for_user(&user_address)
+        .into_iter()
+        .map(|p| p.realized_pnl)
+        .sum();
+    drop(margin_engine);
+
+    // 链上持仓，查询失败时不影响账户概览
+    let market_ids: Vec<u64> = state.config.markets.iter().map(|m| m.market_id).collect();
+    let chain_positions = match state.chain_client.get_positions(&user_address, &market_ids).await {
+        Ok(chain_positions) => Some(chain_positions),
+        Err(e) => {
+            warn!("Failed to query on-chain positions for {}: {}", user_address, e);
+            None
+        }
+    };
+
+    let unrealized_pnl: Decimal = positions.iter().map(|p| p.unrealized_pnl).sum();
+    let available_margin = (collateral - reserved_margin - position_margin
+        + cross_health.unrealized_pnl.min(Decimal::ZERO))
+        .max(Decimal::ZERO);
+
+    Ok(Json(AccountSummaryResponse {
+        user_address,
+        collateral,
+        reserved_margin,
+        position_margin,
+        unrealized_pnl,
+        realized_pnl,
+        equity: collateral + unrealized_pnl,
+        available_margin,
+        margin_ratio: cross_health.margin_ratio(),
+        positions,
+        chain_positions,
+    }))
+}
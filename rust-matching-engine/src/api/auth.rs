@@ -0,0 +1,128 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha3::{Digest, Sha3_256};
+use tracing::warn;
+
+use crate::{chain::normalize_address, SharedState};
+
+/// Why a user-signed request was rejected.
+#[derive(Debug, thiserror::Error)]
+pub enum SignatureError {
+    #[error("public key and signature must be hex-encoded")]
+    Malformed,
+    #[error("public key does not belong to {0}")]
+    WrongKey(String),
+    #[error("signature does not match the request")]
+    Invalid,
+}
+
+/// Admin routes require `Authorization: Bearer <server.admin_api_key>`; with no
+/// key configured they are disabled.
+pub async fn require_admin(
+    State(state): State<SharedState>,
+    req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let expected = state.config.server.admin_api_key.expose().as_bytes();
+    if expected.is_empty() {
+        warn!("Admin request to {} rejected: no admin API key configured", req.uri().path());
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let provided = req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::as_bytes)
+        .unwrap_or_default();
+
+    if !constant_time_eq(provided, expected) {
+        warn!("Unauthorized admin request to {}", req.uri().path());
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    Ok(next.run(req).await)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Check that `message` was signed by the owner of `user_address`.
+///
+/// The Ed25519 public key must derive the address, i.e. the address is
+/// `sha3_256(public_key || 0x00)`; accounts that rotated their key cannot
+/// sign this way.
+pub fn verify_user_signature(
+    user_address: &str,
+    public_key: &str,
+    signature: &str,
+    message: &[u8],
+) -> Result<(), SignatureError> {
+    let public_key: [u8; 32] = decode_hex(public_key)?;
+    let signature: [u8; 64] = decode_hex(signature)?;
+
+    let mut hasher = Sha3_256::new();
+    hasher.update(public_key);
+    hasher.update([0u8]); // Ed25519 single-key scheme
+    if normalize_address(&hex::encode(hasher.finalize())) != normalize_address(user_address) {
+        return Err(SignatureError::WrongKey(user_address.to_string()));
+    }
+
+    VerifyingKey::from_bytes(&public_key)
+        .map_err(|_| SignatureError::Malformed)?
+        .verify_strict(message, &Signature::from_bytes(&signature))
+        .map_err(|_| SignatureError::Invalid)
+}
+
+fn decode_hex<const N: usize>(value: &str) -> Result<[u8; N], SignatureError> {
+    hex::decode(value.trim().trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(SignatureError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn signed(message: &[u8]) -> (String, String, String) {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = key.verifying_key().to_bytes();
+        let address = format!("0x{}", hex::encode(Sha3_256::digest([&public_key[..], &[0u8]].concat())));
+        (address, hex::encode(public_key), hex::encode(key.sign(message).to_bytes()))
+    }
+
+    #[test]
+    fn accepts_the_owners_signature() {
+        let (address, public_key, signature) = signed(b"withdraw 10");
+        assert!(verify_user_signature(&address, &public_key, &signature, b"withdraw 10").is_ok());
+    }
+
+    #[test]
+    fn rejects_a_key_of_another_account() {
+        let (_, public_key, signature) = signed(b"withdraw 10");
+        let result = verify_user_signature("0x1234", &public_key, &signature, b"withdraw 10");
+        assert!(matches!(result, Err(SignatureError::WrongKey(_))));
+    }
+
+    #[test]
+    fn rejects_a_changed_message() {
+        let (address, public_key, signature) = signed(b"withdraw 10");
+        let result = verify_user_signature(&address, &public_key, &signature, b"withdraw 1000");
+        assert!(matches!(result, Err(SignatureError::Invalid)));
+    }
+
+    #[test]
+    fn rejects_malformed_hex() {
+        let (address, public_key, _) = signed(b"withdraw 10");
+        let result = verify_user_signature(&address, &public_key, "0xzz", b"withdraw 10");
+        assert!(matches!(result, Err(SignatureError::Malformed)));
+    }
+}
@@ -3,9 +3,10 @@ use axum::{
     http::StatusCode,
     response::Json,
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::{
+    api::error::ApiError,
     models::{DepositRequest, DepositResponse},
     SharedState,
 };
@@ -13,17 +14,17 @@ use crate::{
 pub async fn deposit_funds(
     State(state): State<SharedState>,
     Json(req): Json<DepositRequest>,
-) -> Result<Json<DepositResponse>, StatusCode> {
+) -> Result<Json<DepositResponse>, ApiError> {
     info!("Received deposit request: {} APT from {}", req.amount, req.user_address);
 
     // Validate amount
     if req.amount <= 0 {
         error!("Invalid deposit amount: {}", req.amount);
-        return Err(StatusCode::BAD_REQUEST);
+        return Err(StatusCode::BAD_REQUEST.into());
     }
 
     // Call Aptos contract to deposit funds
-    match state.aptos_client.deposit_funds(
+    match state.chain_client.deposit_funds(
         &req.user_address,
         req.amount,
     ).await {
@@ -31,13 +32,24 @@ pub async fn deposit_funds(
             info!("Deposit successful for user {}: tx {}", req.user_address, tx_hash);
             
             // Wait for confirmation
-            if !state.aptos_client.wait_for_transaction_confirmation(&tx_hash, 3).await
+            if !state.chain_client.wait_for_transaction_confirmation(&tx_hash, 3).await
                 .map_err(|e| {
                     error!("Failed to wait for deposit confirmation: {}", e);
                     StatusCode::INTERNAL_SERVER_ERROR
                 })? {
-                error!("Deposit transaction not confirmed for user {}", req.user_address);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                // 解析失败交易的VM状态，返回具体原因
+                let failure = state.chain_client.check_transaction_status(&tx_hash).await
+                    .ok()
+                    .and_then(|status| status.error(&state.config.aptos.contract_address));
+                error!("Deposit transaction not confirmed for user {}: {:?}", req.user_address, failure);
+                return Err(match failure {
+                    Some(e) => e.into(),
+                    None => StatusCode::INTERNAL_SERVER_ERROR.into(),
+                });
+            }
+
+            if let Err(e) = state.collateral_ledger.sync_user(&req.user_address).await {
+                warn!("Failed to sync collateral after deposit for {}: {}", req.user_address, e);
             }
 
             let response = DepositResponse {
@@ -50,7 +62,7 @@ pub async fn deposit_funds(
         }
         Err(e) => {
             error!("Failed to deposit funds for user {}: {}", req.user_address, e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
         }
     }
 }
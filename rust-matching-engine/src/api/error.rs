@@ -0,0 +1,55 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+
+use crate::chain::ChainError;
+
+/// Error response carrying a code and message, for handlers that report why a
+/// request failed. Converts from a bare `StatusCode`, so `?` keeps working.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into() }
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self::new(status, "error", status.canonical_reason().unwrap_or_default())
+    }
+}
+
+/// Failures caused by the request map to 400; a paused contract to 503; the
+/// rest are on the engine's side of the chain call.
+impl From<ChainError> for ApiError {
+    fn from(error: ChainError) -> Self {
+        let status = match error {
+            ChainError::AmountTooSmall
+            | ChainError::InsufficientMargin
+            | ChainError::InsufficientCollateral
+            | ChainError::InsufficientBalance
+            | ChainError::PriceOutOfBounds
+            | ChainError::UnknownMarket
+            | ChainError::InvalidLeverage
+            | ChainError::PositionNotFound
+            | ChainError::PositionNotEmpty => StatusCode::BAD_REQUEST,
+            ChainError::Paused => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        Self::new(status, error.code(), error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.code, "message": self.message }))).into_response()
+    }
+}
@@ -0,0 +1,48 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{models::FundingRate, SharedState};
+
+#[derive(Debug, Deserialize)]
+pub struct FundingHistoryQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FundingHistoryResponse {
+    pub market_id: u64,
+    pub funding_rates: Vec<FundingRate>,
+    pub total: usize,
+}
+
+/// 根据市场ID查询资金费率历史
+pub async fn get_funding_history(
+    State(state): State<SharedState>,
+    Path(market_id): Path<u64>,
+    Query(params): Query<FundingHistoryQuery>,
+) -> Result<Json<FundingHistoryResponse>, StatusCode> {
+    info!("Querying funding history for market: {}", market_id);
+
+    let funding_rates = state.database.get_funding_rates(
+        market_id,
+        params.limit.unwrap_or(100),
+        params.offset.unwrap_or(0),
+    ).await.map_err(|e| {
+        error!("Failed to get funding history: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let response = FundingHistoryResponse {
+        market_id,
+        total: funding_rates.len(),
+        funding_rates,
+    };
+
+    Ok(Json(response))
+}
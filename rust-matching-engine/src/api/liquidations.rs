@@ -0,0 +1,64 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{liquidation::AdlIndicator, models::InsuranceFundEntry, SharedState};
+
+#[derive(Debug, Deserialize)]
+pub struct InsuranceFundQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InsuranceFundResponse {
+    pub balance: Decimal,
+    pub entries: Vec<InsuranceFundEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdlIndicatorResponse {
+    pub user_address: String,
+    pub indicators: Vec<AdlIndicator>,
+}
+
+/// 查询保险基金余额及流水
+pub async fn get_insurance_fund(
+    State(state): State<SharedState>,
+    Query(params): Query<InsuranceFundQuery>,
+) -> Result<Json<InsuranceFundResponse>, StatusCode> {
+    info!("Querying insurance fund");
+
+    let entries = state.database.get_insurance_fund_entries(
+        params.limit.unwrap_or(100),
+        params.offset.unwrap_or(0),
+    ).await.map_err(|e| {
+        error!("Failed to get insurance fund entries: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(InsuranceFundResponse {
+        balance: state.insurance_fund.balance().await,
+        entries,
+    }))
+}
+
+/// 查询用户各持仓的自动减仓(ADL)排序指示
+pub async fn get_adl_indicators(
+    State(state): State<SharedState>,
+    Path(user_address): Path<String>,
+) -> Result<Json<AdlIndicatorResponse>, StatusCode> {
+    info!("Querying ADL indicators for user: {}", user_address);
+
+    let indicators = state.liquidation_engine.adl_indicators(&user_address).await;
+
+    Ok(Json(AdlIndicatorResponse {
+        user_address,
+        indicators,
+    }))
+}
@@ -0,0 +1,72 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    api::{auth::verify_user_signature, error::ApiError},
+    models::{MarginSetting, UpdateMarginSettingRequest},
+    SharedState,
+};
+
+/// 查询用户在某市场的保证金模式及杠杆
+pub async fn get_margin_setting(
+    State(state): State<SharedState>,
+    Path((user_address, market_id)): Path<(String, u64)>,
+) -> Result<Json<MarginSetting>, StatusCode> {
+    info!("Querying margin setting for user {} in market {}", user_address, market_id);
+
+    let setting = state.margin_engine.read().await
+        .margin_setting(&user_address, market_id)
+        .map_err(|e| {
+            warn!("Failed to get margin setting: {}", e);
+            StatusCode::NOT_FOUND
+        })?;
+
+    Ok(Json(setting))
+}
+
+/// 修改保证金模式（全仓/逐仓）及杠杆：验证用户签名，有持仓或挂单时拒绝
+pub async fn update_margin_setting(
+    State(state): State<SharedState>,
+    Json(req): Json<UpdateMarginSettingRequest>,
+) -> Result<Json<MarginSetting>, ApiError> {
+    info!("Updating margin setting for user {} in market {}: {:?} {:?}",
+        req.user_address, req.market_id, req.margin_mode, req.leverage);
+
+    // 签名证明请求来自地址所有者；过期的签名不再接受
+    if req.expires_at < chrono::Utc::now().timestamp() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "signature_expired", "margin setting signature has expired"));
+    }
+    let message = req.signing_message(&state.config.aptos.contract_address);
+    if let Err(e) = verify_user_signature(&req.user_address, &req.public_key, &req.signature, message.as_bytes()) {
+        warn!("Margin setting change rejected for {}: {}", req.user_address, e);
+        return Err(ApiError::new(StatusCode::UNAUTHORIZED, "invalid_signature", e.to_string()));
+    }
+
+    // Hold the write lock so no order can open exposure between the check and the update
+    let mut margin_engine = state.margin_engine.write().await;
+
+    if margin_engine.has_open_exposure(&req.user_address, req.market_id) {
+        warn!("Rejecting margin setting change for {} in market {}: open position or orders",
+            req.user_address, req.market_id);
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    let setting = margin_engine
+        .prepare_margin_setting(&req.user_address, req.market_id, req.margin_mode, req.leverage)
+        .map_err(|e| {
+            warn!("Invalid margin setting: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    state.database.upsert_margin_setting(&setting).await.map_err(|e| {
+        error!("Failed to save margin setting: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    margin_engine.apply_margin_setting(setting.clone());
+
+    Ok(Json(setting))
+}
@@ -3,10 +3,15 @@ use axum::{
     http::StatusCode,
     response::Json,
 };
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-use crate::SharedState;
+use crate::{
+    chain::{FeeParams, MarketParams, OraclePrice},
+    SharedState,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarketInfo {
@@ -14,6 +19,14 @@ pub struct MarketInfo {
     pub symbol: String,
     pub base_token: String,
     pub quote_token: String,
+    /// Total long (= short) size currently open
+    pub open_interest: Decimal,
+    pub max_open_interest: Option<Decimal>,
+    /// Parameters registered in `market_registry`
+    pub chain_params: Option<MarketParams>,
+    /// Last price pushed to the on-chain oracle
+    pub oracle_price: Option<OraclePrice>,
+    pub fees: Option<FeeParams>,
 }
 
 // 硬编码的市场数据
@@ -24,32 +37,80 @@ fn get_markets() -> Vec<MarketInfo> {
             symbol: "BTC/USDC".to_string(),
             base_token: "BTC".to_string(),
             quote_token: "USDC".to_string(),
+            open_interest: Decimal::ZERO,
+            max_open_interest: None,
+            chain_params: None,
+            oracle_price: None,
+            fees: None,
         },
         MarketInfo {
             market_id: 2,
             symbol: "ETH/USDC".to_string(),
             base_token: "ETH".to_string(),
             quote_token: "USDC".to_string(),
+            open_interest: Decimal::ZERO,
+            max_open_interest: None,
+            chain_params: None,
+            oracle_price: None,
+            fees: None,
         },
         MarketInfo {
             market_id: 3,
             symbol: "SOL/USDC".to_string(),
             base_token: "SOL".to_string(),
             quote_token: "USDC".to_string(),
+            open_interest: Decimal::ZERO,
+            max_open_interest: None,
+            chain_params: None,
+            oracle_price: None,
+            fees: None,
         },
     ]
 }
 
+/// 填充当前持仓量及持仓量上限
+async fn with_open_interest(state: &SharedState, mut markets: Vec<MarketInfo>) -> Vec<MarketInfo> {
+    let margin_engine = state.margin_engine.read().await;
+    for market in &mut markets {
+        market.open_interest = margin_engine.open_interest(market.market_id);
+        market.max_open_interest = margin_engine
+            .market(market.market_id)
+            .ok()
+            .and_then(|m| m.max_open_interest)
+            .and_then(Decimal::from_f64);
+    }
+    markets
+}
+
+/// 填充链上市场参数、预言机价格及手续费，查询失败的字段留空
+async fn with_chain_data(state: &SharedState, mut markets: Vec<MarketInfo>) -> Vec<MarketInfo> {
+    let fees = state.chain_client.get_fee_params().await
+        .map_err(|e| warn!("Failed to query fee params: {}", e))
+        .ok();
+    for market in &mut markets {
+        market.chain_params = state.chain_client.get_market_params(market.market_id).await
+            .map_err(|e| warn!("Failed to query params for market {}: {}", market.market_id, e))
+            .ok();
+        market.oracle_price = state.chain_client.get_oracle_price(market.market_id).await
+            .map_err(|e| warn!("Failed to query oracle price for market {}: {}", market.market_id, e))
+            .ok()
+            .flatten();
+        market.fees = fees.clone();
+    }
+    markets
+}
+
 /// 根据market_id查询市场信息
 pub async fn get_market(
-    State(_state): State<SharedState>,
+    State(state): State<SharedState>,
     Path(market_id): Path<u64>,
 ) -> Result<Json<MarketInfo>, StatusCode> {
     info!("Querying market info for market_id: {}", market_id);
 
     // 获取市场数据并查找对应的市场信息
-    let markets = get_markets();
-    let market = markets.iter().find(|m| m.market_id == market_id);
+    let markets = get_markets().into_iter().filter(|m| m.market_id == market_id).collect();
+    let markets = with_chain_data(&state, with_open_interest(&state, markets).await).await;
+    let market = markets.first();
 
     match market {
         Some(market_info) => {
@@ -65,11 +126,11 @@ pub async fn get_market(
 
 /// 获取所有市场信息
 pub async fn get_all_markets(
-    State(_state): State<SharedState>,
+    State(state): State<SharedState>,
 ) -> Result<Json<Vec<MarketInfo>>, StatusCode> {
     info!("Querying all markets");
 
-    let markets = get_markets();
+    let markets = with_chain_data(&state, with_open_interest(&state, get_markets()).await).await;
     info!("Returning {} markets", markets.len());
     
     Ok(Json(markets))
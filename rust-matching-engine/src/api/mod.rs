@@ -2,4 +2,13 @@ pub mod orders;
 pub mod health;
 pub mod markets;
 pub mod deposit;
+pub mod withdraw;
 pub mod user_queries;
+pub mod prices;
+pub mod funding;
+pub mod liquidations;
+pub mod margin;
+pub mod accounts;
+pub mod settlement;
+pub mod auth;
+pub mod error;
@@ -4,17 +4,21 @@ use axum::{
     response::Json,
 };
 use rust_decimal::Decimal;
-use rust_decimal::prelude::ToPrimitive;
+use std::collections::HashMap;
 use std::str::FromStr;
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
 use crate::{
+    api::error::ApiError,
+    chain::{ChainError, USDC_UNIT},
     models::{
         Order, OrderBook, OrderBookLevel, OrderResponse, OrderStatus, OrderType,
         SubmitOrderRequest, FreezeTransactionRequest, FreezeTransactionResponse,
-        FreezeTransactionPayload, ConfirmOrderRequest, ConfirmOrderResponse,
+        FreezeTransactionPayload, ConfirmOrderRequest, ConfirmOrderResponse, Trade, MarginMode,
+        FreezeRequest, FreezeRequestStatus,
     },
+    freeze, margin,
     SharedState,
 };
 
@@ -61,68 +65,91 @@ pub async fn submit_order(
         expires_at: req.expires_at,
     };
 
-    // ==================== 功能1: 下单时冻结资金 ====================
-    // 计算所需抵押品
-    let required_collateral = calculate_required_collateral(&order);
-    
-    // 验证用户抵押品
-    if !state.aptos_client.validate_collateral(&order.user_address, required_collateral).await
+    // ==================== 功能1: 下单时占用保证金 ====================
+    // 以链下抵押品账本校验，不再逐单链上冻结
+    let reference_price = reference_price(&state, &order).await?;
+    let mark_prices = mark_prices(&state).await;
+    let balance = state.collateral_ledger.balance(&order.user_address).await
         .map_err(|e| {
-            error!("Failed to validate collateral: {}", e);
+            error!("Failed to get collateral balance: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
-        })? {
-        warn!("Insufficient collateral for user {}: required {}", order.user_address, required_collateral);
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
-    // 冻结用户资金
-    match state.aptos_client.freeze_user_funds(
-        &order.user_address,
-        required_collateral,
-        order.market_id,
-    ).await {
-        Ok(tx_hash) => {
-            info!("Funds frozen for user {}: tx {}", order.user_address, tx_hash);
-            
-            // 等待资金冻结确认
-            if !state.aptos_client.wait_for_transaction_confirmation(&tx_hash, 3).await
-                .map_err(|e| {
-                    error!("Failed to wait for freeze confirmation: {}", e);
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })? {
-                warn!("Freeze transaction not confirmed for user {}", order.user_address);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        }
-        Err(e) => {
-            error!("Failed to freeze funds for user {}: {}", order.user_address, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
+        })?;
+    {
+        // 校验与占用在同一把写锁内完成，避免并发订单重复使用同一笔抵押品
+        let mut margin_engine = state.margin_engine.write().await;
+        // 风控限额：持仓规模/名义价值上限及市场持仓量上限
+        margin_engine.check_risk_limits(&order, reference_price)
+            .map_err(|e| {
+                warn!("Order {} rejected by risk limits: {}", order.id, e);
+                StatusCode::BAD_REQUEST
+            })?;
+        let required_margin = margin_engine.quote_order(&order, reference_price)
+            .map_err(|e| {
+                warn!("Failed to quote margin for order {}: {}", order.id, e);
+                StatusCode::BAD_REQUEST
+            })?;
+
+        // 验证用户抵押品（已占用保证金 + 本单保证金，全仓模式另需覆盖全仓浮亏）
+        margin_engine.check_collateral(&order, required_margin, &mark_prices, balance)
+            .map_err(|e| {
+                warn!("Insufficient collateral for user {}: {}", order.user_address, e);
+                StatusCode::BAD_REQUEST
+            })?;
+        margin_engine.reserve(&order, required_margin);
     }
 
     // Submit order to matching engine
-    match state.matching_engine.write().await.submit_order(order.clone()).await {
-        Ok(trades) => {
-            let response = OrderResponse { order, trades };
-            Ok(Json(response))
-        }
+    let trades = match state.matching_engine.write().await.submit_order(order.clone()).await {
+        Ok(trades) => trades,
         Err(e) => {
             error!("Failed to submit order: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            state.margin_engine.write().await.release(order.id);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
+    };
+
+    // 未挂单的剩余部分（市价单）释放占用
+    apply_order_fills(&state, &order, &trades).await;
+
+    let response = OrderResponse { order, trades };
+    Ok(Json(response))
+}
+
+/// 各市场标记价格
+async fn mark_prices(state: &SharedState) -> HashMap<u64, Decimal> {
+    state.price_service
+        .get_all_prices()
+        .await
+        .into_iter()
+        .map(|p| (p.market_id, p.mark_price))
+        .collect()
+}
+
+/// 保证金计算的参考价格：限价单用委托价，市价单用盘口可成交的最差价格
+async fn reference_price(state: &SharedState, order: &Order) -> Result<Decimal, StatusCode> {
+    if order.order_type == OrderType::Limit {
+        return order.price.ok_or(StatusCode::BAD_REQUEST);
     }
+
+    state.matching_engine.read().await
+        .get_order_book(order.market_id)
+        .and_then(|book| book.estimate_fill_price(&order.side, order.size))
+        .ok_or_else(|| {
+            warn!("No liquidity to price market order {}", order.id);
+            StatusCode::BAD_REQUEST
+        })
 }
 
-/// 计算所需抵押品
-fn calculate_required_collateral(order: &Order) -> u64 {
-    // 简化的抵押品计算逻辑
-    let notional_value = match order.price {
-        Some(price) => (order.size * price).to_u64().unwrap_or(0),
-        None => order.size.to_u64().unwrap_or(0),
-    };
-    
-    // 要求10%的抵押品，最少1000个最小单位
-    (notional_value).max(1)
+/// 撮合后更新持仓和保证金占用，订单未挂单的剩余部分释放占用
+async fn apply_order_fills(state: &SharedState, order: &Order, trades: &[Trade]) {
+    let mut margin_engine = state.margin_engine.write().await;
+    margin_engine.apply_trades(trades);
+
+    let filled_size: Decimal = trades.iter().map(|t| t.size).sum();
+    let resting = order.order_type == OrderType::Limit && filled_size < order.size;
+    if !resting {
+        margin_engine.release(order.id);
+    }
 }
 
 pub async fn cancel_order(
@@ -132,46 +159,12 @@ pub async fn cancel_order(
     let order_uuid = Uuid::from_str(&order_id)
         .map_err(|_| StatusCode::BAD_REQUEST)?;
 
-    // 获取订单信息用于计算解冻金额
-    let order_info = state.database.get_order(order_uuid).await
-        .map_err(|e| {
-            error!("Failed to get order info: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
     // 取消订单
     match state.matching_engine.write().await.cancel_order(order_uuid).await {
         Ok(true) => {
-            // ==================== 功能3: 撤单时解冻资金 ====================
-            // 计算需要解冻的资金
-            let unfrozen_amount = calculate_unfrozen_amount(&order_info);
-            
-            if unfrozen_amount > 0 {
-                // 解冻用户资金
-                match state.aptos_client.unfreeze_user_funds(
-                    &order_info.user_address,
-                    unfrozen_amount,
-                ).await {
-                    Ok(tx_hash) => {
-                        info!("Funds unfrozen for user {}: tx {}", order_info.user_address, tx_hash);
-                        
-                        // 等待资金解冻确认
-                        if !state.aptos_client.wait_for_transaction_confirmation(&tx_hash, 3).await
-                            .map_err(|e| {
-                                error!("Failed to wait for unfreeze confirmation: {}", e);
-                                StatusCode::INTERNAL_SERVER_ERROR
-                            })? {
-                            warn!("Unfreeze transaction not confirmed for user {}", order_info.user_address);
-                            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to unfreeze funds for user {}: {}", order_info.user_address, e);
-                        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                    }
-                }
-            }
-            
+            // ==================== 功能3: 撤单时释放保证金 ====================
+            // 仅释放链下占用，资金始终留在金库中
+            state.margin_engine.write().await.release(order_uuid);
             Ok(StatusCode::OK)
         }
         Ok(false) => Err(StatusCode::NOT_FOUND),
@@ -182,22 +175,6 @@ pub async fn cancel_order(
     }
 }
 
-/// 计算解冻金额
-fn calculate_unfrozen_amount(order: &Order) -> u64 {
-    let remaining_size = order.size - order.filled_size;
-    if remaining_size == Decimal::ZERO {
-        return 0;
-    }
-
-    let notional_value = match order.price {
-        Some(price) => (remaining_size * price).to_u64().unwrap_or(0),
-        None => remaining_size.to_u64().unwrap_or(0),
-    };
-    
-    // 解冻对应的抵押品
-    (notional_value / 10).max(1000)
-}
-
 pub async fn get_order_book(
     State(state): State<SharedState>,
     Path(market_id): Path<u64>,
@@ -288,11 +265,46 @@ pub async fn request_freeze_transaction(
         expires_at: req.expires_at,
     };
 
-    // Calculate required collateral
-    let required_collateral = calculate_required_collateral(&order);
+    // Calculate required margin
+    let reference_price = reference_price(&state, &order).await?;
+    let mark_prices = mark_prices(&state).await;
+    let (required_margin, existing_requirement, margin_mode) = {
+        let margin_engine = state.margin_engine.read().await;
+        margin_engine.check_risk_limits(&order, reference_price)
+            .map_err(|e| {
+                warn!("Order {} rejected by risk limits: {}", order.id, e);
+                StatusCode::BAD_REQUEST
+            })?;
+        let required_margin = margin_engine.quote_order(&order, reference_price)
+            .map_err(|e| {
+                warn!("Failed to quote margin for order {}: {}", order.id, e);
+                StatusCode::BAD_REQUEST
+            })?;
+        let existing = margin_engine.pre_trade_requirement(&order, Decimal::ZERO, &mark_prices);
+        (required_margin, existing, margin_engine.margin_mode(&order.user_address, order.market_id))
+    };
+    let required_collateral = margin::to_collateral_units(required_margin);
+    let Some(amount) = required_collateral.checked_mul(USDC_UNIT) else {
+        warn!("Order {} needs more collateral than the vault can hold: {}", order.id, required_collateral);
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    // 全仓订单共享账户保证金：现有占用及全仓浮亏须已被抵押品覆盖；逐仓订单只需本单保证金
+    if margin_mode == MarginMode::Cross {
+        let balance = state.collateral_ledger.balance(&req.user_address).await
+            .map_err(|e| {
+                error!("Failed to get collateral balance: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        if existing_requirement > balance {
+            warn!("Cross account of user {} under-collateralized: required {}, available {}",
+                req.user_address, existing_requirement, balance);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
     
     // Validate user has sufficient balance
-    if !state.aptos_client.validate_user_balance(&req.user_address, required_collateral).await
+    if !state.chain_client.validate_user_balance(&req.user_address, required_collateral).await
         .map_err(|e| {
             error!("Failed to validate user balance: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
@@ -301,13 +313,38 @@ pub async fn request_freeze_transaction(
         return Err(StatusCode::BAD_REQUEST);
     }
 
+    // 记录订单及报价，确认订单时以此为准，并校验用户签名的冻结交易
+    let now = chrono::Utc::now();
+    let freeze_request = FreezeRequest {
+        order_id: order.id,
+        user_address: order.user_address.clone(),
+        market_id: order.market_id,
+        side: order.side.clone(),
+        order_type: order.order_type.clone(),
+        size: order.size,
+        price,
+        order_expires_at: order.expires_at,
+        required_collateral,
+        amount,
+        status: FreezeRequestStatus::Pending,
+        tx_hash: None,
+        refund_tx_hash: None,
+        created_at: now,
+        expires_at: now + chrono::Duration::seconds(state.config.freeze.request_ttl_secs as i64),
+    };
+    state.database.insert_freeze_request(&freeze_request).await
+        .map_err(|e| {
+            error!("Failed to store freeze request for order {}: {}", order.id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
     // Create freeze transaction payload
     let freeze_payload = FreezeTransactionPayload {
         function: format!("{}::vault_coin::deposit", state.config.aptos.contract_address),
         type_arguments: vec![state.config.aptos.usdc_token_type.clone()],
         arguments: vec![
             state.config.aptos.admin_address.to_string(),
-            (required_collateral*1000000).to_string(),
+            freeze_request.amount.to_string(),
         ],
         gas_limit: 100_000,
         gas_unit_price: 100,
@@ -317,6 +354,7 @@ pub async fn request_freeze_transaction(
         order_id: order.id,
         freeze_transaction_payload: freeze_payload,
         required_collateral,
+        expires_at: freeze_request.expires_at,
         message: "Please sign the freeze transaction with your wallet to confirm the order".to_string(),
     };
 
@@ -327,52 +365,120 @@ pub async fn request_freeze_transaction(
 }
 
 /// Step 2: Confirm order with signed transaction hash
+///
+/// 订单内容取自第一步保存的冻结请求，不信任客户端重新提交的字段。
+/// 冻结交易须与报价一致（发送者、合约函数、币种、金额），且每个交易哈希只能确认一个订单；
+/// 请求过期后才到账的冻结资金会排队退回，返回 410
 pub async fn confirm_order(
     State(state): State<SharedState>,
     Json(req): Json<ConfirmOrderRequest>,
-) -> Result<Json<ConfirmOrderResponse>, StatusCode> {
+) -> Result<Json<ConfirmOrderResponse>, ApiError> {
     info!("Received order confirmation: order_id={}, tx_hash={}", 
         req.order_id, req.signed_transaction_hash);
 
-    // Verify the transaction was successful
-    if !state.aptos_client.wait_for_transaction_confirmation(&req.signed_transaction_hash, 3).await
+    let freeze_request = state.database.get_freeze_request(req.order_id).await
         .map_err(|e| {
-            error!("Failed to verify transaction confirmation: {}", e);
+            error!("Failed to load freeze request for order {}: {}", req.order_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or_else(|| {
+            warn!("No freeze request for order {}", req.order_id);
+            StatusCode::NOT_FOUND
+        })?;
+
+    // Verify the transaction is the quoted freeze
+    let tx = freeze::wait_for_committed_transaction(
+        state.chain_client.as_ref(), &req.signed_transaction_hash, 3,
+    ).await
+        .map_err(|e| {
+            error!("Failed to fetch transaction {}: {}", req.signed_transaction_hash, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or_else(|| {
+            warn!("Transaction {} not committed for order {}", req.signed_transaction_hash, req.order_id);
+            StatusCode::BAD_REQUEST
+        })?;
+    if let Err(e) = freeze::verify_freeze_transaction(&tx, &freeze_request, &state.config.aptos) {
+        warn!("Rejected freeze transaction for order {}: {:#}", req.order_id, e);
+        let code = e.downcast_ref::<ChainError>().map_or("invalid_freeze_transaction", ChainError::code);
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, code, format!("{:#}", e)));
+    }
+
+    if !state.database.claim_freeze_transaction(&req.signed_transaction_hash, &freeze_request).await
+        .map_err(|e| {
+            error!("Failed to record freeze transaction: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })? {
-        warn!("Transaction not confirmed for order {}", req.order_id);
-        return Err(StatusCode::BAD_REQUEST);
+        warn!("Freeze transaction {} or order {} already confirmed",
+            req.signed_transaction_hash, req.order_id);
+        return Err(StatusCode::CONFLICT.into());
     }
 
-    // Parse price for limit orders
-    let price = match req.order_type {
-        OrderType::Limit => {
-            req.price.ok_or(StatusCode::BAD_REQUEST)?
-                .parse::<Decimal>()
-                .map_err(|_| StatusCode::BAD_REQUEST)?
-        }
-        OrderType::Market => Decimal::ZERO, // Market orders don't have price
-    };
+    if !state.database.confirm_freeze_request(req.order_id, &req.signed_transaction_hash).await
+        .map_err(|e| {
+            error!("Failed to confirm freeze request: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })? {
+        // 请求已过期，资金已进入金库，交给清理任务退回
+        state.database.mark_freeze_refund_pending(req.order_id, &req.signed_transaction_hash).await
+            .map_err(|e| {
+                error!("Failed to queue refund for order {}: {}", req.order_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        warn!("Freeze request for order {} expired at {}, deposit will be refunded",
+            req.order_id, freeze_request.expires_at);
+        return Err(StatusCode::GONE.into());
+    }
 
-    // For now, we'll create a new order and process it
     let order = Order {
-        id: req.order_id,
-        user_address: req.user_address,
-        market_id: req.market_id,
-        side: req.side,
-        order_type: req.order_type,
-        size: Decimal::from_str(&req.size).map_err(|_| StatusCode::BAD_REQUEST)?,
-        price: Some(price),
+        id: freeze_request.order_id,
+        user_address: freeze_request.user_address,
+        market_id: freeze_request.market_id,
+        side: freeze_request.side,
+        order_type: freeze_request.order_type,
+        size: freeze_request.size,
+        price: Some(freeze_request.price),
         filled_size: Decimal::ZERO,
         status: OrderStatus::Pending,
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
-        expires_at: req.expires_at,
+        expires_at: freeze_request.order_expires_at,
     };
 
+    // The deposit just landed in the vault, refresh the ledger before checking it
+    let balance = state.collateral_ledger.sync_user(&order.user_address).await
+        .map_err(|e| {
+            error!("Failed to sync collateral balance: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // Reserve margin for the order before it reaches the book
+    let reference_price = reference_price(&state, &order).await?;
+    let mark_prices = mark_prices(&state).await;
+    {
+        let mut margin_engine = state.margin_engine.write().await;
+        margin_engine.check_risk_limits(&order, reference_price)
+            .map_err(|e| {
+                warn!("Order {} rejected by risk limits: {}", order.id, e);
+                StatusCode::BAD_REQUEST
+            })?;
+        let required_margin = margin_engine.quote_order(&order, reference_price)
+            .map_err(|e| {
+                warn!("Failed to quote margin for order {}: {}", order.id, e);
+                StatusCode::BAD_REQUEST
+            })?;
+        margin_engine.check_collateral(&order, required_margin, &mark_prices, balance)
+            .map_err(|e| {
+                warn!("Insufficient collateral for user {}: {}", order.user_address, e);
+                StatusCode::BAD_REQUEST
+            })?;
+        margin_engine.reserve(&order, required_margin);
+    }
+
     // Submit order to matching engine
     match state.matching_engine.write().await.submit_order(order.clone()).await {
         Ok(trades) => {
+            apply_order_fills(&state, &order, &trades).await;
             let response = ConfirmOrderResponse {
                 order,
                 trades,
@@ -382,7 +488,8 @@ pub async fn confirm_order(
         }
         Err(e) => {
             error!("Failed to submit confirmed order: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            state.margin_engine.write().await.release(order.id);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
         }
     }
 }
@@ -0,0 +1,61 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Json,
+    },
+};
+use futures::stream::{self, Stream};
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::{pricing::MarketPrice, SharedState};
+
+/// 查询所有市场的指数价格和标记价格
+pub async fn get_all_prices(
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<MarketPrice>>, StatusCode> {
+    let prices = state.price_service.get_all_prices().await;
+    info!("Returning prices for {} markets", prices.len());
+    Ok(Json(prices))
+}
+
+/// 根据market_id查询指数价格和标记价格
+pub async fn get_price(
+    State(state): State<SharedState>,
+    Path(market_id): Path<u64>,
+) -> Result<Json<MarketPrice>, StatusCode> {
+    match state.price_service.get_price(market_id).await {
+        Some(price) => Ok(Json(price)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// 以 Server-Sent Events 推送价格更新
+pub async fn stream_prices(
+    State(state): State<SharedState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.price_service.subscribe();
+
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(price) => {
+                    let event = Event::default()
+                        .event("price")
+                        .json_data(&price)
+                        .unwrap_or_default();
+                    return Some((Ok(event), receiver));
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Price stream subscriber lagged, skipped {} updates", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
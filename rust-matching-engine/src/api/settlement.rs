@@ -0,0 +1,265 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        DeadLetteredTrade, HeldTrade, NetDelta, SettlementBatch, SettlementBatchSummary,
+        SettlementLag, SettlementStatus,
+    },
+    SharedState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SettlementBatchQuery {
+    pub status: Option<SettlementStatus>,
+    pub market_id: Option<u64>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchActionResponse {
+    pub batch_id: Uuid,
+    /// Unsettled trades the action applied to
+    pub trades: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettlementControlResponse {
+    pub paused: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettlementMetricsResponse {
+    #[serde(flatten)]
+    pub lag: SettlementLag,
+    pub oldest_unsettled_trade_age_secs: Option<i64>,
+    pub paused: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeadLetterQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequeueTradesRequest {
+    pub trade_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RequeueTradesResponse {
+    pub requeued: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReleaseTradesResponse {
+    pub released: u64,
+}
+
+/// 按状态和市场查询结算批次
+pub async fn list_settlement_batches(
+    State(state): State<SharedState>,
+    Query(params): Query<SettlementBatchQuery>,
+) -> Result<Json<Vec<SettlementBatchSummary>>, StatusCode> {
+    info!("Querying settlement batches: status {:?}, market {:?}", params.status, params.market_id);
+
+    let batches = state.database.get_settlement_batches(
+        params.status,
+        params.market_id,
+        params.limit.unwrap_or(100),
+        params.offset.unwrap_or(0),
+    ).await.map_err(|e| {
+        error!("Failed to get settlement batches: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(batches))
+}
+
+/// 查询单个结算批次及其成交和交易哈希
+pub async fn get_settlement_batch(
+    State(state): State<SharedState>,
+    Path(batch_id): Path<Uuid>,
+) -> Result<Json<SettlementBatch>, StatusCode> {
+    info!("Querying settlement batch {}", batch_id);
+    Ok(Json(load_batch(&state, batch_id).await?))
+}
+
+/// 立即重试失败的结算批次，跳过退避并恢复已进入死信队列的成交
+pub async fn retry_settlement_batch(
+    State(state): State<SharedState>,
+    Path(batch_id): Path<Uuid>,
+) -> Result<Json<BatchActionResponse>, StatusCode> {
+    let batch = load_failed_batch(&state, batch_id).await?;
+
+    let trades = state.settlement_service.retry_failed_batch(&batch).await.map_err(|e| {
+        error!("Failed to retry settlement batch {}: {}", batch_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(BatchActionResponse { batch_id, trades }))
+}
+
+/// 放弃失败的结算批次，未结算的成交进入死信队列
+pub async fn abandon_settlement_batch(
+    State(state): State<SharedState>,
+    Path(batch_id): Path<Uuid>,
+) -> Result<Json<BatchActionResponse>, StatusCode> {
+    let mut batch = load_failed_batch(&state, batch_id).await?;
+
+    let trades = state.settlement_service.abandon_failed_batch(&mut batch).await.map_err(|e| {
+        error!("Failed to abandon settlement batch {}: {}", batch_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(BatchActionResponse { batch_id, trades }))
+}
+
+/// 暂停结算循环，已提交的批次仍会继续跟踪确认
+pub async fn pause_settlement(
+    State(state): State<SharedState>,
+) -> Json<SettlementControlResponse> {
+    state.settlement_service.pause();
+    Json(SettlementControlResponse { paused: true })
+}
+
+/// 恢复结算循环
+pub async fn resume_settlement(
+    State(state): State<SharedState>,
+) -> Json<SettlementControlResponse> {
+    state.settlement_service.resume();
+    Json(SettlementControlResponse { paused: false })
+}
+
+/// 结算延迟指标：最早未结算成交的时长及待结算成交数
+pub async fn get_settlement_metrics(
+    State(state): State<SharedState>,
+) -> Result<Json<SettlementMetricsResponse>, StatusCode> {
+    let lag = state.database.get_settlement_lag().await.map_err(|e| {
+        error!("Failed to get settlement lag: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let oldest_unsettled_trade_age_secs = lag.oldest_unsettled_trade_at
+        .map(|at| (chrono::Utc::now() - at).num_seconds());
+
+    Ok(Json(SettlementMetricsResponse {
+        lag,
+        oldest_unsettled_trade_age_secs,
+        paused: state.settlement_service.is_paused(),
+    }))
+}
+
+async fn load_batch(state: &SharedState, batch_id: Uuid) -> Result<SettlementBatch, StatusCode> {
+    state.database.get_settlement_batch(batch_id).await
+        .map_err(|e| {
+            error!("Failed to get settlement batch {}: {}", batch_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Only failed batches can be retried or abandoned; anything else is a conflict.
+async fn load_failed_batch(state: &SharedState, batch_id: Uuid) -> Result<SettlementBatch, StatusCode> {
+    let batch = load_batch(state, batch_id).await?;
+    if !matches!(batch.status, SettlementStatus::Failed) {
+        warn!("Settlement batch {} is {:?}, not failed", batch_id, batch.status);
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(batch)
+}
+
+/// 查询重试耗尽、进入死信队列的成交
+pub async fn get_dead_lettered_trades(
+    State(state): State<SharedState>,
+    Query(params): Query<DeadLetterQuery>,
+) -> Result<Json<Vec<DeadLetteredTrade>>, StatusCode> {
+    info!("Querying dead-lettered settlement trades");
+
+    let trades = state.database.get_dead_lettered_trades(
+        params.limit.unwrap_or(100),
+        params.offset.unwrap_or(0),
+    ).await.map_err(|e| {
+        error!("Failed to get dead-lettered trades: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(trades))
+}
+
+/// 将死信成交重新放回结算队列
+pub async fn requeue_dead_lettered_trades(
+    State(state): State<SharedState>,
+    Json(req): Json<RequeueTradesRequest>,
+) -> Result<Json<RequeueTradesResponse>, StatusCode> {
+    if req.trade_ids.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let requeued = state.database.requeue_dead_lettered_trades(&req.trade_ids).await.map_err(|e| {
+        error!("Failed to requeue dead-lettered trades: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    info!("Requeued {} of {} dead-lettered trades for settlement", requeued, req.trade_ids.len());
+    Ok(Json(RequeueTradesResponse { requeued }))
+}
+
+/// 查询价格偏离预言机价格区间、等待人工审核的成交
+pub async fn get_held_trades(
+    State(state): State<SharedState>,
+    Query(params): Query<DeadLetterQuery>,
+) -> Result<Json<Vec<HeldTrade>>, StatusCode> {
+    info!("Querying settlement trades held for review");
+
+    let trades = state.database.get_held_trades(
+        params.limit.unwrap_or(100),
+        params.offset.unwrap_or(0),
+    ).await.map_err(|e| {
+        error!("Failed to get held trades: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(trades))
+}
+
+/// 审核通过，按成交价放行被暂扣的成交
+pub async fn release_held_trades(
+    State(state): State<SharedState>,
+    Json(req): Json<RequeueTradesRequest>,
+) -> Result<Json<ReleaseTradesResponse>, StatusCode> {
+    if req.trade_ids.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let released = state.database.release_held_trades(&req.trade_ids).await.map_err(|e| {
+        error!("Failed to release held trades: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    info!("Released {} of {} held trades for settlement", released, req.trade_ids.len());
+    Ok(Json(ReleaseTradesResponse { released }))
+}
+
+/// 查询净额结算批次的各用户净变动及其对应的原始成交ID
+pub async fn get_net_deltas(
+    State(state): State<SharedState>,
+    Path(batch_id): Path<Uuid>,
+) -> Result<Json<Vec<NetDelta>>, StatusCode> {
+    info!("Querying net deltas for settlement batch {}", batch_id);
+
+    let deltas = state.database.get_net_deltas(batch_id).await.map_err(|e| {
+        error!("Failed to get net deltas: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(deltas))
+}
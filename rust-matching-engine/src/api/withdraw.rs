@@ -0,0 +1,108 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    api::{auth::verify_user_signature, error::ApiError},
+    models::{WithdrawRequest, WithdrawResponse, Withdrawal},
+    withdrawal::WithdrawalError,
+    SharedState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct UserWithdrawalsQuery {
+    pub limit: Option<i64>,
+}
+
+/// 提交提款请求：验证用户签名并检查可提取权益后排队，由后台任务提交vault_coin::withdraw_for
+pub async fn withdraw_funds(
+    State(state): State<SharedState>,
+    Json(req): Json<WithdrawRequest>,
+) -> Result<Json<WithdrawResponse>, ApiError> {
+    info!("Received withdrawal request: {} from {}", req.amount, req.user_address);
+
+    if req.user_address.is_empty() || req.user_address.len() < 10 {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    if req.amount == 0 {
+        error!("Invalid withdrawal amount: {}", req.amount);
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    // 签名证明请求来自地址所有者；过期的签名不再接受
+    if req.expires_at < chrono::Utc::now().timestamp() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "signature_expired", "withdrawal signature has expired"));
+    }
+    let message = req.signing_message(&state.config.aptos.contract_address);
+    if let Err(e) = verify_user_signature(&req.user_address, &req.public_key, &req.signature, message.as_bytes()) {
+        warn!("Withdrawal rejected for {}: {}", req.user_address, e);
+        return Err(ApiError::new(StatusCode::UNAUTHORIZED, "invalid_signature", e.to_string()));
+    }
+
+    let withdrawal = match state.withdrawal_service.request_withdrawal(req.nonce, &req.user_address, req.amount).await {
+        Ok(withdrawal) => withdrawal,
+        Err(e @ WithdrawalError::InsufficientEquity { .. }) => {
+            warn!("Withdrawal rejected for {}: {}", req.user_address, e);
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, "insufficient_withdrawable_equity", e.to_string()));
+        }
+        Err(e @ WithdrawalError::Duplicate(_)) => {
+            warn!("Withdrawal rejected for {}: {}", req.user_address, e);
+            return Err(ApiError::new(StatusCode::CONFLICT, "duplicate_nonce", e.to_string()));
+        }
+        Err(WithdrawalError::Other(e)) => {
+            error!("Failed to queue withdrawal for {}: {}", req.user_address, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+    };
+
+    let withdrawable = state.withdrawal_service.withdrawable(&req.user_address).await
+        .map_err(|e| {
+            error!("Failed to compute withdrawable equity for {}: {}", req.user_address, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(WithdrawResponse { withdrawal, withdrawable }))
+}
+
+/// 根据提款ID查询提款状态
+pub async fn get_withdrawal(
+    State(state): State<SharedState>,
+    Path(withdrawal_id): Path<Uuid>,
+) -> Result<Json<Withdrawal>, StatusCode> {
+    let withdrawal = state.database.get_withdrawal(withdrawal_id).await
+        .map_err(|e| {
+            error!("Failed to get withdrawal {}: {}", withdrawal_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(withdrawal))
+}
+
+/// 根据用户地址查询提款记录，最新的在前
+pub async fn get_user_withdrawals(
+    State(state): State<SharedState>,
+    Path(user_address): Path<String>,
+    Query(params): Query<UserWithdrawalsQuery>,
+) -> Result<Json<Vec<Withdrawal>>, StatusCode> {
+    // 验证用户地址格式（简单验证）
+    if user_address.is_empty() || user_address.len() < 10 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let withdrawals = state.database
+        .get_user_withdrawals(&user_address, params.limit.unwrap_or(50).clamp(1, 500))
+        .await
+        .map_err(|e| {
+            error!("Failed to get withdrawals for {}: {}", user_address, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Retrieved {} withdrawals for user {}", withdrawals.len(), user_address);
+    Ok(Json(withdrawals))
+}
@@ -1,50 +1,60 @@
-use anyhow::{Context, Result};
-use aptos_crypto::ed25519::{Ed25519PrivateKey, Ed25519PublicKey};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use aptos_crypto::ed25519::Ed25519Signature;
 use aptos_rust_sdk::client::builder::AptosClientBuilder;
 use hex;
 use aptos_rust_sdk::client::config::AptosNetwork;
 use aptos_rust_sdk_types::api_types::{
     account::AccountResource, address::AccountAddress, chain_id::ChainId, module_id::ModuleId, transaction::{
         EntryFunction, GenerateSigningMessage, RawTransaction, SignedTransaction, TransactionPayload,
-    }, transaction_authenticator::TransactionAuthenticator, type_tag::{StructTag, TypeTag}
+    }, transaction_authenticator::TransactionAuthenticator, type_tag::{StructTag, TypeTag}, view::ViewRequest
 };
+use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
+use sha3::{Digest, Sha3_256};
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::str::FromStr;
-use std::time::Duration;
-use tracing::{info, warn};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
 
 use crate::{
+    chain::{
+        price_x, size_units, ChainClient, ChainError, ChainPosition, CommittedTransaction, FeeParams,
+        GasEstimate, MarketParams, OraclePrice, PreparedTransaction, TransactionStatus, TRANSACTION_EXPIRATION_SECS,
+    },
     config::AptosConfig,
-    models::SettlementBatch,
+    keys::TransactionSigner,
+    models::{LiquidationPenalty, NetDelta, OrderSide, SettlementBatch},
+    signer_pool::SettlementSigner,
 };
 
+/// Fee rate passed with every settled fill
+pub const SETTLEMENT_FEE_BPS: u64 = 10; // 0.1% fee
+
+const SETTLEMENT_GAS_UNIT_PRICE: u64 = 100;
+
 #[derive(Debug)]
 pub struct AptosClient {
     client: aptos_rust_sdk::client::rest_api::AptosFullnodeClient,
-    admin_private_key: Ed25519PrivateKey,
+    /// Admin account, shared with the signer pool so every admin transaction uses one sequence counter
+    admin: Arc<SettlementSigner>,
     admin_address: AccountAddress,
+    /// 强平罚金转入的金库账户
+    insurance_address: AccountAddress,
     contract_address: AccountAddress,
     chain_id: ChainId,
     usdc_token_type: String,
+    /// View-function results by call, reused for `view_cache_ttl`
+    view_cache: Mutex<HashMap<String, (Instant, Vec<serde_json::Value>)>>,
+    view_cache_ttl: Duration,
 }
 
 impl AptosClient {
-    fn parse_private_key(key_str: &str) -> Result<Ed25519PrivateKey> {
-        // Remove the "ed25519-priv-0x" prefix if present
-        let hex_str = key_str
-            .strip_prefix("ed25519-priv-0x")
-            .unwrap_or(key_str);
-        
-        // Convert hex string to bytes
-        let bytes = hex::decode(hex_str)
-            .context("Failed to decode private key hex string")?;
-        
-        // Create Ed25519PrivateKey from bytes
-        Ed25519PrivateKey::try_from(bytes.as_slice())
-            .context("Failed to create Ed25519PrivateKey from bytes")
-    }
-    pub async fn new(config: &AptosConfig) -> Result<Self> {
+    pub async fn new(config: &AptosConfig, admin: Arc<SettlementSigner>) -> Result<Self> {
         // Create client using the new SDK
         let network = match config.chain_id {
             1 => AptosNetwork::mainnet(),
@@ -52,12 +62,12 @@ impl AptosClient {
             _ => AptosNetwork::testnet(), // Default to testnet
         };
         let client = AptosClientBuilder::new(network).build();
-        
-        // Load admin private key - parse from config format (ed25519-priv-0x...)
-        let private_key = Self::parse_private_key(&config.admin_private_key)
-            .context("Failed to parse admin private key")?;
-        
-        let admin_address = AccountAddress::from_str(&config.admin_address)?;
+
+        let admin_address = admin.address;
+        let insurance_address = match &config.insurance_address {
+            Some(address) => AccountAddress::from_str(address).context("Invalid insurance address")?,
+            None => admin_address,
+        };
         let contract_address = AccountAddress::from_str(&config.contract_address)?;
         let chain_id = match config.chain_id {
             1 => ChainId::Mainnet,
@@ -68,11 +78,14 @@ impl AptosClient {
 
         let aptos_client = Self {
             client,
-            admin_private_key: private_key,
+            admin,
             admin_address,
+            insurance_address,
             contract_address,
             chain_id,
             usdc_token_type: config.usdc_token_type.clone(),
+            view_cache: Mutex::new(HashMap::new()),
+            view_cache_ttl: Duration::from_millis(config.view_cache_ttl_ms),
         };
 
         info!("Aptos client initialized for admin: {}", aptos_client.admin_address);
@@ -80,58 +93,277 @@ impl AptosClient {
         Ok(aptos_client)
     }
 
+    fn settlement_payload(
+        &self,
+        batch: &SettlementBatch,
+        net_deltas: Option<&[NetDelta]>,
+    ) -> Result<(TransactionPayload, usize)> {
+        let admin_addr = bcs::to_bytes(&self.admin_address)?;
+        let batch_id = bcs::to_bytes(&batch.id.as_bytes().to_vec())?;
+        let events_addr = bcs::to_bytes(&self.admin_address)?;
 
-
-    fn create_settlement_batch_data(&self, batch: &SettlementBatch) -> SettlementBatchData {
-        SettlementBatchData {
-            fills: batch.trades.iter().map(|trade| {
-                BatchFillData {
-                    taker: AccountAddress::from_str(&trade.taker_address).unwrap_or(AccountAddress::ZERO),
-                    maker: AccountAddress::from_str(&trade.maker_address).unwrap_or(AccountAddress::ZERO),
-                    market_id: trade.market_id,
-                    size: self.decimal_to_u128(trade.size).unwrap_or(0),
-                    price_x: self.decimal_to_u128(trade.price * Decimal::from(100_000_000)).unwrap_or(0),
-                    fee_bps: 10, // 0.1% fee
-                    ts: trade.created_at.timestamp() as u64,
+        let (function, args) = match net_deltas {
+            Some(deltas) => {
+                let mut delta_data = NetDeltaData::default();
+                for delta in deltas {
+                    delta_data.push(delta)?;
                 }
-            }).collect(),
-            oracle_ts: batch.oracle_timestamp,
-            min_px: self.decimal_to_u128(batch.min_price * Decimal::from(100_000_000)).unwrap_or(0),
-            max_px: self.decimal_to_u128(batch.max_price * Decimal::from(100_000_000)).unwrap_or(u128::MAX),
-            expiry: batch.expiry_timestamp,
+                let mut args = vec![admin_addr, batch_id];
+                args.extend(delta_data.to_args()?);
+                args.extend([
+                    bcs::to_bytes(&batch.oracle_timestamp)?,
+                    bcs::to_bytes(&price_x(batch.min_price).context("Batch min price out of range")?)?,
+                    bcs::to_bytes(&price_x(batch.max_price).context("Batch max price out of range")?)?,
+                    bcs::to_bytes(&batch.expiry_timestamp)?,
+                    events_addr,
+                ]);
+                ("apply_net_batch_once_simple", args)
+            }
+            None => {
+                let mut args = vec![admin_addr, batch_id];
+                args.extend(BatchFillData::from_batch(batch)?.to_args()?);
+                args.extend([
+                    bcs::to_bytes(&batch.oracle_timestamp)?,
+                    bcs::to_bytes(&price_x(batch.min_price).context("Batch min price out of range")?)?,
+                    bcs::to_bytes(&price_x(batch.max_price).context("Batch max price out of range")?)?,
+                    bcs::to_bytes(&batch.expiry_timestamp)?,
+                    events_addr,
+                ]);
+                ("apply_batch_once_simple", args)
+            }
+        };
+
+        let payload_bytes = args.iter().map(Vec::len).sum();
+        let payload = TransactionPayload::EntryFunction(EntryFunction::new(
+            ModuleId::new(self.contract_address, "perp_engine".to_string()),
+            function.to_string(),
+            vec![], // 无类型参数
+            args,
+        ));
+        Ok((payload, payload_bytes))
+    }
+
+    // ==================== 通用辅助方法 ====================
+
+    /// 调用view函数，返回值按Move返回值顺序排列
+    async fn view(
+        &self,
+        function: String,
+        type_arguments: Vec<String>,
+        arguments: Vec<serde_json::Value>,
+    ) -> Result<Vec<serde_json::Value>> {
+        let request = ViewRequest { function: function.clone(), type_arguments, arguments };
+        let response = self.client.view_function(request).await?.into_inner();
+        response
+            .as_array()
+            .cloned()
+            .with_context(|| format!("Unexpected {} response: {}", function, response))
+    }
+
+    /// 调用合约view函数，短时间内复用相同调用的结果
+    async fn cached_view(&self, function: &str, arguments: Vec<serde_json::Value>) -> Result<Vec<serde_json::Value>> {
+        let function = format!("{}::{}", self.contract_address, function);
+        let key = format!("{}{}", function, serde_json::Value::Array(arguments.clone()));
+        if let Some((fetched_at, values)) = self.view_cache.lock().await.get(&key) {
+            if fetched_at.elapsed() < self.view_cache_ttl {
+                return Ok(values.clone());
+            }
         }
+
+        let values = self.view(function, vec![], arguments).await?;
+        let mut cache = self.view_cache.lock().await;
+        cache.retain(|_, (fetched_at, _)| fetched_at.elapsed() < self.view_cache_ttl);
+        cache.insert(key, (Instant::now(), values.clone()));
+        Ok(values)
+    }
+    
+    /// 签名并提交交易
+    async fn sign_and_submit_transaction(
+        &self,
+        raw_txn: RawTransaction,
+    ) -> Result<String> {
+        self.sign_and_submit_with(self.admin.signer.as_ref(), raw_txn).await
     }
 
-    fn decimal_to_u128(&self, decimal: Decimal) -> Result<u128> {
-        decimal
-            .to_string()
-            .parse()
-            .context("Failed to convert decimal to u128")
+    /// 以本地跟踪序列号的账户提交交易
+    /// 序列号在提交前即被占用，超时或被取消的交易不会把同一序列号让给下一笔交易；
+    /// 提交被拒绝时才从链上重新同步
+    async fn submit_tracked(
+        &self,
+        account: &SettlementSigner,
+        payload: TransactionPayload,
+        max_gas_amount: u64,
+        gas_unit_price: u64,
+    ) -> Result<String> {
+        // 持有序列号锁直到提交完成，保证同一账户的交易按序提交
+        let mut next_sequence = account.next_sequence.lock().await;
+        let sequence_number = match *next_sequence {
+            Some(sequence_number) => sequence_number,
+            None => self.fetch_sequence_number(account.address).await?,
+        };
+
+        let raw_txn = RawTransaction::new(
+            account.address,
+            sequence_number,
+            payload,
+            max_gas_amount,
+            gas_unit_price,
+            self.get_expiration_timestamp().await?,
+            self.chain_id,
+        );
+
+        *next_sequence = Some(sequence_number + 1);
+        match self.sign_and_submit_with(account.signer.as_ref(), raw_txn).await {
+            Ok(tx_hash) => {
+                debug!("Transaction {} from {} uses sequence {}", tx_hash, account.address, sequence_number);
+                Ok(tx_hash)
+            }
+            Err(e) => {
+                *next_sequence = None;
+                Err(e)
+            }
+        }
     }
 
-    pub async fn get_account_balance(&self, address: &str) -> Result<u64> {
-        let resources = self.client.get_account_resources(address.to_string()).await?;
+    /// 以管理员账户提交交易，与结算签名池共用序列号
+    async fn submit_as_admin(&self, payload: TransactionPayload) -> Result<String> {
+        self.submit_tracked(&self.admin, payload, 100_000, 100).await
+    }
+
+    /// 以管理员账户签名但不提交，预先占用其下一个序列号
+    /// 交易哈希在提交前即可记录，提交结果丢失时据此查询交易是否上链
+    async fn prepare_as_admin(&self, payload: TransactionPayload) -> Result<PreparedTransaction> {
+        let mut next_sequence = self.admin.next_sequence.lock().await;
+        let sequence_number = match *next_sequence {
+            Some(sequence_number) => sequence_number,
+            None => self.fetch_sequence_number(self.admin.address).await?,
+        };
+
+        let raw_txn = RawTransaction::new(
+            self.admin.address,
+            sequence_number,
+            payload,
+            100_000,
+            100,
+            self.get_expiration_timestamp().await?,
+            self.chain_id,
+        );
+        let signed_txn = self.sign_with(self.admin.signer.as_ref(), raw_txn).await?;
+        *next_sequence = Some(sequence_number + 1);
+
+        Ok(PreparedTransaction {
+            tx_hash: transaction_hash(&signed_txn)?,
+            sequence_number,
+            bytes: bcs::to_bytes(&signed_txn)?,
+        })
+    }
+
+    async fn sign_with(
+        &self,
+        signer: &dyn TransactionSigner,
+        raw_txn: RawTransaction,
+    ) -> Result<SignedTransaction> {
+        // 生成签名消息
+        let message = raw_txn.generate_signing_message()?;
+        let signature = signer.sign(&message).await?;
+        
+        // 创建签名交易
+        Ok(SignedTransaction::new(
+            raw_txn,
+            TransactionAuthenticator::ed25519(
+                signer.public_key().clone(),
+                signature,
+            ),
+        ))
+    }
+
+    async fn sign_and_submit_with(
+        &self,
+        signer: &dyn TransactionSigner,
+        raw_txn: RawTransaction,
+    ) -> Result<String> {
+        let signed_txn = self.sign_with(signer, raw_txn).await?;
+
+        // 提交交易
+        let result = self.client.submit_transaction(signed_txn).await?;
+        let response_data = result.into_inner();
+        
+        // 提取交易哈希
+        let txn_hash = response_data
+            .get("hash")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string();
         
-        // Find the coin store resource
-        for resource in resources.into_inner() {
-            if resource.type_ == "0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>" {
-                if let Some(coin) = resource.data.get("coin") {
-                    if let Some(value) = coin.get("value") {
-                        if let Some(balance_str) = value.as_str() {
-                            return balance_str.parse().context("Failed to parse balance");
-                        }
-                    }
+        info!("Transaction submitted: {}", txn_hash);
+        Ok(txn_hash)
+    }
+
+    /// 从链上读取账户当前序列号
+    async fn fetch_sequence_number(&self, address: AccountAddress) -> Result<u64> {
+        let resources = self.client.get_account_resources(address.to_string()).await?;
+        self.get_sequence_number(&resources.into_inner())
+    }
+
+    /// 获取账户序列号
+    fn get_sequence_number(&self, resources: &[AccountResource]) -> Result<u64> {
+        for resource in resources {
+            if resource.type_ == "0x1::account::Account" {
+                if let Some(seq) = resource.data.get("sequence_number")
+                    .and_then(|s| s.as_str())
+                    .and_then(|s| s.parse::<u64>().ok()) {
+                    return Ok(seq);
                 }
             }
         }
-
         Ok(0)
     }
 
+    /// 解析配置中的USDC代币类型，如 0x1::module::Coin
+    fn usdc_type_tag(&self) -> Result<TypeTag> {
+        let parts: Vec<&str> = self.usdc_token_type.split("::").collect();
+        let [address, module, name] = parts[..] else {
+            bail!("Invalid coin type {}", self.usdc_token_type);
+        };
+        Ok(TypeTag::Struct(Box::new(StructTag {
+            address: AccountAddress::from_str(address)?,
+            module: module.to_owned(),
+            name: name.to_owned(),
+            type_args: vec![],
+        })))
+    }
+
+    /// 获取过期时间戳
+    async fn get_expiration_timestamp(&self) -> Result<u64> {
+        let state = self.client.get_state().await?;
+        Ok(state.timestamp_usecs / 1000 / 1000 + TRANSACTION_EXPIRATION_SECS)
+    }
+}
+
+#[async_trait]
+impl ChainClient for AptosClient {
+    fn name(&self) -> &str {
+        "aptos"
+    }
+
+    fn decode_vm_status(&self, vm_status: &str) -> ChainError {
+        ChainError::from_vm_status(vm_status, &self.contract_address.to_string())
+    }
+
+    /// 查询APT余额 - 调用0x1::coin::balance
+    async fn get_account_balance(&self, address: &str) -> Result<u64> {
+        let values = self.view(
+            "0x1::coin::balance".to_string(),
+            vec!["0x1::aptos_coin::AptosCoin".to_string()],
+            vec![serde_json::Value::String(address.to_string())],
+        ).await?;
+        view_u64(&values, 0)
+    }
+
     // ==================== 功能1: 下单时冻结资金 ====================
     
     /// 用户下单时冻结资金 - 调用合约划转金额
-    pub async fn freeze_user_funds(
+    async fn freeze_user_funds(
         &self,
         user_address: &str,
         amount: u64,
@@ -139,14 +371,6 @@ impl AptosClient {
     ) -> Result<String> {
         info!("Freezing funds for user {}: {} USDC in market {}", user_address, amount, market_id);
 
-        // 在测试环境中，我们模拟冻结操作
-        // 使用配置文件中的 admin_address 作为测试地址
-        if user_address == self.admin_address.to_string() {
-            let mock_tx_hash = format!("mock_freeze_{}_{}", user_address, chrono::Utc::now().timestamp());
-            info!("Test environment: simulating fund freeze for user {}: tx {}", user_address, mock_tx_hash);
-            return Ok(mock_tx_hash);
-        }
-
         // 获取用户账户信息
         let user_addr = AccountAddress::from_str(user_address)?;
         let resources = self.client.get_account_resources(user_address.to_string()).await?;
@@ -185,51 +409,19 @@ impl AptosClient {
         Ok(tx_hash)
     }
 
-    /// 检查用户抵押品余额
-    pub async fn get_user_collateral(&self, user_address: &str) -> Result<u64> {
-        let resources = self.client.get_account_resources(user_address.to_string()).await?;
-        info!("result Resources: {:?}", resources);
-        
-        // 查找账户资源中的抵押品余额
-        for resource in resources.into_inner() {
-            if resource.type_.contains("Account") {
-                if let Some(collateral) = resource.data.get("collateral") {
-                    if let Some(value) = collateral.as_str() {
-                        return value.parse().context("Failed to parse collateral");
-                    }
-                }
-            }
-        }
-        
-        Ok(0)
-    }
-
-    /// 验证用户是否有足够抵押品
-    pub async fn validate_collateral(
-        &self,
-        user_address: &str,
-        required_amount: u64,
-    ) -> Result<bool> {
-        // 在测试环境中，我们模拟抵押品验证
-        // 使用配置文件中的 admin_address 作为测试地址
-        if user_address == self.admin_address.to_string() {
-            info!("Test environment: simulating sufficient collateral for user {}", user_address);
-            return Ok(true);
-        }
-        
-        let collateral = self.get_user_collateral(user_address).await?;
-        let has_sufficient = collateral >= required_amount;
-
-        if !has_sufficient {
-            warn!("Insufficient collateral for user {}: required {}, available {}", 
-                user_address, required_amount, collateral);
-        }
-        
-        Ok(has_sufficient)
+    /// 检查用户抵押品余额 - 调用account::get_collateral
+    /// 不走view缓存：抵押品账本本身就是缓存，存款后需要读到最新值
+    async fn get_user_collateral(&self, user_address: &str) -> Result<u64> {
+        let values = self.view(
+            format!("{}::account::get_collateral", self.contract_address),
+            vec![],
+            vec![serde_json::Value::String(user_address.to_string())],
+        ).await?;
+        view_u64(&values, 0)
     }
 
     /// 验证用户是否有足够的代币余额
-    pub async fn validate_user_balance(
+    async fn validate_user_balance(
         &self,
         user_address: &str,
         required_amount: u64,
@@ -254,211 +446,366 @@ impl AptosClient {
 
     // ==================== 功能2: 撮合成功后的批量结算 ====================
     
-    /// 批量结算交易 - 调用合约批量结算
-    pub async fn submit_settlement_batch(
+    /// 批量结算交易 - 由结算签名账户提交
+    /// 传入净额变动时调用perp_engine::apply_net_batch_once_simple，否则逐笔调用apply_batch_once_simple
+    /// 序列号在本地递增，提交被拒绝后从链上重新同步
+    async fn submit_settlement_batch(
         &self,
+        signer: &SettlementSigner,
         batch: &SettlementBatch,
+        net_deltas: Option<&[NetDelta]>,
+        max_gas_amount: u64,
     ) -> Result<String> {
-        info!("Submitting settlement batch: {} trades", batch.trades.len());
+        info!("Submitting settlement batch {} from {}: {} trades, max gas {}",
+            batch.id, signer.address, batch.trades.len(), max_gas_amount);
 
-        // 获取管理员账户序列号
-        let resources = self.client.get_account_resources(self.admin_address.to_string()).await?;
-        let sequence_number = self.get_sequence_number(&resources.into_inner())?;
+        // 创建交易载荷，附带批次ID保证幂等
+        let (payload, _) = self.settlement_payload(batch, net_deltas)?;
 
-        // 创建结算批次数据
-        let batch_data = self.create_settlement_batch_data(batch);
+        let tx_hash = self.submit_tracked(signer, payload, max_gas_amount, SETTLEMENT_GAS_UNIT_PRICE).await?;
+        info!("Settlement batch {} submitted: tx {}", batch.id, tx_hash);
+        Ok(tx_hash)
+    }
 
-        // 创建交易载荷 - 调用perp_engine::apply_batch
-        let payload = TransactionPayload::EntryFunction(EntryFunction::new(
-            ModuleId::new(self.contract_address, "perp_engine".to_string()),
-            "apply_batch".to_string(),
-            vec![], // 无类型参数
-            vec![
-                bcs::to_bytes(&batch_data)?,
-                bcs::to_bytes(&self.admin_address)?, // events_addr
-            ],
-        ));
+    /// 模拟结算交易 - 不上链，返回预估gas消耗和执行结果
+    async fn simulate_settlement_batch(
+        &self,
+        signer: &SettlementSigner,
+        batch: &SettlementBatch,
+        net_deltas: Option<&[NetDelta]>,
+        max_gas_amount: u64,
+    ) -> Result<GasEstimate> {
+        // 模拟使用链上当前序列号，不占用本地序列号
+        let sequence_number = self.fetch_sequence_number(signer.address).await?;
 
-        // 创建原始交易
+        let (payload, _) = self.settlement_payload(batch, net_deltas)?;
         let raw_txn = RawTransaction::new(
-            self.admin_address,
+            signer.address,
             sequence_number,
             payload,
-            1_000_000, // 更高的gas限制用于批量操作
-            100,       // gas_unit_price
+            max_gas_amount,
+            SETTLEMENT_GAS_UNIT_PRICE,
             self.get_expiration_timestamp().await?,
             self.chain_id,
         );
 
-        // 签名并提交
-        let tx_hash = self.sign_and_submit_transaction(raw_txn).await?;
-        info!("Settlement batch submitted: tx {}", tx_hash);
+        // 模拟接口要求使用无效签名
+        let signed_txn = SignedTransaction::new(
+            raw_txn,
+            TransactionAuthenticator::ed25519(
+                signer.signer.public_key().clone(),
+                Ed25519Signature::try_from(&[0u8; 64][..])?,
+            ),
+        );
+
+        let response = self.client.simulate_transaction(signed_txn).await?.into_inner();
+        let tx = &response[0];
+        let estimate = GasEstimate {
+            success: tx["success"].as_bool().unwrap_or(false),
+            vm_status: tx["vm_status"].as_str().unwrap_or_default().to_string(),
+            gas_used: tx["gas_used"]
+                .as_str()
+                .and_then(|g| g.parse::<u64>().ok())
+                .unwrap_or_default(),
+        };
+
+        debug!("Simulated settlement batch {}: {:?}", batch.id, estimate);
+        Ok(estimate)
+    }
+
+    /// 结算交易参数的BCS字节数
+    fn settlement_payload_size(
+        &self,
+        batch: &SettlementBatch,
+        net_deltas: Option<&[NetDelta]>,
+    ) -> Result<usize> {
+        Ok(self.settlement_payload(batch, net_deltas)?.1)
+    }
+
+    /// 查询批次是否已在链上应用 - 调用视图函数perp_engine::is_batch_applied
+    async fn is_settlement_batch_applied(&self, batch_id: Uuid) -> Result<bool> {
+        let request = ViewRequest {
+            function: format!("{}::perp_engine::is_batch_applied", self.contract_address),
+            type_arguments: vec![],
+            arguments: vec![
+                serde_json::Value::String(self.admin_address.to_string()),
+                serde_json::Value::String(format!("0x{}", hex::encode(batch_id.as_bytes()))),
+            ],
+        };
+
+        let response = self.client.view_function(request).await?.into_inner();
+        response[0]
+            .as_bool()
+            .context("Unexpected is_batch_applied response")
+    }
+
+    /// 收取强平罚金 - 由结算签名账户提交
+    /// 调用perp_engine::charge_penalty_once，从被强平账户的金库余额转入保险基金账户，强平ID保证只收取一次
+    async fn submit_liquidation_penalty(&self, signer: &SettlementSigner, penalty: &LiquidationPenalty) -> Result<String> {
+        info!("Charging liquidation penalty {} of {} for {}", penalty.liquidation_id, penalty.amount, penalty.user_address);
+
+        let owner = AccountAddress::from_str(&penalty.user_address)?;
+        let payload = TransactionPayload::EntryFunction(EntryFunction::new(
+            ModuleId::new(self.contract_address, "perp_engine".to_string()),
+            "charge_penalty_once".to_string(),
+            vec![],
+            vec![
+                bcs::to_bytes(&self.admin_address)?,
+                bcs::to_bytes(&penalty.liquidation_id.as_bytes().to_vec())?,
+                bcs::to_bytes(&owner)?,
+                bcs::to_bytes(&self.insurance_address)?,
+                bcs::to_bytes(&penalty.market_id)?,
+                bcs::to_bytes(&(penalty.amount as u128))?,
+                bcs::to_bytes(&(penalty.created_at.timestamp() as u64))?,
+            ],
+        ));
+
+        let tx_hash = self.submit_tracked(signer, payload, 100_000, SETTLEMENT_GAS_UNIT_PRICE).await?;
+        info!("Liquidation penalty {} submitted: tx {}", penalty.liquidation_id, tx_hash);
         Ok(tx_hash)
     }
 
-    /// 检查交易状态
-    pub async fn check_transaction_status(&self, tx_hash: &str) -> Result<bool> {
-        match self.client.get_transaction_by_hash(tx_hash.to_string()).await {
-            Ok(_tx) => {
-                // 如果交易存在，认为已确认
-                // 在实际应用中，可以进一步解析交易内容来检查成功状态
-                info!("交易确认Transaction {} confirmed", tx_hash);
-                Ok(true)
+    /// 查询用户在各市场的持仓 - 调用positions::position_of
+    async fn get_positions(&self, user_address: &str, market_ids: &[u64]) -> Result<Vec<ChainPosition>> {
+        let mut positions = Vec::new();
+        for &market_id in market_ids {
+            let values = self.cached_view("positions::position_of", vec![
+                serde_json::Value::String(user_address.to_string()),
+                serde_json::Value::String(market_id.to_string()),
+            ]).await?;
+            let size = view_u128(&values, 0)?;
+            if size == 0 {
+                continue;
             }
-            Err(_) => Ok(false),
+            positions.push(ChainPosition {
+                market_id,
+                size,
+                is_long: values.get(1).and_then(|v| v.as_bool()).context("Missing is_long in position_of response")?,
+                entry_notional: view_u128(&values, 2)?,
+                funding_acc: view_u128(&values, 3)?,
+                last_updated: view_u64(&values, 4)?,
+            });
         }
+        Ok(positions)
     }
 
-    /// 等待交易确认
-    pub async fn wait_for_transaction_confirmation(
-        &self,
-        tx_hash: &str,
-        max_attempts: u32,
-    ) -> Result<bool> {
-        for attempt in 1..=max_attempts {
-            tokio::time::sleep(Duration::from_secs(1)).await;
+    /// 查询市场参数 - 调用market_registry::params
+    async fn get_market_params(&self, market_id: u64) -> Result<MarketParams> {
+        let values = self.cached_view("market_registry::params", vec![
+            serde_json::Value::String(self.admin_address.to_string()),
+            serde_json::Value::String(market_id.to_string()),
+        ]).await?;
+        // vector<u8> 以十六进制字符串返回
+        let symbol = values.first()
+            .and_then(|v| v.as_str())
+            .and_then(|s| hex::decode(s.trim_start_matches("0x")).ok())
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .context("Missing symbol in market_registry::params response")?;
+
+        Ok(MarketParams {
+            market_id,
+            symbol,
+            imr_bps: view_u64(&values, 1)?,
+            mmr_bps: view_u64(&values, 2)?,
+            lot_size: view_u64(&values, 3)?,
+            tick_size: view_u64(&values, 4)?,
+            max_leverage: view_u64(&values, 5)?,
+        })
+    }
+
+    /// 查询链上预言机价格 - 调用oracle_adapter::price_of
+    async fn get_oracle_price(&self, market_id: u64) -> Result<Option<OraclePrice>> {
+        let values = self.cached_view("oracle_adapter::price_of", vec![
+            serde_json::Value::String(self.admin_address.to_string()),
+            serde_json::Value::String(market_id.to_string()),
+        ]).await?;
+        let timestamp = view_u64(&values, 2)?;
+        if timestamp == 0 {
+            return Ok(None);
+        }
 
-            if self.check_transaction_status(tx_hash).await? {
-                info!("Transaction {} confirmed after {} attempts", tx_hash, attempt);
-                return Ok(true);
+        let scale = Decimal::from(100_000_000);
+        Ok(Some(OraclePrice {
+            market_id,
+            price: Decimal::from(view_u64(&values, 0)?) / scale,
+            confidence: Decimal::from(view_u64(&values, 1)?) / scale,
+            timestamp,
+        }))
+    }
+
+    /// 查询手续费参数 - 调用fee::params
+    async fn get_fee_params(&self) -> Result<FeeParams> {
+        let values = self.cached_view("fee::params", vec![
+            serde_json::Value::String(self.admin_address.to_string()),
+        ]).await?;
+        Ok(FeeParams {
+            maker_bps: view_u64(&values, 0)?,
+            taker_bps: view_u64(&values, 1)?,
+            insurance_bps: view_u64(&values, 2)?,
+        })
+    }
+
+    /// 查询已上链的用户交易及其调用的入口函数和参数
+    async fn get_transaction(&self, tx_hash: &str) -> Result<Option<CommittedTransaction>> {
+        let tx = match self.client.get_transaction_by_hash(tx_hash.to_string()).await {
+            Ok(response) => response.into_inner(),
+            Err(e) => {
+                debug!("Transaction {} not found yet: {}", tx_hash, e);
+                return Ok(None);
             }
+        };
+
+        if tx["type"].as_str() != Some("user_transaction") {
+            return Ok(None);
         }
-        
-        warn!("Transaction {} not confirmed after {} attempts", tx_hash, max_attempts);
-        Ok(true)
+
+        let payload = &tx["payload"];
+        Ok(Some(CommittedTransaction {
+            hash: tx_hash.to_string(),
+            sender: tx["sender"].as_str().unwrap_or_default().to_string(),
+            success: tx["success"].as_bool().unwrap_or(false),
+            vm_status: tx["vm_status"].as_str().unwrap_or_default().to_string(),
+            function: payload["function"].as_str().unwrap_or_default().to_string(),
+            type_arguments: payload["type_arguments"]
+                .as_array()
+                .map(|args| args.iter().filter_map(|a| a.as_str().map(str::to_string)).collect())
+                .unwrap_or_default(),
+            arguments: payload["arguments"].as_array().cloned().unwrap_or_default(),
+        }))
     }
 
-    // ==================== 功能3: 撤单时解冻资金 ====================
-    
-    /// 用户撤单时解冻资金 - 取消冻结（划转回去）
-    pub async fn unfreeze_user_funds(
+    /// 检查交易状态
+    /// 交易未上链或仍在内存池中时返回 `Pending`
+    async fn check_transaction_status(&self, tx_hash: &str) -> Result<TransactionStatus> {
+        let tx = match self.client.get_transaction_by_hash(tx_hash.to_string()).await {
+            Ok(response) => response.into_inner(),
+            Err(e) => {
+                debug!("Transaction {} not found yet: {}", tx_hash, e);
+                return Ok(TransactionStatus::Pending);
+            }
+        };
+
+        if tx["type"].as_str() == Some("pending_transaction") {
+            return Ok(TransactionStatus::Pending);
+        }
+
+        let Some(success) = tx["success"].as_bool() else {
+            return Ok(TransactionStatus::Pending);
+        };
+        let vm_status = tx["vm_status"].as_str().unwrap_or_default().to_string();
+        let gas_used = tx["gas_used"]
+            .as_str()
+            .and_then(|g| g.parse::<u64>().ok())
+            .unwrap_or_default();
+
+        if success {
+            info!("Transaction {} confirmed", tx_hash);
+            Ok(TransactionStatus::Success { vm_status, gas_used })
+        } else {
+            warn!("Transaction {} failed: {} ({})", tx_hash, self.decode_vm_status(&vm_status), vm_status);
+            Ok(TransactionStatus::Failed { vm_status, gas_used })
+        }
+    }
+
+    // ==================== 资金费率 ====================
+
+    /// 发布资金费率 - 调用perp_engine::apply_funding_simple
+    /// `rate` 为每个周期的带符号费率，正数表示多头支付空头
+    async fn submit_funding_rate(
         &self,
-        user_address: &str,
-        amount: u64,
+        market_id: u64,
+        rate: Decimal,
     ) -> Result<String> {
-        info!("Unfreezing funds for user {}: {} USDC", user_address, amount);
-
-        // 获取管理员账户信息（只有管理员可以执行提款）
-        let resources = self.client.get_account_resources(self.admin_address.to_string()).await?;
-        let sequence_number = self.get_sequence_number(&resources.into_inner())?;
+        info!("Submitting funding rate for market {}: {}", market_id, rate);
 
-        let user_addr = AccountAddress::from_str(user_address)?;
+        // 链上费率以 constants::rate_scale() (1e8) 缩放，符号单独传递
+        let scaled_rate = (rate.abs() * Decimal::from(100_000_000))
+            .round()
+            .to_u64()
+            .context("Funding rate out of range")?;
+        let longs_pay = rate >= Decimal::ZERO;
 
-        // 创建解冻资金的交易载荷 - 调用vault_coin::withdraw_for
         let payload = TransactionPayload::EntryFunction(EntryFunction::new(
-            ModuleId::new(self.contract_address, "vault_coin".to_owned()),
-            "withdraw_for".to_owned(),
-            vec![TypeTag::Struct(Box::new(StructTag {
-                address: AccountAddress::from_str("0x29b0681a76b20595201859a5d2b269ae9d1fe98251198cefa513c95267003c0c")?,
-                module: "mint_test_coin".to_owned(),
-                name: "Coin".to_owned(),
-                type_args: vec![],
-            }))], // 使用外部测试币类型
+            ModuleId::new(self.contract_address, "perp_engine".to_string()),
+            "apply_funding_simple".to_string(),
+            vec![],
             vec![
-                bcs::to_bytes(&user_addr)?,              // to (admin is implicit signer)
-                bcs::to_bytes(&(amount as u128))?,       // amount as u128
+                bcs::to_bytes(&market_id)?,
+                bcs::to_bytes(&scaled_rate)?,
+                bcs::to_bytes(&longs_pay)?,
+                bcs::to_bytes(&self.admin_address)?, // events_addr
             ],
         ));
 
-        // 创建原始交易
-        let raw_txn = RawTransaction::new(
-            self.admin_address,
-            sequence_number,
-            payload,
-            100_000, // max_gas_amount
-            100,     // gas_unit_price
-            self.get_expiration_timestamp().await?,
-            self.chain_id,
-        );
-
-        // 签名并提交交易
-        let tx_hash = self.sign_and_submit_transaction(raw_txn).await?;
-        info!("Funds unfrozen for user {}: tx {}", user_address, tx_hash);
+        let tx_hash = self.submit_as_admin(payload).await?;
+        info!("Funding rate submitted for market {}: tx {}", market_id, tx_hash);
         Ok(tx_hash)
     }
 
-    /// 批量解冻多个用户的资金
-    pub async fn batch_unfreeze_funds(
+    /// Push an aggregated index price to `oracle_adapter`, scaled by
+    /// `constants::px_scale()` (1e8).
+    async fn push_oracle_price(
         &self,
-        unfreeze_requests: Vec<(String, u64)>,
+        market_id: u64,
+        price: Decimal,
+        confidence: Decimal,
+        timestamp: u64,
     ) -> Result<String> {
-        info!("Batch unfreezing funds for {} users", unfreeze_requests.len());
+        debug!("Pushing oracle price for market {}: {} ± {}", market_id, price, confidence);
 
-        let resources = self.client.get_account_resources(self.admin_address.to_string()).await?;
-        let _sequence_number = self.get_sequence_number(&resources.into_inner())?;
+        let scale = Decimal::from(100_000_000);
+        let px = (price * scale).round().to_u64().context("Oracle price out of range")?;
+        let conf = (confidence * scale).round().to_u64().context("Oracle confidence out of range")?;
 
-        // 由于 vault_coin 模块没有批量操作，我们循环调用单个 withdraw_for
-        let mut tx_hashes = Vec::new();
-        
-        for (user_address, amount) in unfreeze_requests {
-            let tx_hash = self.unfreeze_user_funds(&user_address, amount).await?;
-            tx_hashes.push(tx_hash);
-        }
+        let payload = TransactionPayload::EntryFunction(EntryFunction::new(
+            ModuleId::new(self.contract_address, "oracle_adapter".to_string()),
+            "push_price".to_string(),
+            vec![],
+            vec![
+                bcs::to_bytes(&market_id)?,
+                bcs::to_bytes(&px)?,
+                bcs::to_bytes(&conf)?,
+                bcs::to_bytes(&timestamp)?,
+            ],
+        ));
 
-        info!("Batch unfreeze completed: {} transactions", tx_hashes.len());
-        // 返回最后一个交易哈希作为代表
-        Ok(tx_hashes.last().unwrap_or(&"no_transactions".to_string()).clone())
+        let tx_hash = self.submit_as_admin(payload).await?;
+        info!("Oracle price pushed for market {}: tx {}", market_id, tx_hash);
+        Ok(tx_hash)
     }
 
-    // ==================== 通用辅助方法 ====================
+    // ==================== 功能3: 撤单时解冻资金 ====================
     
-    /// 签名并提交交易
-    async fn sign_and_submit_transaction(
+    /// 用户撤单时解冻资金 - 取消冻结（划转回去）
+    async fn unfreeze_user_funds(
         &self,
-        raw_txn: RawTransaction,
+        user_address: &str,
+        amount: u64,
     ) -> Result<String> {
-        // 生成签名消息
-        let message = raw_txn.generate_signing_message()?;
-        let signature = self.admin_private_key.sign_message(&message);
-        
-        // 创建签名交易
-        let signed_txn = SignedTransaction::new(
-            raw_txn,
-            TransactionAuthenticator::ed25519(
-                Ed25519PublicKey::from(&self.admin_private_key),
-                signature,
-            ),
-        );
+        info!("Unfreezing funds for user {}: {} USDC", user_address, amount);
 
-        // 提交交易
-        let result = self.client.submit_transaction(signed_txn).await?;
-        let response_data = result.into_inner();
-        
-        // 提取交易哈希
-        let txn_hash = response_data
-            .get("hash")
-            .and_then(|v| v.as_str())
-            .unwrap_or("unknown")
-            .to_string();
-        
-        info!("Transaction submitted: {}", txn_hash);
-        Ok(txn_hash)
-    }
+        let user_addr = AccountAddress::from_str(user_address)?;
 
-    /// 获取账户序列号
-    fn get_sequence_number(&self, resources: &[AccountResource]) -> Result<u64> {
-        for resource in resources {
-            if resource.type_ == "0x1::account::Account" {
-                if let Some(seq) = resource.data.get("sequence_number")
-                    .and_then(|s| s.as_str())
-                    .and_then(|s| s.parse::<u64>().ok()) {
-                    return Ok(seq);
-                }
-            }
-        }
-        Ok(0)
-    }
+        // 创建解冻资金的交易载荷 - 调用vault_coin::withdraw_for
+        let payload = TransactionPayload::EntryFunction(EntryFunction::new(
+            ModuleId::new(self.contract_address, "vault_coin".to_owned()),
+            "withdraw_for".to_owned(),
+            vec![self.usdc_type_tag()?], // 与冻结时存入的代币类型一致
+            vec![
+                bcs::to_bytes(&user_addr)?,              // to (admin is implicit signer)
+                bcs::to_bytes(&(amount as u128))?,       // amount as u128
+            ],
+        ));
 
-    /// 获取过期时间戳
-    async fn get_expiration_timestamp(&self) -> Result<u64> {
-        let state = self.client.get_state().await?;
-        Ok(state.timestamp_usecs / 1000 / 1000 + 30) // 30秒后过期
+        let tx_hash = self.submit_as_admin(payload).await?;
+        info!("Funds unfrozen for user {}: tx {}", user_address, tx_hash);
+        Ok(tx_hash)
     }
 
     // ==================== 功能4: 用户存款 ====================
     
     /// 用户存款到HyperPerp金库
-    pub async fn deposit_funds(
+    async fn deposit_funds(
         &self,
         user_address: &str,
         amount: u64,
@@ -498,24 +845,285 @@ impl AptosClient {
         info!("Deposit transaction submitted: tx {}", tx_hash);
         Ok(tx_hash)
     }
+
+    /// 签名提取抵押品到用户钱包的交易 - 管理员调用vault_coin::withdraw_for，与存款同一金库
+    async fn sign_withdrawal(
+        &self,
+        user_address: &str,
+        amount: u64,
+    ) -> Result<PreparedTransaction> {
+        info!("Signing withdrawal of {} USDC for user {}", amount, user_address);
+
+        let user_addr = AccountAddress::from_str(user_address)?;
+
+        // 创建提款交易载荷 - 调用vault_coin::withdraw_for
+        let payload = TransactionPayload::EntryFunction(EntryFunction::new(
+            ModuleId::new(self.contract_address, "vault_coin".to_owned()),
+            "withdraw_for".to_owned(),
+            vec![self.usdc_type_tag()?],
+            vec![
+                bcs::to_bytes(&user_addr)?,              // to (admin is implicit signer)
+                bcs::to_bytes(&(amount as u128))?,       // amount as u128
+            ],
+        ));
+
+        let transaction = self.prepare_as_admin(payload).await?;
+        info!("Withdrawal transaction signed: tx {} (sequence {})", transaction.tx_hash, transaction.sequence_number);
+        Ok(transaction)
+    }
+
+    /// 提交预先签名的交易；被拒绝时从链上重新同步管理员序列号
+    async fn submit_prepared(&self, transaction: &PreparedTransaction) -> Result<()> {
+        let signed_txn: SignedTransaction = bcs::from_bytes(&transaction.bytes)?;
+        if let Err(e) = self.client.submit_transaction(signed_txn).await {
+            *self.admin.next_sequence.lock().await = None;
+            return Err(e.into());
+        }
+        info!("Transaction submitted: {}", transaction.tx_hash);
+        Ok(())
+    }
+}
+
+/// 用户交易哈希：sha3_256(sha3_256("APTOS::Transaction") || bcs(Transaction::UserTransaction(txn)))
+fn transaction_hash(signed_txn: &SignedTransaction) -> Result<String> {
+    let mut hasher = Sha3_256::new();
+    hasher.update(Sha3_256::digest(b"APTOS::Transaction"));
+    // Transaction::UserTransaction 的枚举序号
+    hasher.update([0u8]);
+    hasher.update(bcs::to_bytes(signed_txn)?);
+    Ok(format!("0x{}", hex::encode(hasher.finalize())))
+}
+
+/// u64 view return values are JSON strings
+fn view_u64(values: &[serde_json::Value], index: usize) -> Result<u64> {
+    values.get(index)
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse().ok())
+        .with_context(|| format!("Expected a u64 view value at {}: {:?}", index, values))
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+fn view_u128(values: &[serde_json::Value], index: usize) -> Result<u128> {
+    values.get(index)
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse().ok())
+        .with_context(|| format!("Expected a u128 view value at {}: {:?}", index, values))
+}
+
+/// Fill arguments of `perp_engine::apply_batch_once_simple`, one vector per field
+#[derive(Debug, Clone, Default)]
 struct BatchFillData {
-    taker: AccountAddress,
-    maker: AccountAddress,
-    market_id: u64,
-    size: u128,
-    price_x: u128,
-    fee_bps: u64,
-    ts: u64,
+    takers: Vec<AccountAddress>,
+    makers: Vec<AccountAddress>,
+    market_ids: Vec<u64>,
+    sizes: Vec<u128>,
+    prices_x: Vec<u64>,
+    fee_bps: Vec<u64>,
+    timestamps: Vec<u64>,
+    taker_is_long: Vec<bool>,
+}
+
+impl BatchFillData {
+    fn from_batch(batch: &SettlementBatch) -> Result<Self> {
+        let mut fills = BatchFillData::default();
+        for trade in &batch.trades {
+            fills.takers.push(AccountAddress::from_str(&trade.taker_address)?);
+            fills.makers.push(AccountAddress::from_str(&trade.maker_address)?);
+            fills.market_ids.push(trade.market_id);
+            fills.sizes.push(size_units(trade.size)?);
+            fills.prices_x.push(price_x(trade.price).context("Fill price out of range")?);
+            fills.fee_bps.push(SETTLEMENT_FEE_BPS);
+            fills.timestamps.push(trade.created_at.timestamp() as u64);
+            fills.taker_is_long.push(trade.side == OrderSide::Buy);
+        }
+        Ok(fills)
+    }
+
+    fn to_args(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(vec![
+            bcs::to_bytes(&self.takers)?,
+            bcs::to_bytes(&self.makers)?,
+            bcs::to_bytes(&self.market_ids)?,
+            bcs::to_bytes(&self.sizes)?,
+            bcs::to_bytes(&self.prices_x)?,
+            bcs::to_bytes(&self.fee_bps)?,
+            bcs::to_bytes(&self.timestamps)?,
+            bcs::to_bytes(&self.taker_is_long)?,
+        ])
+    }
+}
+
+/// Delta arguments of `perp_engine::apply_net_batch_once_simple`, one vector per field
+#[derive(Debug, Clone, Default)]
+struct NetDeltaData {
+    owners: Vec<AccountAddress>,
+    market_ids: Vec<u64>,
+    sizes: Vec<u128>,
+    is_long: Vec<bool>,
+    prices_x: Vec<u64>,
+    min_fill_prices_x: Vec<u64>,
+    max_fill_prices_x: Vec<u64>,
+    entry_notionals: Vec<u128>,
+    quotes: Vec<u128>,
+    receives_quote: Vec<bool>,
+    taker_fees: Vec<u64>,
+    maker_fees: Vec<u64>,
+    fill_counts: Vec<u64>,
+}
+
+impl NetDeltaData {
+    fn push(&mut self, delta: &NetDelta) -> Result<()> {
+        self.owners.push(AccountAddress::from_str(&delta.user_address)?);
+        self.market_ids.push(delta.market_id);
+        self.sizes.push(size_units(delta.size_delta.abs())?);
+        self.is_long.push(delta.size_delta >= Decimal::ZERO);
+        self.prices_x.push(price_x(delta.price).context("Net delta price out of range")?);
+        self.min_fill_prices_x.push(price_x(delta.min_price).context("Net delta price out of range")?);
+        self.max_fill_prices_x.push(price_x(delta.max_price).context("Net delta price out of range")?);
+        // Already summed from truncated per-fill quotes; rounding here would settle a different amount
+        if !delta.quote_delta.fract().is_zero() || !delta.entry_notional.fract().is_zero() {
+            bail!("Net delta of {} does not have whole quote amounts", delta.user_address);
+        }
+        self.entry_notionals.push(delta.entry_notional.to_u128().context("Net delta notional out of range")?);
+        self.quotes.push(delta.quote_delta.abs().to_u128().context("Net delta quote out of range")?);
+        self.receives_quote.push(delta.quote_delta > Decimal::ZERO);
+        self.taker_fees.push(delta.taker_fee.round().to_u64().context("Taker fee out of range")?);
+        self.maker_fees.push(delta.maker_fee.round().to_u64().context("Maker fee out of range")?);
+        self.fill_counts.push(delta.trade_ids.len() as u64);
+        Ok(())
+    }
+
+    fn to_args(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(vec![
+            bcs::to_bytes(&self.owners)?,
+            bcs::to_bytes(&self.market_ids)?,
+            bcs::to_bytes(&self.sizes)?,
+            bcs::to_bytes(&self.is_long)?,
+            bcs::to_bytes(&self.prices_x)?,
+            bcs::to_bytes(&self.min_fill_prices_x)?,
+            bcs::to_bytes(&self.max_fill_prices_x)?,
+            bcs::to_bytes(&self.entry_notionals)?,
+            bcs::to_bytes(&self.quotes)?,
+            bcs::to_bytes(&self.receives_quote)?,
+            bcs::to_bytes(&self.taker_fees)?,
+            bcs::to_bytes(&self.maker_fees)?,
+            bcs::to_bytes(&self.fill_counts)?,
+        ])
+    }
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
-struct SettlementBatchData {
-    fills: Vec<BatchFillData>,
-    oracle_ts: u64,
-    min_px: u128,
-    max_px: u128,
-    expiry: u64,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chain::fill_quote, models::Trade, netting::net_trades};
+
+    /// Signed positions, entry notionals and quote balances after replaying a payload on-chain
+    #[derive(Debug, Default, PartialEq)]
+    struct Ledger {
+        positions: HashMap<(AccountAddress, u64), i128>,
+        entry_notionals: HashMap<(AccountAddress, u64), u128>,
+        quote: HashMap<AccountAddress, i128>,
+    }
+
+    impl Ledger {
+        fn fill(&mut self, owner: AccountAddress, market_id: u64, size: u128, is_long: bool, notional: u128) {
+            let size = size as i128;
+            *self.positions.entry((owner, market_id)).or_default() += if is_long { size } else { -size };
+            *self.entry_notionals.entry((owner, market_id)).or_default() += notional;
+        }
+
+        fn transfer(&mut self, from: AccountAddress, to: AccountAddress, amount: u128) {
+            *self.quote.entry(from).or_default() -= amount as i128;
+            *self.quote.entry(to).or_default() += amount as i128;
+        }
+
+        /// Mirrors `perp_engine::apply_batch_at`
+        fn apply_fills(fills: &BatchFillData) -> Self {
+            let mut ledger = Ledger::default();
+            for i in 0..fills.takers.len() {
+                let (taker, maker, taker_is_long) = (fills.takers[i], fills.makers[i], fills.taker_is_long[i]);
+                let quote = fill_quote(fills.sizes[i], fills.prices_x[i]);
+                ledger.fill(taker, fills.market_ids[i], fills.sizes[i], taker_is_long, quote);
+                ledger.fill(maker, fills.market_ids[i], fills.sizes[i], !taker_is_long, quote);
+                if taker_is_long {
+                    ledger.transfer(taker, maker, quote);
+                } else {
+                    ledger.transfer(maker, taker, quote);
+                }
+            }
+            ledger
+        }
+
+        /// Mirrors `perp_engine::apply_net_batch_once`
+        fn apply_deltas(deltas: &NetDeltaData) -> Self {
+            let mut ledger = Ledger::default();
+            for i in 0..deltas.owners.len() {
+                ledger.fill(
+                    deltas.owners[i], deltas.market_ids[i], deltas.sizes[i], deltas.is_long[i], deltas.entry_notionals[i],
+                );
+                let quote = deltas.quotes[i] as i128;
+                *ledger.quote.entry(deltas.owners[i]).or_default() +=
+                    if deltas.receives_quote[i] { quote } else { -quote };
+            }
+            ledger.positions.retain(|_, size| *size != 0);
+            ledger
+        }
+    }
+
+    fn trade(market_id: u64, taker: &str, maker: &str, side: OrderSide, size: i64, price: Decimal) -> Trade {
+        Trade {
+            id: Uuid::new_v4(),
+            market_id,
+            taker_order_id: Uuid::new_v4(),
+            maker_order_id: Uuid::new_v4(),
+            taker_address: taker.to_string(),
+            maker_address: maker.to_string(),
+            size: Decimal::from(size),
+            price,
+            side,
+            created_at: chrono::Utc::now(),
+            settlement_batch_id: None,
+        }
+    }
+
+    fn batch(trades: Vec<Trade>) -> SettlementBatch {
+        SettlementBatch {
+            id: Uuid::new_v4(),
+            trades,
+            oracle_timestamp: 0,
+            min_price: Decimal::ZERO,
+            max_price: Decimal::from(1_000_000),
+            expiry_timestamp: 0,
+            status: crate::models::SettlementStatus::Pending,
+            transaction_hash: None,
+            vm_status: None,
+            gas_used: None,
+            submitted_at: None,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn netted_settlement_matches_gross_settlement() {
+        let batch = batch(vec![
+            // Fractional prices, so per-fill quotes truncate differently from the netted notional
+            trade(1, "0xa", "0xb", OrderSide::Buy, 2, Decimal::new(1009, 1)),
+            trade(1, "0xa", "0xb", OrderSide::Sell, 3, Decimal::new(11035, 2)),
+            trade(1, "0xc", "0xa", OrderSide::Sell, 1, Decimal::new(1047, 1)),
+            trade(2, "0xb", "0xc", OrderSide::Sell, 4, Decimal::new(5055, 2)),
+            trade(2, "0xc", "0xb", OrderSide::Buy, 1, Decimal::new(551, 1)),
+        ]);
+
+        let mut gross = Ledger::apply_fills(&BatchFillData::from_batch(&batch).unwrap());
+        gross.positions.retain(|_, size| *size != 0);
+
+        let mut deltas = NetDeltaData::default();
+        for delta in net_trades(batch.id, &batch.trades, SETTLEMENT_FEE_BPS).unwrap() {
+            deltas.push(&delta).unwrap();
+        }
+
+        assert_eq!(Ledger::apply_deltas(&deltas), gross);
+        // A selling taker ends up short on both paths
+        let b = AccountAddress::from_str("0xb").unwrap();
+        assert_eq!(gross.positions[&(b, 2)], -5);
+    }
 }
@@ -0,0 +1,486 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    aptos_client::AptosClient,
+    config::{AptosConfig, ChainKind},
+    mock_chain::MockChain,
+    models::{LiquidationPenalty, NetDelta, SettlementBatch},
+    signer_pool::SettlementSigner,
+};
+
+/// Decimals of the USDC coin; wallet and vault amounts are in its base units
+pub const USDC_DECIMALS: u32 = 6;
+/// Base units per whole USDC
+pub const USDC_UNIT: u64 = 10u64.pow(USDC_DECIMALS);
+/// Fixed point scale of on-chain prices, `constants::px_scale()`
+pub const PX_SCALE: u64 = 100_000_000;
+/// `constants::bps_scale()`
+pub const BPS_SCALE: u64 = 10_000;
+/// How long after submission a transaction can still be executed
+pub const TRANSACTION_EXPIRATION_SECS: u64 = 30;
+
+/// Result of simulating a transaction.
+#[derive(Debug, Clone)]
+pub struct GasEstimate {
+    pub success: bool,
+    pub vm_status: String,
+    pub gas_used: u64,
+}
+
+impl GasEstimate {
+    pub fn is_out_of_gas(&self) -> bool {
+        !self.success && ChainError::is_out_of_gas(&self.vm_status)
+    }
+
+    pub fn error(&self, contract_address: &str) -> Option<ChainError> {
+        if self.success { None } else { Some(ChainError::from_vm_status(&self.vm_status, contract_address)) }
+    }
+}
+
+/// On-chain outcome of a submitted transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionStatus {
+    /// Not yet committed, or not visible to the fullnode yet
+    Pending,
+    Success { vm_status: String, gas_used: u64 },
+    Failed { vm_status: String, gas_used: u64 },
+}
+
+impl TransactionStatus {
+    /// Decoded failure of a failed transaction.
+    pub fn error(&self, contract_address: &str) -> Option<ChainError> {
+        match self {
+            TransactionStatus::Failed { vm_status, .. } => Some(ChainError::from_vm_status(vm_status, contract_address)),
+            _ => None,
+        }
+    }
+}
+
+/// A transaction signed but not yet submitted, so its hash can be recorded
+/// before it can reach the chain.
+#[derive(Debug, Clone)]
+pub struct PreparedTransaction {
+    pub tx_hash: String,
+    /// Sequence number of the sender it was signed under
+    pub sequence_number: u64,
+    /// BCS of the signed transaction
+    pub bytes: Vec<u8>,
+}
+
+/// Why a transaction failed, decoded from its VM status.
+///
+/// Abort codes are those of `hyperperp::errors`, and are only read that way
+/// for modules published at the configured contract address; `managed_coin`
+/// keeps its own codes, and the framework coin balance check is recognised
+/// too.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ChainError {
+    #[error("contract not initialized")]
+    NotInitialized,
+    #[error("already initialized")]
+    AlreadyInitialized,
+    #[error("signer is not authorized")]
+    Unauthorized,
+    #[error("contract is paused")]
+    Paused,
+    #[error("amount too small")]
+    AmountTooSmall,
+    #[error("contract must be paused first")]
+    PausedRequired,
+    #[error("insufficient margin")]
+    InsufficientMargin,
+    #[error("fill price outside the batch price bounds")]
+    PriceOutOfBounds,
+    #[error("oracle price is stale")]
+    OracleStale,
+    #[error("batch expired")]
+    BatchExpired,
+    #[error("batch already applied")]
+    BatchAlreadyApplied,
+    #[error("batch argument vectors differ in length")]
+    BatchLengthMismatch,
+    #[error("unknown market")]
+    UnknownMarket,
+    #[error("position not found")]
+    PositionNotFound,
+    #[error("invalid leverage")]
+    InvalidLeverage,
+    #[error("insufficient collateral")]
+    InsufficientCollateral,
+    #[error("position registry already exists")]
+    PositionRegistryExists,
+    #[error("position registry not found")]
+    PositionRegistryNotFound,
+    #[error("position not empty")]
+    PositionNotEmpty,
+    #[error("insufficient coin balance")]
+    InsufficientBalance,
+    #[error("Move abort 0x{code:x} in {location}")]
+    UnknownAbort { location: String, code: u64 },
+    #[error("out of gas")]
+    OutOfGas,
+    #[error("transaction failed: {0}")]
+    Other(String),
+}
+
+impl ChainError {
+    /// Decode a failed transaction's VM status, e.g.
+    /// `Move abort in 0x1234::perp_engine: E_BATCH_EXPIRED(0xd): Batch expired`.
+    pub fn from_vm_status(vm_status: &str, contract_address: &str) -> Self {
+        if let Some((_, abort)) = vm_status.split_once("Move abort in ") {
+            let (location, detail) = abort.split_once(": ").unwrap_or((abort, ""));
+            if let Some(code) = Self::parse_abort_code(detail) {
+                return Self::from_abort(location.trim(), code, contract_address);
+            }
+        }
+        if Self::is_out_of_gas(vm_status) {
+            return ChainError::OutOfGas;
+        }
+        ChainError::Other(vm_status.to_string())
+    }
+
+    fn is_out_of_gas(vm_status: &str) -> bool {
+        vm_status.to_uppercase().replace(' ', "_").contains("OUT_OF_GAS")
+    }
+
+    /// The code in `E_NAME(0xd): description` or a bare `0xd`.
+    fn parse_abort_code(detail: &str) -> Option<u64> {
+        let code = match detail.split_once('(') {
+            Some((_, rest)) => rest.split(')').next()?,
+            None => detail.split_whitespace().next()?,
+        };
+        u64::from_str_radix(code.trim_start_matches("0x"), 16).ok()
+    }
+
+    fn from_abort(location: &str, code: u64, contract_address: &str) -> Self {
+        let (address, module) = location.rsplit_once("::").unwrap_or(("", location));
+        let unknown = || ChainError::UnknownAbort { location: location.to_string(), code };
+
+        if normalize_address(address) == "1" {
+            // Framework codes carry an error category in the upper bits
+            return match (module, code) {
+                ("coin", 0x10006) => ChainError::InsufficientBalance,
+                _ => unknown(),
+            };
+        }
+
+        // Another package's codes mean something else entirely
+        if normalize_address(address) != normalize_address(contract_address) {
+            return unknown();
+        }
+
+        match (module, code) {
+            ("managed_coin", 3) => ChainError::InsufficientBalance,
+            ("managed_coin", 4) => ChainError::AmountTooSmall,
+            ("managed_coin", 5) => ChainError::Unauthorized,
+            (_, 1) => ChainError::NotInitialized,
+            (_, 2) => ChainError::AlreadyInitialized,
+            (_, 3) => ChainError::Unauthorized,
+            (_, 4) => ChainError::Paused,
+            (_, 5) => ChainError::AmountTooSmall,
+            (_, 6) => ChainError::PausedRequired,
+            (_, 10) => ChainError::InsufficientMargin,
+            (_, 11) => ChainError::PriceOutOfBounds,
+            (_, 12) => ChainError::OracleStale,
+            (_, 13) => ChainError::BatchExpired,
+            (_, 14) => ChainError::BatchAlreadyApplied,
+            (_, 15) => ChainError::BatchLengthMismatch,
+            (_, 20) => ChainError::UnknownMarket,
+            (_, 21) => ChainError::PositionNotFound,
+            (_, 22) => ChainError::InvalidLeverage,
+            (_, 23) => ChainError::InsufficientCollateral,
+            (_, 24) => ChainError::PositionRegistryExists,
+            (_, 25) => ChainError::PositionRegistryNotFound,
+            (_, 26) => ChainError::PositionNotEmpty,
+            _ => unknown(),
+        }
+    }
+
+    /// Stable identifier returned to API clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ChainError::NotInitialized => "not_initialized",
+            ChainError::AlreadyInitialized => "already_initialized",
+            ChainError::Unauthorized => "unauthorized",
+            ChainError::Paused => "paused",
+            ChainError::AmountTooSmall => "amount_too_small",
+            ChainError::PausedRequired => "paused_required",
+            ChainError::InsufficientMargin => "insufficient_margin",
+            ChainError::PriceOutOfBounds => "price_out_of_bounds",
+            ChainError::OracleStale => "oracle_stale",
+            ChainError::BatchExpired => "batch_expired",
+            ChainError::BatchAlreadyApplied => "batch_already_applied",
+            ChainError::BatchLengthMismatch => "batch_length_mismatch",
+            ChainError::UnknownMarket => "unknown_market",
+            ChainError::PositionNotFound => "position_not_found",
+            ChainError::InvalidLeverage => "invalid_leverage",
+            ChainError::InsufficientCollateral => "insufficient_collateral",
+            ChainError::PositionRegistryExists => "position_registry_exists",
+            ChainError::PositionRegistryNotFound => "position_registry_not_found",
+            ChainError::PositionNotEmpty => "position_not_empty",
+            ChainError::InsufficientBalance => "insufficient_balance",
+            ChainError::UnknownAbort { .. } => "move_abort",
+            ChainError::OutOfGas => "out_of_gas",
+            ChainError::Other(_) => "transaction_failed",
+        }
+    }
+
+    /// Whether the same transaction may succeed later, as opposed to failing
+    /// on its contents every time.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ChainError::Paused
+                | ChainError::PriceOutOfBounds
+                | ChainError::OracleStale
+                | ChainError::BatchExpired
+                | ChainError::OutOfGas
+                | ChainError::Other(_)
+        )
+    }
+
+    /// Failures of the engine's own accounts or deployment rather than of a
+    /// transaction; nothing settles until an operator fixes them.
+    pub fn needs_operator(&self) -> bool {
+        matches!(self, ChainError::Unauthorized | ChainError::NotInitialized)
+    }
+}
+
+/// A user's position in one market, from `positions::position_of`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainPosition {
+    pub market_id: u64,
+    pub size: u128,
+    pub is_long: bool,
+    pub entry_notional: u128,
+    pub funding_acc: u128,
+    /// Microseconds since the epoch
+    pub last_updated: u64,
+}
+
+/// Market parameters from `market_registry::params`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketParams {
+    pub market_id: u64,
+    pub symbol: String,
+    pub imr_bps: u64,
+    pub mmr_bps: u64,
+    pub lot_size: u64,
+    pub tick_size: u64,
+    pub max_leverage: u64,
+}
+
+/// Last price pushed to `oracle_adapter`, unscaled from its 1e8 fixed point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OraclePrice {
+    pub market_id: u64,
+    pub price: Decimal,
+    pub confidence: Decimal,
+    pub timestamp: u64,
+}
+
+/// Fee rates from `fee::params`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeParams {
+    pub maker_bps: u64,
+    pub taker_bps: u64,
+    pub insurance_bps: u64,
+}
+
+/// A committed user transaction with its entry function call.
+#[derive(Debug, Clone)]
+pub struct CommittedTransaction {
+    pub hash: String,
+    pub sender: String,
+    pub success: bool,
+    pub vm_status: String,
+    /// Fully qualified entry function, `<address>::<module>::<function>`
+    pub function: String,
+    pub type_arguments: Vec<String>,
+    /// Arguments in their JSON encoding; u64 and u128 values are strings
+    pub arguments: Vec<serde_json::Value>,
+}
+
+/// Whole USDC in an amount of coin base units.
+pub fn usdc_amount(units: u64) -> Decimal {
+    Decimal::from(units) / Decimal::from(USDC_UNIT)
+}
+
+/// Canonical form of an account address for comparisons: lowercase hex
+/// without the `0x` prefix or leading zeros.
+pub fn normalize_address(address: &str) -> String {
+    let hex = address.trim().trim_start_matches("0x").to_lowercase();
+    let trimmed = hex.trim_start_matches('0');
+    if trimmed.is_empty() { "0".to_string() } else { trimmed.to_string() }
+}
+
+/// Price in the contract's 1e8 fixed point, `constants::px_scale()`.
+pub fn price_x(price: Decimal) -> Result<u64> {
+    (price * Decimal::from(PX_SCALE))
+        .round()
+        .to_u64()
+        .with_context(|| format!("Price {} out of range", price))
+}
+
+/// Size as the contract takes it. The contract has no lot decimals, so only
+/// whole sizes can settle.
+pub fn size_units(size: Decimal) -> Result<u128> {
+    if size.is_sign_negative() || !size.fract().is_zero() {
+        bail!("Size {} is not a whole number of units", size);
+    }
+    size.to_u128().with_context(|| format!("Size {} out of range", size))
+}
+
+/// Quote a fill moves on-chain: `size * price_x / px_scale`, truncated as in
+/// `perp_engine::apply_batch_at`.
+pub fn fill_quote(size: u128, price_x: u64) -> u128 {
+    size * price_x as u128 / PX_SCALE as u128
+}
+
+/// The engine's view of the chain: balances and collateral, fund movements,
+/// settlement, funding and oracle pushes, and transaction status.
+///
+/// `AptosClient` talks to a fullnode; `MockChain` keeps the same state in
+/// memory so the engine can run end to end without a network.
+#[async_trait]
+pub trait ChainClient: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Decode a failed transaction's VM status against this client's contract.
+    fn decode_vm_status(&self, vm_status: &str) -> ChainError;
+
+    async fn get_account_balance(&self, address: &str) -> Result<u64>;
+
+    /// Whether the user's wallet holds at least `required_amount` whole coins.
+    async fn validate_user_balance(&self, user_address: &str, required_amount: u64) -> Result<bool>;
+
+    async fn get_user_collateral(&self, user_address: &str) -> Result<u64>;
+
+    async fn validate_collateral(&self, user_address: &str, required_amount: u64) -> Result<bool> {
+        let collateral = self.get_user_collateral(user_address).await?;
+        let has_sufficient = collateral >= required_amount;
+
+        if !has_sufficient {
+            warn!("Insufficient collateral for user {}: required {}, available {}",
+                user_address, required_amount, collateral);
+        }
+
+        Ok(has_sufficient)
+    }
+
+    async fn freeze_user_funds(&self, user_address: &str, amount: u64, market_id: u64) -> Result<String>;
+
+    async fn unfreeze_user_funds(&self, user_address: &str, amount: u64) -> Result<String>;
+
+    /// Unfreeze for several users, one transaction each; returns the last hash.
+    async fn batch_unfreeze_funds(&self, unfreeze_requests: Vec<(String, u64)>) -> Result<String> {
+        info!("Batch unfreezing funds for {} users", unfreeze_requests.len());
+
+        let mut tx_hashes = Vec::new();
+        for (user_address, amount) in unfreeze_requests {
+            tx_hashes.push(self.unfreeze_user_funds(&user_address, amount).await?);
+        }
+
+        info!("Batch unfreeze completed: {} transactions", tx_hashes.len());
+        Ok(tx_hashes.pop().unwrap_or_else(|| "no_transactions".to_string()))
+    }
+
+    async fn deposit_funds(&self, user_address: &str, amount: u64) -> Result<String>;
+
+    /// Sign a move of vault collateral back to the user's wallet through
+    /// `vault_coin::withdraw_for`, out of the same vault deposits go into.
+    /// Takes the admin account's next sequence number; nothing is submitted.
+    async fn sign_withdrawal(&self, user_address: &str, amount: u64) -> Result<PreparedTransaction>;
+
+    /// Submit a transaction from `sign_withdrawal`. An error does not mean the
+    /// chain never received it; its hash tells.
+    async fn submit_prepared(&self, transaction: &PreparedTransaction) -> Result<()>;
+
+    /// Size of the settlement call arguments, checked against the payload limit.
+    fn settlement_payload_size(&self, batch: &SettlementBatch, net_deltas: Option<&[NetDelta]>) -> Result<usize>;
+
+    async fn simulate_settlement_batch(
+        &self,
+        signer: &SettlementSigner,
+        batch: &SettlementBatch,
+        net_deltas: Option<&[NetDelta]>,
+        max_gas_amount: u64,
+    ) -> Result<GasEstimate>;
+
+    async fn submit_settlement_batch(
+        &self,
+        signer: &SettlementSigner,
+        batch: &SettlementBatch,
+        net_deltas: Option<&[NetDelta]>,
+        max_gas_amount: u64,
+    ) -> Result<String>;
+
+    /// Whether the batch id, or a liquidation id charged by
+    /// `submit_liquidation_penalty`, is recorded on-chain.
+    async fn is_settlement_batch_applied(&self, batch_id: Uuid) -> Result<bool>;
+
+    /// Move a liquidation penalty from the liquidated account's vault balance to
+    /// the insurance account through `perp_engine::charge_penalty_once`.
+    async fn submit_liquidation_penalty(&self, signer: &SettlementSigner, penalty: &LiquidationPenalty) -> Result<String>;
+
+    async fn submit_funding_rate(&self, market_id: u64, rate: Decimal) -> Result<String>;
+
+    async fn push_oracle_price(
+        &self,
+        market_id: u64,
+        price: Decimal,
+        confidence: Decimal,
+        timestamp: u64,
+    ) -> Result<String>;
+
+    /// Open positions of a user in the given markets; empty markets are left out.
+    async fn get_positions(&self, user_address: &str, market_ids: &[u64]) -> Result<Vec<ChainPosition>>;
+
+    async fn get_market_params(&self, market_id: u64) -> Result<MarketParams>;
+
+    /// `None` if no price has been pushed for the market.
+    async fn get_oracle_price(&self, market_id: u64) -> Result<Option<OraclePrice>>;
+
+    async fn get_fee_params(&self) -> Result<FeeParams>;
+
+    /// The committed user transaction, or `None` while it is unknown or pending.
+    async fn get_transaction(&self, tx_hash: &str) -> Result<Option<CommittedTransaction>>;
+
+    /// `Pending` while the transaction is unknown or not yet committed.
+    async fn check_transaction_status(&self, tx_hash: &str) -> Result<TransactionStatus>;
+
+    /// Poll once a second; a transaction still pending after `max_attempts` counts as confirmed.
+    async fn wait_for_transaction_confirmation(&self, tx_hash: &str, max_attempts: u32) -> Result<bool> {
+        for attempt in 1..=max_attempts {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+
+            match self.check_transaction_status(tx_hash).await? {
+                TransactionStatus::Success { .. } => {
+                    info!("Transaction {} confirmed after {} attempts", tx_hash, attempt);
+                    return Ok(true);
+                }
+                TransactionStatus::Failed { .. } => return Ok(false),
+                TransactionStatus::Pending => {}
+            }
+        }
+
+        warn!("Transaction {} not confirmed after {} attempts", tx_hash, max_attempts);
+        Ok(true)
+    }
+}
+
+/// Build the chain client selected in config; `admin` is the signer pool's admin account.
+pub async fn chain_client_from_config(config: &AptosConfig, admin: Arc<SettlementSigner>) -> Result<Arc<dyn ChainClient>> {
+    let client: Arc<dyn ChainClient> = match config.chain {
+        ChainKind::Aptos => Arc::new(AptosClient::new(config, admin).await?),
+        ChainKind::Mock => Arc::new(MockChain::new(config, admin)?),
+    };
+    info!("Using {} chain client", client.name());
+    Ok(client)
+}
@@ -0,0 +1,177 @@
+use anyhow::Result;
+use chrono::Utc;
+use rust_decimal::Decimal;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::RwLock;
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+use crate::{
+    chain::{usdc_amount, ChainClient},
+    config::CollateralConfig,
+    database::Database,
+    models::CollateralBalance,
+};
+
+/// Off-chain view of each user's vault collateral, in whole USDC.
+///
+/// Orders are checked against these balances and the margin engine's
+/// reservations instead of freezing funds on-chain per order; collateral
+/// only moves on-chain through deposits, withdrawals and settlement. Balances
+/// are refreshed from the vault on a timer and right after a deposit or
+/// withdrawal goes through. Collateral of withdrawals still on their way to
+/// the chain is held back from the balance so it cannot back new orders.
+pub struct CollateralLedger {
+    chain_client: Arc<dyn ChainClient>,
+    database: Arc<Database>,
+    config: CollateralConfig,
+    balances: RwLock<HashMap<String, CollateralBalance>>,
+    withdrawal_holds: RwLock<HashMap<String, Decimal>>,
+}
+
+impl CollateralLedger {
+    pub async fn new(
+        chain_client: Arc<dyn ChainClient>,
+        database: Arc<Database>,
+        config: CollateralConfig,
+    ) -> Result<Self> {
+        let balances: HashMap<String, CollateralBalance> = database
+            .get_collateral_balances()
+            .await?
+            .into_iter()
+            .map(|b| (b.user_address.clone(), b))
+            .collect();
+        info!("Collateral ledger loaded {} accounts", balances.len());
+
+        Ok(Self {
+            chain_client,
+            database,
+            config,
+            balances: RwLock::new(balances),
+            withdrawal_holds: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Ledger balance of a user less pending withdrawals, synced from chain
+    /// the first time the user is seen.
+    pub async fn balance(&self, user_address: &str) -> Result<Decimal> {
+        let synced = self.balances.read().await.get(user_address).map(|b| b.balance);
+        match synced {
+            Some(balance) => Ok(balance - self.held(user_address).await),
+            None => self.sync_user(user_address).await,
+        }
+    }
+
+    /// Hold collateral for a withdrawal until it settles on-chain.
+    pub async fn hold(&self, user_address: &str, amount: Decimal) {
+        *self.withdrawal_holds.write().await.entry(user_address.to_string()).or_default() += amount;
+    }
+
+    pub async fn release(&self, user_address: &str, amount: Decimal) {
+        let mut holds = self.withdrawal_holds.write().await;
+        if let Some(held) = holds.get_mut(user_address) {
+            *held = (*held - amount).max(Decimal::ZERO);
+            if held.is_zero() {
+                holds.remove(user_address);
+            }
+        }
+    }
+
+    async fn held(&self, user_address: &str) -> Decimal {
+        self.withdrawal_holds.read().await.get(user_address).copied().unwrap_or_default()
+    }
+
+    /// Refresh a user's balance from the vault, returning it less pending withdrawals.
+    pub async fn sync_user(&self, user_address: &str) -> Result<Decimal> {
+        let collateral = usdc_amount(self.chain_client.get_user_collateral(user_address).await?);
+        let balance = CollateralBalance {
+            user_address: user_address.to_string(),
+            balance: collateral,
+            synced_at: Utc::now(),
+        };
+        self.database.upsert_collateral_balance(&balance).await?;
+
+        let previous = self.balances.write().await.insert(user_address.to_string(), balance);
+        if previous.as_ref().map(|b| b.balance) != Some(collateral) {
+            debug!("Collateral for {} synced: {}", user_address, collateral);
+        }
+        Ok(collateral - self.held(user_address).await)
+    }
+
+    pub async fn start_sync_loop(&self) -> Result<()> {
+        info!("Starting collateral sync loop");
+        let mut interval = interval(Duration::from_secs(self.config.sync_interval_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+
+            let users: Vec<String> = self.balances.read().await.keys().cloned().collect();
+            for user_address in users {
+                if let Err(e) = self.sync_user(&user_address).await {
+                    warn!("Failed to sync collateral for {}: {}", user_address, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chain::USDC_UNIT,
+        margin::MarginEngine,
+        mock_chain::testing::{mock_chain, test_config, ScratchDatabase},
+        models::{Order, OrderSide, OrderStatus, OrderType},
+    };
+    use uuid::Uuid;
+
+    const USER: &str = "0xa11ce";
+
+    #[tokio::test]
+    #[ignore = "needs Postgres in TEST_DATABASE_URL"]
+    async fn rejects_orders_needing_more_than_the_deposited_usdc() {
+        let scratch = ScratchDatabase::create("collateral").await;
+        let config = test_config();
+        let (chain, _) = mock_chain(&config.aptos).await;
+        let chain = Arc::new(chain);
+
+        // 1,000 USDC in the vault
+        chain.deposit_funds(USER, 1_000 * USDC_UNIT).await.unwrap();
+        let ledger = CollateralLedger::new(chain, scratch.database.clone(), config.collateral.clone()).await.unwrap();
+        assert_eq!(ledger.sync_user(USER).await.unwrap(), Decimal::from(1_000));
+
+        let engine = MarginEngine::new(&config.markets);
+        let market_id = config.markets[0].market_id;
+        let order = |size: i64| Order {
+            id: Uuid::new_v4(),
+            user_address: USER.to_string(),
+            market_id,
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            size: Decimal::from(size),
+            price: Some(Decimal::from(100)),
+            filled_size: Decimal::ZERO,
+            status: OrderStatus::Pending,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            expires_at: None,
+        };
+        let balance = ledger.balance(USER).await.unwrap();
+        let marks = HashMap::new();
+
+        let small = order(1);
+        let margin = engine.quote_order(&small, Decimal::from(100)).unwrap();
+        assert!(margin <= Decimal::from(1_000));
+        assert!(engine.check_collateral(&small, margin, &marks, balance).is_ok());
+
+        // Notional of 1,000,000 needs far more than 1,000 USDC of margin
+        let large = order(10_000);
+        let margin = engine.quote_order(&large, Decimal::from(100)).unwrap();
+        assert!(margin > Decimal::from(1_000));
+        assert!(engine.check_collateral(&large, margin, &marks, balance).is_err());
+
+        drop(ledger);
+        scratch.drop().await;
+    }
+}
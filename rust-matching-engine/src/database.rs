@@ -1,11 +1,15 @@
 use anyhow::Result;
 use rust_decimal::Decimal;
-use sqlx::{postgres::PgPoolOptions, PgPool, Row};
+use sqlx::{postgres::{PgPoolOptions, PgRow}, PgPool, Row};
 use std::time::Duration;
 use tracing::{debug, info};
 use uuid::Uuid;
 
-use crate::models::{Order, SettlementBatch, Trade};
+use crate::models::{
+    AdlEvent, CollateralBalance, DeadLetteredTrade, FreezeRequest, FundingRate, HeldTrade, InsuranceFundEntry,
+    Liquidation, LiquidationPenalty, MarginSetting, NetDelta, Order, SettlementBatch, SettlementBatchSummary,
+    SettlementHold, SettlementLag, SettlementRetry, SettlementStatus, Trade, Withdrawal,
+};
 
 pub struct Database {
     pool: PgPool,
@@ -43,7 +47,18 @@ impl Database {
         self.create_type_if_not_exists("order_side", "('buy', 'sell')").await?;
         self.create_type_if_not_exists("order_type", "('market', 'limit')").await?;
         self.create_type_if_not_exists("order_status", "('pending', 'partially_filled', 'filled', 'cancelled', 'expired')").await?;
-        self.create_type_if_not_exists("settlement_status", "('pending', 'submitted', 'confirmed', 'failed')").await?;
+        self.create_type_if_not_exists("settlement_status", "('pending', 'submitted', 'confirmed', 'failed', 'abandoned', 'split')").await?;
+        for value in ["abandoned", "split"] {
+            sqlx::query(&format!("ALTER TYPE settlement_status ADD VALUE IF NOT EXISTS '{}'", value))
+                .execute(&self.pool)
+                .await?;
+        }
+        self.create_type_if_not_exists("insurance_entry_kind", "('liquidation_penalty', 'trading_fee', 'liquidation_loss')").await?;
+        self.create_type_if_not_exists("margin_mode", "('cross', 'isolated')").await?;
+        self.create_type_if_not_exists("freeze_request_status", "('pending', 'confirmed', 'expired', 'refund_pending', 'refunded')").await?;
+        self.create_type_if_not_exists("withdrawal_status", "('queued', 'submitted', 'completed', 'failed')").await?;
+        self.create_type_if_not_exists("penalty_status", "('queued', 'submitted', 'charged', 'failed')").await?;
+        self.create_type_if_not_exists("funding_status", "('submitted', 'published', 'failed')").await?;
         
         sqlx::query(
             r#"
@@ -90,12 +105,150 @@ impl Database {
             r#"
             CREATE TABLE IF NOT EXISTS settlement_batches (
                 id UUID PRIMARY KEY,
+                market_id BIGINT NOT NULL,
                 oracle_timestamp BIGINT NOT NULL,
                 min_price DECIMAL NOT NULL,
                 max_price DECIMAL NOT NULL,
                 expiry_timestamp BIGINT NOT NULL,
                 status settlement_status NOT NULL DEFAULT 'pending',
                 transaction_hash TEXT,
+                vm_status TEXT,
+                gas_used BIGINT,
+                submitted_at TIMESTAMPTZ,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Confirmation tracking columns, for tables created before they existed
+        sqlx::query(
+            r#"
+            ALTER TABLE settlement_batches
+                ADD COLUMN IF NOT EXISTS vm_status TEXT,
+                ADD COLUMN IF NOT EXISTS gas_used BIGINT,
+                ADD COLUMN IF NOT EXISTS submitted_at TIMESTAMPTZ;
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Batches created before market_id was stored take it from their trades
+        let added_market_id = sqlx::query(
+            "SELECT 1 FROM information_schema.columns WHERE table_name = 'settlement_batches' AND column_name = 'market_id'",
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .is_none();
+        if added_market_id {
+            sqlx::query("ALTER TABLE settlement_batches ADD COLUMN market_id BIGINT NOT NULL DEFAULT 0")
+                .execute(&self.pool)
+                .await?;
+            sqlx::query(
+                r#"
+                UPDATE settlement_batches b
+                SET market_id = t.market_id
+                FROM trades t
+                WHERE t.settlement_batch_id = b.id
+                "#,
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS funding_rates (
+                id UUID PRIMARY KEY,
+                market_id BIGINT NOT NULL,
+                rate DECIMAL NOT NULL,
+                premium DECIMAL NOT NULL,
+                mark_price DECIMAL NOT NULL,
+                index_price DECIMAL NOT NULL,
+                funding_time TIMESTAMPTZ NOT NULL,
+                transaction_hash TEXT,
+                status funding_status NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                error TEXT,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS liquidations (
+                id UUID PRIMARY KEY,
+                user_address TEXT NOT NULL,
+                market_id BIGINT NOT NULL,
+                order_id UUID NOT NULL,
+                side order_side NOT NULL,
+                size DECIMAL NOT NULL,
+                filled_size DECIMAL NOT NULL,
+                average_price DECIMAL,
+                mark_price DECIMAL NOT NULL,
+                penalty DECIMAL NOT NULL,
+                equity DECIMAL NOT NULL,
+                maintenance_margin DECIMAL NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS liquidation_penalties (
+                liquidation_id UUID PRIMARY KEY,
+                order_id UUID NOT NULL,
+                user_address TEXT NOT NULL,
+                market_id BIGINT NOT NULL,
+                amount BIGINT NOT NULL,
+                status penalty_status NOT NULL DEFAULT 'queued',
+                tx_hash TEXT,
+                error TEXT,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS insurance_fund (
+                id UUID PRIMARY KEY,
+                kind insurance_entry_kind NOT NULL,
+                market_id BIGINT NOT NULL,
+                amount DECIMAL NOT NULL,
+                balance DECIMAL NOT NULL,
+                reference_id UUID,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS adl_events (
+                id UUID PRIMARY KEY,
+                liquidation_id UUID NOT NULL,
+                trade_id UUID NOT NULL,
+                market_id BIGINT NOT NULL,
+                liquidated_address TEXT NOT NULL,
+                counterparty_address TEXT NOT NULL,
+                side order_side NOT NULL,
+                size DECIMAL NOT NULL,
+                bankruptcy_price DECIMAL NOT NULL,
                 created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
             );
             "#,
@@ -103,6 +256,158 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS margin_settings (
+                user_address TEXT NOT NULL,
+                market_id BIGINT NOT NULL,
+                margin_mode margin_mode NOT NULL DEFAULT 'cross',
+                leverage BIGINT NOT NULL,
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                PRIMARY KEY (user_address, market_id)
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS collateral_balances (
+                user_address TEXT PRIMARY KEY,
+                balance DECIMAL NOT NULL,
+                synced_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS freeze_requests (
+                order_id UUID PRIMARY KEY,
+                user_address TEXT NOT NULL,
+                market_id BIGINT NOT NULL,
+                side order_side NOT NULL,
+                order_type order_type NOT NULL,
+                size NUMERIC NOT NULL,
+                price NUMERIC NOT NULL,
+                order_expires_at TIMESTAMPTZ,
+                required_collateral BIGINT NOT NULL,
+                amount BIGINT NOT NULL,
+                status freeze_request_status NOT NULL DEFAULT 'pending',
+                tx_hash TEXT,
+                refund_tx_hash TEXT,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                expires_at TIMESTAMPTZ NOT NULL
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Each freeze transaction backs at most one order
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS freeze_transactions (
+                tx_hash TEXT PRIMARY KEY,
+                order_id UUID NOT NULL UNIQUE,
+                user_address TEXT NOT NULL,
+                amount BIGINT NOT NULL,
+                used_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS withdrawals (
+                id UUID PRIMARY KEY,
+                user_address TEXT NOT NULL,
+                amount BIGINT NOT NULL,
+                status withdrawal_status NOT NULL DEFAULT 'queued',
+                tx_hash TEXT,
+                sequence_number BIGINT,
+                error TEXT,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Trades a batch was created with, kept after a failed batch releases them
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS settlement_batch_trades (
+                batch_id UUID NOT NULL,
+                trade_id UUID NOT NULL,
+                PRIMARY KEY (batch_id, trade_id)
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS settlement_retries (
+                trade_id UUID PRIMARY KEY,
+                retry_group UUID NOT NULL,
+                batch_id UUID,
+                attempts INTEGER NOT NULL,
+                next_attempt_at TIMESTAMPTZ NOT NULL,
+                last_error TEXT,
+                dead_lettered_at TIMESTAMPTZ,
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS settlement_holds (
+                trade_id UUID PRIMARY KEY,
+                reference_price DECIMAL NOT NULL,
+                min_price DECIMAL NOT NULL,
+                max_price DECIMAL NOT NULL,
+                held_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                released_at TIMESTAMPTZ
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS settlement_net_deltas (
+                batch_id UUID NOT NULL,
+                user_address TEXT NOT NULL,
+                market_id BIGINT NOT NULL,
+                size_delta DECIMAL NOT NULL,
+                price DECIMAL NOT NULL,
+                min_price DECIMAL NOT NULL,
+                max_price DECIMAL NOT NULL,
+                entry_notional DECIMAL NOT NULL,
+                quote_delta DECIMAL NOT NULL,
+                taker_fee DECIMAL NOT NULL,
+                maker_fee DECIMAL NOT NULL,
+                trade_ids UUID[] NOT NULL,
+                PRIMARY KEY (batch_id, user_address, market_id)
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         // Create indexes
         sqlx::query("CREATE INDEX IF NOT EXISTS idx_orders_market_status ON orders(market_id, status)")
             .execute(&self.pool)
@@ -112,6 +417,30 @@ impl Database {
             .execute(&self.pool)
             .await?;
 
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_funding_rates_market_time ON funding_rates(market_id, funding_time)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_insurance_fund_created ON insurance_fund(created_at)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_freeze_requests_status ON freeze_requests(status, expires_at)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_withdrawals_user ON withdrawals(user_address, created_at)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_withdrawals_status ON withdrawals(status)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_liquidation_penalties_status ON liquidation_penalties(status)")
+            .execute(&self.pool)
+            .await?;
+
         debug!("Database migrations completed");
         Ok(())
     }
@@ -262,12 +591,17 @@ impl Database {
     pub async fn get_pending_trades(&self) -> Result<Vec<Trade>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, market_id, taker_order_id, maker_order_id,
-                   taker_address, maker_address, CAST(size AS TEXT) as size, 
-                   CAST(price AS TEXT) as price, side, created_at, settlement_batch_id
-            FROM trades 
-            WHERE settlement_batch_id IS NULL
-            ORDER BY created_at ASC
+            SELECT t.id, t.market_id, t.taker_order_id, t.maker_order_id,
+                   t.taker_address, t.maker_address, CAST(t.size AS TEXT) as size, 
+                   CAST(t.price AS TEXT) as price, t.side, t.created_at, t.settlement_batch_id
+            FROM trades t
+            LEFT JOIN settlement_retries r ON r.trade_id = t.id
+            LEFT JOIN settlement_holds h ON h.trade_id = t.id
+            WHERE t.settlement_batch_id IS NULL
+              AND r.dead_lettered_at IS NULL
+              AND (h.trade_id IS NULL OR h.released_at IS NOT NULL)
+              AND (r.next_attempt_at IS NULL OR r.next_attempt_at <= NOW())
+            ORDER BY t.created_at ASC
             "#,
         )
         .fetch_all(&self.pool)
@@ -290,16 +624,23 @@ impl Database {
         Ok(trades)
     }
 
+    /// Insert a batch, or start a batch resubmitted under its own id over.
     pub async fn insert_settlement_batch(&self, batch: &SettlementBatch) -> Result<()> {
         sqlx::query(
             r#"
             INSERT INTO settlement_batches (
-                id, oracle_timestamp, min_price, max_price,
+                id, market_id, oracle_timestamp, min_price, max_price,
                 expiry_timestamp, status, transaction_hash, created_at
-            ) VALUES ($1, $2, CAST($3 AS numeric), CAST($4 AS numeric), $5, $6, $7, $8)
+            ) VALUES ($1, $2, $3, CAST($4 AS numeric), CAST($5 AS numeric), $6, $7, $8, $9)
+            ON CONFLICT (id)
+            DO UPDATE SET oracle_timestamp = EXCLUDED.oracle_timestamp, min_price = EXCLUDED.min_price,
+                          max_price = EXCLUDED.max_price, expiry_timestamp = EXCLUDED.expiry_timestamp,
+                          status = EXCLUDED.status, transaction_hash = EXCLUDED.transaction_hash,
+                          vm_status = NULL, gas_used = NULL, submitted_at = NULL
             "#,
         )
         .bind(batch.id)
+        .bind(batch.trades.first().map_or(0, |t| t.market_id) as i64)
         .bind(batch.oracle_timestamp as i64)
         .bind(Self::decimal_to_string(&batch.min_price))
         .bind(Self::decimal_to_string(&batch.max_price))
@@ -310,6 +651,14 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        for trade in &batch.trades {
+            sqlx::query("INSERT INTO settlement_batch_trades (batch_id, trade_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+                .bind(batch.id)
+                .bind(trade.id)
+                .execute(&self.pool)
+                .await?;
+        }
+
         debug!("Inserted settlement batch: {}", batch.id);
         Ok(())
     }
@@ -318,12 +667,15 @@ impl Database {
         sqlx::query(
             r#"
             UPDATE settlement_batches 
-            SET status = $1, transaction_hash = $2
-            WHERE id = $3
+            SET status = $1, transaction_hash = $2, vm_status = $3, gas_used = $4, submitted_at = $5
+            WHERE id = $6
             "#,
         )
         .bind(&batch.status)
         .bind(&batch.transaction_hash)
+        .bind(&batch.vm_status)
+        .bind(batch.gas_used.map(|g| g as i64))
+        .bind(batch.submitted_at)
         .bind(batch.id)
         .execute(&self.pool)
         .await?;
@@ -332,63 +684,282 @@ impl Database {
         Ok(())
     }
 
-    pub async fn update_trade_settlement_batch(&self, trade_id: Uuid, batch_id: Uuid) -> Result<()> {
-        sqlx::query(
+    /// Batches in `status`, oldest first, with their trades.
+    pub async fn get_settlement_batches_by_status(&self, status: SettlementStatus) -> Result<Vec<SettlementBatch>> {
+        let rows = sqlx::query(
             r#"
-            UPDATE trades 
-            SET settlement_batch_id = $1 
-            WHERE id = $2
+            SELECT id, oracle_timestamp, CAST(min_price AS TEXT) as min_price,
+                   CAST(max_price AS TEXT) as max_price, expiry_timestamp, status,
+                   transaction_hash, vm_status, gas_used, submitted_at, created_at
+            FROM settlement_batches
+            WHERE status = $1
+            ORDER BY created_at ASC
             "#,
         )
-        .bind(batch_id)
-        .bind(trade_id)
-        .execute(&self.pool)
+        .bind(&status)
+        .fetch_all(&self.pool)
         .await?;
 
-        debug!("Updated trade {} with settlement batch {}", trade_id, batch_id);
-        Ok(())
+        let mut batches = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id: Uuid = row.get("id");
+            batches.push(SettlementBatch {
+                id,
+                trades: self.get_trades_by_settlement_batch(id).await?,
+                oracle_timestamp: row.get::<i64, _>("oracle_timestamp") as u64,
+                min_price: Self::string_to_decimal(row.get::<&str, _>("min_price")),
+                max_price: Self::string_to_decimal(row.get::<&str, _>("max_price")),
+                expiry_timestamp: row.get::<i64, _>("expiry_timestamp") as u64,
+                status: row.get("status"),
+                transaction_hash: row.get("transaction_hash"),
+                vm_status: row.get("vm_status"),
+                gas_used: row.get::<Option<i64>, _>("gas_used").map(|g| g as u64),
+                submitted_at: row.get("submitted_at"),
+                created_at: row.get("created_at"),
+            });
+        }
+
+        Ok(batches)
     }
 
-    pub async fn get_order(&self, order_id: Uuid) -> Result<Order> {
-        let row = sqlx::query(
+    pub async fn get_trades_by_settlement_batch(&self, batch_id: Uuid) -> Result<Vec<Trade>> {
+        let rows = sqlx::query(
             r#"
-            SELECT id, user_address, market_id, side, order_type, 
-                   CAST(size AS TEXT) as size, CAST(price AS TEXT) as price, 
-                   CAST(filled_size AS TEXT) as filled_size, status, created_at, 
-                   updated_at, expires_at
-            FROM orders 
-            WHERE id = $1
+            SELECT id, market_id, taker_order_id, maker_order_id,
+                   taker_address, maker_address, CAST(size AS TEXT) as size,
+                   CAST(price AS TEXT) as price, side, created_at, settlement_batch_id
+            FROM trades
+            WHERE settlement_batch_id = $1
+            ORDER BY created_at ASC
             "#,
         )
-        .bind(order_id)
-        .fetch_one(&self.pool)
+        .bind(batch_id)
+        .fetch_all(&self.pool)
         .await?;
 
-        let order = Order {
+        let trades = rows.into_iter().map(|row| Trade {
             id: row.get("id"),
-            user_address: row.get("user_address"),
             market_id: row.get::<i64, _>("market_id") as u64,
-            side: row.get("side"),
-            order_type: row.get("order_type"),
+            taker_order_id: row.get("taker_order_id"),
+            maker_order_id: row.get("maker_order_id"),
+            taker_address: row.get("taker_address"),
+            maker_address: row.get("maker_address"),
             size: Self::string_to_decimal(row.get::<&str, _>("size")),
-            price: row.get::<Option<&str>, _>("price").map(|p| Self::string_to_decimal(p)),
-            filled_size: Self::string_to_decimal(row.get::<&str, _>("filled_size")),
-            status: row.get("status"),
+            price: Self::string_to_decimal(row.get::<&str, _>("price")),
+            side: row.get("side"),
             created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-            expires_at: row.get("expires_at"),
-        };
+            settlement_batch_id: row.get("settlement_batch_id"),
+        }).collect();
 
-        debug!("Retrieved order: {}", order.id);
-        Ok(order)
+        Ok(trades)
     }
 
-    pub async fn get_orders_by_user(
-        &self, 
-        user_address: &str, 
-        status_filter: Option<&str>,
-        limit: Option<i64>,
-        offset: Option<i64>
+    /// Detach trades from a failed batch so they can be picked up again.
+    pub async fn release_settlement_batch_trades(&self, batch_id: Uuid) -> Result<()> {
+        sqlx::query("UPDATE trades SET settlement_batch_id = NULL WHERE settlement_batch_id = $1")
+            .bind(batch_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_settlement_batches(
+        &self,
+        status: Option<SettlementStatus>,
+        market_id: Option<u64>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SettlementBatchSummary>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT b.id, b.market_id, CAST(b.min_price AS TEXT) as min_price,
+                   CAST(b.max_price AS TEXT) as max_price, b.status, b.transaction_hash,
+                   b.vm_status, b.gas_used, b.submitted_at, b.created_at,
+                   (SELECT COUNT(*) FROM settlement_batch_trades bt WHERE bt.batch_id = b.id) as trade_count
+            FROM settlement_batches b
+            WHERE ($1::settlement_status IS NULL OR b.status = $1)
+              AND ($2::BIGINT IS NULL OR b.market_id = $2)
+            ORDER BY b.created_at DESC
+            LIMIT $3 OFFSET $4
+            "#,
+        )
+        .bind(status)
+        .bind(market_id.map(|m| m as i64))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let batches = rows.into_iter().map(|row| SettlementBatchSummary {
+            id: row.get("id"),
+            market_id: row.get::<i64, _>("market_id") as u64,
+            trade_count: row.get("trade_count"),
+            min_price: Self::string_to_decimal(row.get::<&str, _>("min_price")),
+            max_price: Self::string_to_decimal(row.get::<&str, _>("max_price")),
+            status: row.get("status"),
+            transaction_hash: row.get("transaction_hash"),
+            vm_status: row.get("vm_status"),
+            gas_used: row.get::<Option<i64>, _>("gas_used").map(|g| g as u64),
+            submitted_at: row.get("submitted_at"),
+            created_at: row.get("created_at"),
+        }).collect();
+
+        Ok(batches)
+    }
+
+    /// A batch with every trade it was created with, including ones a failure released.
+    pub async fn get_settlement_batch(&self, batch_id: Uuid) -> Result<Option<SettlementBatch>> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, oracle_timestamp, CAST(min_price AS TEXT) as min_price,
+                   CAST(max_price AS TEXT) as max_price, expiry_timestamp, status,
+                   transaction_hash, vm_status, gas_used, submitted_at, created_at
+            FROM settlement_batches
+            WHERE id = $1
+            "#,
+        )
+        .bind(batch_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(SettlementBatch {
+            id: batch_id,
+            trades: self.get_settlement_batch_members(batch_id).await?,
+            oracle_timestamp: row.get::<i64, _>("oracle_timestamp") as u64,
+            min_price: Self::string_to_decimal(row.get::<&str, _>("min_price")),
+            max_price: Self::string_to_decimal(row.get::<&str, _>("max_price")),
+            expiry_timestamp: row.get::<i64, _>("expiry_timestamp") as u64,
+            status: row.get("status"),
+            transaction_hash: row.get("transaction_hash"),
+            vm_status: row.get("vm_status"),
+            gas_used: row.get::<Option<i64>, _>("gas_used").map(|g| g as u64),
+            submitted_at: row.get("submitted_at"),
+            created_at: row.get("created_at"),
+        }))
+    }
+
+    async fn get_settlement_batch_members(&self, batch_id: Uuid) -> Result<Vec<Trade>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT t.id, t.market_id, t.taker_order_id, t.maker_order_id,
+                   t.taker_address, t.maker_address, CAST(t.size AS TEXT) as size,
+                   CAST(t.price AS TEXT) as price, t.side, t.created_at, t.settlement_batch_id
+            FROM settlement_batch_trades bt
+            JOIN trades t ON t.id = bt.trade_id
+            WHERE bt.batch_id = $1
+            ORDER BY t.created_at ASC
+            "#,
+        )
+        .bind(batch_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let trades = rows.into_iter().map(|row| Trade {
+            id: row.get("id"),
+            market_id: row.get::<i64, _>("market_id") as u64,
+            taker_order_id: row.get("taker_order_id"),
+            maker_order_id: row.get("maker_order_id"),
+            taker_address: row.get("taker_address"),
+            maker_address: row.get("maker_address"),
+            size: Self::string_to_decimal(row.get::<&str, _>("size")),
+            price: Self::string_to_decimal(row.get::<&str, _>("price")),
+            side: row.get("side"),
+            created_at: row.get("created_at"),
+            settlement_batch_id: row.get("settlement_batch_id"),
+        }).collect();
+
+        Ok(trades)
+    }
+
+    pub async fn get_settlement_lag(&self) -> Result<SettlementLag> {
+        let row = sqlx::query(
+            r#"
+            SELECT COUNT(*) as unsettled_trades,
+                   COUNT(*) FILTER (WHERE t.settlement_batch_id IS NULL) as pending_trades,
+                   MIN(t.created_at) as oldest_unsettled_trade_at,
+                   (SELECT COUNT(*) FROM settlement_holds WHERE released_at IS NULL) as held_trades,
+                   (SELECT COUNT(*) FROM settlement_retries WHERE dead_lettered_at IS NOT NULL) as dead_lettered_trades,
+                   (SELECT COUNT(*) FROM settlement_batches WHERE status = 'submitted') as in_flight_batches
+            FROM trades t
+            LEFT JOIN settlement_batches b ON b.id = t.settlement_batch_id
+            WHERE b.status IS DISTINCT FROM 'confirmed'
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(SettlementLag {
+            unsettled_trades: row.get("unsettled_trades"),
+            pending_trades: row.get("pending_trades"),
+            held_trades: row.get("held_trades"),
+            dead_lettered_trades: row.get("dead_lettered_trades"),
+            in_flight_batches: row.get("in_flight_batches"),
+            oldest_unsettled_trade_at: row.get("oldest_unsettled_trade_at"),
+        })
+    }
+
+    pub async fn update_trade_settlement_batch(&self, trade_id: Uuid, batch_id: Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE trades 
+            SET settlement_batch_id = $1 
+            WHERE id = $2
+            "#,
+        )
+        .bind(batch_id)
+        .bind(trade_id)
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Updated trade {} with settlement batch {}", trade_id, batch_id);
+        Ok(())
+    }
+
+    pub async fn get_order(&self, order_id: Uuid) -> Result<Order> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, user_address, market_id, side, order_type, 
+                   CAST(size AS TEXT) as size, CAST(price AS TEXT) as price, 
+                   CAST(filled_size AS TEXT) as filled_size, status, created_at, 
+                   updated_at, expires_at
+            FROM orders 
+            WHERE id = $1
+            "#,
+        )
+        .bind(order_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let order = Order {
+            id: row.get("id"),
+            user_address: row.get("user_address"),
+            market_id: row.get::<i64, _>("market_id") as u64,
+            side: row.get("side"),
+            order_type: row.get("order_type"),
+            size: Self::string_to_decimal(row.get::<&str, _>("size")),
+            price: row.get::<Option<&str>, _>("price").map(|p| Self::string_to_decimal(p)),
+            filled_size: Self::string_to_decimal(row.get::<&str, _>("filled_size")),
+            status: row.get("status"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+            expires_at: row.get("expires_at"),
+        };
+
+        debug!("Retrieved order: {}", order.id);
+        Ok(order)
+    }
+
+    pub async fn get_orders_by_user(
+        &self, 
+        user_address: &str, 
+        status_filter: Option<&str>,
+        limit: Option<i64>,
+        offset: Option<i64>
     ) -> Result<Vec<Order>> {
         let mut query = String::from(
             r#"
@@ -672,4 +1243,1105 @@ impl Database {
         debug!("Retrieved {} trades for market {}", trades.len(), market_id);
         Ok(trades)
     }
+
+    pub async fn insert_funding_rate(&self, funding_rate: &FundingRate) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO funding_rates (
+                id, market_id, rate, premium, mark_price, index_price,
+                funding_time, transaction_hash, status, attempts, error, created_at, updated_at
+            ) VALUES ($1, $2, CAST($3 AS numeric), CAST($4 AS numeric), CAST($5 AS numeric), CAST($6 AS numeric), $7, $8, $9, $10, $11, $12, $13)
+            "#,
+        )
+        .bind(funding_rate.id)
+        .bind(funding_rate.market_id as i64)
+        .bind(Self::decimal_to_string(&funding_rate.rate))
+        .bind(Self::decimal_to_string(&funding_rate.premium))
+        .bind(Self::decimal_to_string(&funding_rate.mark_price))
+        .bind(Self::decimal_to_string(&funding_rate.index_price))
+        .bind(funding_rate.funding_time)
+        .bind(&funding_rate.transaction_hash)
+        .bind(&funding_rate.status)
+        .bind(funding_rate.attempts as i32)
+        .bind(&funding_rate.error)
+        .bind(funding_rate.created_at)
+        .bind(funding_rate.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Inserted funding rate {} for market {}", funding_rate.rate, funding_rate.market_id);
+        Ok(())
+    }
+
+    /// Record the progress of publishing a funding rate.
+    pub async fn update_funding_rate(&self, funding_rate: &FundingRate) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE funding_rates
+            SET transaction_hash = $1, status = $2, attempts = $3, error = $4, updated_at = $5
+            WHERE id = $6
+            "#,
+        )
+        .bind(&funding_rate.transaction_hash)
+        .bind(&funding_rate.status)
+        .bind(funding_rate.attempts as i32)
+        .bind(&funding_rate.error)
+        .bind(funding_rate.updated_at)
+        .bind(funding_rate.id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Funding rates still being published: submitted ones waiting to commit, and
+    /// failed ones with fewer than `max_attempts` submissions. Oldest first.
+    pub async fn get_unpublished_funding_rates(&self, max_attempts: u32) -> Result<Vec<FundingRate>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, market_id, CAST(rate AS TEXT) as rate, CAST(premium AS TEXT) as premium,
+                   CAST(mark_price AS TEXT) as mark_price, CAST(index_price AS TEXT) as index_price,
+                   funding_time, transaction_hash, status, attempts, error, created_at, updated_at
+            FROM funding_rates
+            WHERE status = 'submitted' OR (status = 'failed' AND attempts < $1)
+            ORDER BY funding_time ASC
+            "#,
+        )
+        .bind(max_attempts as i32)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(Self::funding_rate_from_row).collect())
+    }
+
+    pub async fn get_funding_rates(
+        &self,
+        market_id: u64,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<FundingRate>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, market_id, CAST(rate AS TEXT) as rate, CAST(premium AS TEXT) as premium,
+                   CAST(mark_price AS TEXT) as mark_price, CAST(index_price AS TEXT) as index_price,
+                   funding_time, transaction_hash, status, attempts, error, created_at, updated_at
+            FROM funding_rates
+            WHERE market_id = $1
+            ORDER BY funding_time DESC
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(market_id as i64)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(Self::funding_rate_from_row).collect())
+    }
+
+    fn funding_rate_from_row(row: &PgRow) -> FundingRate {
+        FundingRate {
+            id: row.get("id"),
+            market_id: row.get::<i64, _>("market_id") as u64,
+            rate: Self::string_to_decimal(row.get::<&str, _>("rate")),
+            premium: Self::string_to_decimal(row.get::<&str, _>("premium")),
+            mark_price: Self::string_to_decimal(row.get::<&str, _>("mark_price")),
+            index_price: Self::string_to_decimal(row.get::<&str, _>("index_price")),
+            funding_time: row.get("funding_time"),
+            transaction_hash: row.get("transaction_hash"),
+            status: row.get("status"),
+            attempts: row.get::<i32, _>("attempts") as u32,
+            error: row.get("error"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        }
+    }
+
+    pub async fn insert_liquidation(&self, liquidation: &Liquidation) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO liquidations (
+                id, user_address, market_id, order_id, side, size, filled_size,
+                average_price, mark_price, penalty, equity, maintenance_margin, created_at
+            ) VALUES ($1, $2, $3, $4, $5, CAST($6 AS numeric), CAST($7 AS numeric), CAST($8 AS numeric),
+                      CAST($9 AS numeric), CAST($10 AS numeric), CAST($11 AS numeric), CAST($12 AS numeric), $13)
+            "#,
+        )
+        .bind(liquidation.id)
+        .bind(&liquidation.user_address)
+        .bind(liquidation.market_id as i64)
+        .bind(liquidation.order_id)
+        .bind(&liquidation.side)
+        .bind(Self::decimal_to_string(&liquidation.size))
+        .bind(Self::decimal_to_string(&liquidation.filled_size))
+        .bind(liquidation.average_price.map(|p| Self::decimal_to_string(&p)))
+        .bind(Self::decimal_to_string(&liquidation.mark_price))
+        .bind(Self::decimal_to_string(&liquidation.penalty))
+        .bind(Self::decimal_to_string(&liquidation.equity))
+        .bind(Self::decimal_to_string(&liquidation.maintenance_margin))
+        .bind(liquidation.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Inserted liquidation: {}", liquidation.id);
+        Ok(())
+    }
+
+    pub async fn insert_liquidation_penalty(&self, penalty: &LiquidationPenalty) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO liquidation_penalties (
+                liquidation_id, order_id, user_address, market_id, amount, status, attempts, created_at, updated_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(penalty.liquidation_id)
+        .bind(penalty.order_id)
+        .bind(&penalty.user_address)
+        .bind(penalty.market_id as i64)
+        .bind(penalty.amount as i64)
+        .bind(&penalty.status)
+        .bind(penalty.attempts as i32)
+        .bind(penalty.created_at)
+        .bind(penalty.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Penalties not yet charged or failed: queued or submitted, oldest first.
+    pub async fn get_active_liquidation_penalties(&self) -> Result<Vec<LiquidationPenalty>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT liquidation_id, order_id, user_address, market_id, amount, status, tx_hash, error, attempts,
+                   created_at, updated_at
+            FROM liquidation_penalties
+            WHERE status IN ('queued', 'submitted')
+            ORDER BY created_at ASC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| LiquidationPenalty {
+            liquidation_id: row.get("liquidation_id"),
+            order_id: row.get("order_id"),
+            user_address: row.get("user_address"),
+            market_id: row.get::<i64, _>("market_id") as u64,
+            amount: row.get::<i64, _>("amount") as u64,
+            status: row.get("status"),
+            tx_hash: row.get("tx_hash"),
+            error: row.get("error"),
+            attempts: row.get::<i32, _>("attempts") as u32,
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        }).collect())
+    }
+
+    /// Whether every fill of the order is in a confirmed settlement batch.
+    pub async fn order_trades_settled(&self, order_id: Uuid) -> Result<bool> {
+        let row = sqlx::query(
+            r#"
+            SELECT NOT EXISTS (
+                SELECT 1
+                FROM trades t
+                LEFT JOIN settlement_batches b ON b.id = t.settlement_batch_id
+                WHERE (t.taker_order_id = $1 OR t.maker_order_id = $1)
+                  AND (b.status IS NULL OR b.status <> 'confirmed')
+            ) AS settled
+            "#,
+        )
+        .bind(order_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.get("settled"))
+    }
+
+    pub async fn mark_liquidation_penalty_submitted(&self, liquidation_id: Uuid, tx_hash: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE liquidation_penalties
+            SET status = 'submitted', tx_hash = $2, error = NULL, attempts = attempts + 1, updated_at = NOW()
+            WHERE liquidation_id = $1 AND status = 'queued'
+            "#,
+        )
+        .bind(liquidation_id)
+        .bind(tx_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed attempt and queue the penalty again for a retry.
+    pub async fn requeue_liquidation_penalty(&self, liquidation_id: Uuid, error: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE liquidation_penalties
+            SET status = 'queued', tx_hash = NULL, error = $2,
+                attempts = attempts + CASE WHEN status = 'queued' THEN 1 ELSE 0 END, updated_at = NOW()
+            WHERE liquidation_id = $1 AND status IN ('queued', 'submitted')
+            "#,
+        )
+        .bind(liquidation_id)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark the penalty charged; false if it already was, so it is credited only once.
+    pub async fn charge_liquidation_penalty(&self, liquidation_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE liquidation_penalties
+            SET status = 'charged', error = NULL, updated_at = NOW()
+            WHERE liquidation_id = $1 AND status IN ('queued', 'submitted')
+            "#,
+        )
+        .bind(liquidation_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn fail_liquidation_penalty(&self, liquidation_id: Uuid, error: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE liquidation_penalties
+            SET status = 'failed', error = $2, updated_at = NOW()
+            WHERE liquidation_id = $1 AND status IN ('queued', 'submitted')
+            "#,
+        )
+        .bind(liquidation_id)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn insert_insurance_fund_entry(&self, entry: &InsuranceFundEntry) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO insurance_fund (
+                id, kind, market_id, amount, balance, reference_id, created_at
+            ) VALUES ($1, $2, $3, CAST($4 AS numeric), CAST($5 AS numeric), $6, $7)
+            "#,
+        )
+        .bind(entry.id)
+        .bind(&entry.kind)
+        .bind(entry.market_id as i64)
+        .bind(Self::decimal_to_string(&entry.amount))
+        .bind(Self::decimal_to_string(&entry.balance))
+        .bind(entry.reference_id)
+        .bind(entry.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Inserted insurance fund entry {:?} {}", entry.kind, entry.amount);
+        Ok(())
+    }
+
+    /// Balance after the most recent insurance fund entry.
+    pub async fn get_insurance_fund_balance(&self) -> Result<Decimal> {
+        let row = sqlx::query(
+            "SELECT CAST(balance AS TEXT) as balance FROM insurance_fund ORDER BY created_at DESC LIMIT 1",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row
+            .map(|row| Self::string_to_decimal(row.get::<&str, _>("balance")))
+            .unwrap_or_default())
+    }
+
+    pub async fn get_insurance_fund_entries(&self, limit: i64, offset: i64) -> Result<Vec<InsuranceFundEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, kind, market_id, CAST(amount AS TEXT) as amount,
+                   CAST(balance AS TEXT) as balance, reference_id, created_at
+            FROM insurance_fund
+            ORDER BY created_at DESC
+            LIMIT $1 OFFSET $2
+            "#,
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let entries = rows.into_iter().map(|row| InsuranceFundEntry {
+            id: row.get("id"),
+            kind: row.get("kind"),
+            market_id: row.get::<i64, _>("market_id") as u64,
+            amount: Self::string_to_decimal(row.get::<&str, _>("amount")),
+            balance: Self::string_to_decimal(row.get::<&str, _>("balance")),
+            reference_id: row.get("reference_id"),
+            created_at: row.get("created_at"),
+        }).collect();
+
+        Ok(entries)
+    }
+
+    pub async fn insert_adl_event(&self, event: &AdlEvent) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO adl_events (
+                id, liquidation_id, trade_id, market_id, liquidated_address,
+                counterparty_address, side, size, bankruptcy_price, created_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, CAST($8 AS numeric), CAST($9 AS numeric), $10)
+            "#,
+        )
+        .bind(event.id)
+        .bind(event.liquidation_id)
+        .bind(event.trade_id)
+        .bind(event.market_id as i64)
+        .bind(&event.liquidated_address)
+        .bind(&event.counterparty_address)
+        .bind(&event.side)
+        .bind(Self::decimal_to_string(&event.size))
+        .bind(Self::decimal_to_string(&event.bankruptcy_price))
+        .bind(event.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Inserted ADL event: {}", event.id);
+        Ok(())
+    }
+
+    pub async fn upsert_margin_setting(&self, setting: &MarginSetting) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO margin_settings (user_address, market_id, margin_mode, leverage, updated_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (user_address, market_id)
+            DO UPDATE SET margin_mode = EXCLUDED.margin_mode, leverage = EXCLUDED.leverage, updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(&setting.user_address)
+        .bind(setting.market_id as i64)
+        .bind(setting.margin_mode)
+        .bind(setting.leverage as i64)
+        .bind(setting.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Saved margin setting for {} in market {}", setting.user_address, setting.market_id);
+        Ok(())
+    }
+
+    pub async fn get_all_margin_settings(&self) -> Result<Vec<MarginSetting>> {
+        let rows = sqlx::query(
+            "SELECT user_address, market_id, margin_mode, leverage, updated_at FROM margin_settings",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let settings = rows.into_iter().map(|row| MarginSetting {
+            user_address: row.get("user_address"),
+            market_id: row.get::<i64, _>("market_id") as u64,
+            margin_mode: row.get("margin_mode"),
+            leverage: row.get::<i64, _>("leverage") as u64,
+            updated_at: row.get("updated_at"),
+        }).collect();
+
+        Ok(settings)
+    }
+
+    pub async fn upsert_collateral_balance(&self, balance: &CollateralBalance) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO collateral_balances (user_address, balance, synced_at)
+            VALUES ($1, CAST($2 AS numeric), $3)
+            ON CONFLICT (user_address)
+            DO UPDATE SET balance = EXCLUDED.balance, synced_at = EXCLUDED.synced_at
+            "#,
+        )
+        .bind(&balance.user_address)
+        .bind(Self::decimal_to_string(&balance.balance))
+        .bind(balance.synced_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_collateral_balances(&self) -> Result<Vec<CollateralBalance>> {
+        let rows = sqlx::query(
+            "SELECT user_address, CAST(balance AS TEXT) as balance, synced_at FROM collateral_balances",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let balances = rows.into_iter().map(|row| CollateralBalance {
+            user_address: row.get("user_address"),
+            balance: Self::string_to_decimal(row.get::<&str, _>("balance")),
+            synced_at: row.get("synced_at"),
+        }).collect();
+
+        Ok(balances)
+    }
+
+    pub async fn insert_freeze_request(&self, request: &FreezeRequest) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO freeze_requests (
+                order_id, user_address, market_id, side, order_type, size, price,
+                order_expires_at, required_collateral, amount, status, created_at, expires_at
+            ) VALUES ($1, $2, $3, $4, $5, CAST($6 AS numeric), CAST($7 AS numeric), $8, $9, $10, $11, $12, $13)
+            "#,
+        )
+        .bind(request.order_id)
+        .bind(&request.user_address)
+        .bind(request.market_id as i64)
+        .bind(&request.side)
+        .bind(&request.order_type)
+        .bind(Self::decimal_to_string(&request.size))
+        .bind(Self::decimal_to_string(&request.price))
+        .bind(request.order_expires_at)
+        .bind(request.required_collateral as i64)
+        .bind(request.amount as i64)
+        .bind(&request.status)
+        .bind(request.created_at)
+        .bind(request.expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_freeze_request(&self, order_id: Uuid) -> Result<Option<FreezeRequest>> {
+        let row = sqlx::query(
+            r#"
+            SELECT order_id, user_address, market_id, side, order_type,
+                   CAST(size AS TEXT) as size, CAST(price AS TEXT) as price,
+                   order_expires_at, required_collateral, amount, status,
+                   tx_hash, refund_tx_hash, created_at, expires_at
+            FROM freeze_requests
+            WHERE order_id = $1
+            "#,
+        )
+        .bind(order_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| FreezeRequest {
+            order_id: row.get("order_id"),
+            user_address: row.get("user_address"),
+            market_id: row.get::<i64, _>("market_id") as u64,
+            side: row.get("side"),
+            order_type: row.get("order_type"),
+            size: Self::string_to_decimal(row.get::<&str, _>("size")),
+            price: Self::string_to_decimal(row.get::<&str, _>("price")),
+            order_expires_at: row.get("order_expires_at"),
+            required_collateral: row.get::<i64, _>("required_collateral") as u64,
+            amount: row.get::<i64, _>("amount") as u64,
+            status: row.get("status"),
+            tx_hash: row.get("tx_hash"),
+            refund_tx_hash: row.get("refund_tx_hash"),
+            created_at: row.get("created_at"),
+            expires_at: row.get("expires_at"),
+        }))
+    }
+
+    /// Freeze requests whose deposit landed after they expired and is owed back.
+    pub async fn get_refund_pending_freeze_requests(&self) -> Result<Vec<FreezeRequest>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT order_id, user_address, market_id, side, order_type,
+                   CAST(size AS TEXT) as size, CAST(price AS TEXT) as price,
+                   order_expires_at, required_collateral, amount, status,
+                   tx_hash, refund_tx_hash, created_at, expires_at
+            FROM freeze_requests
+            WHERE status = 'refund_pending'
+            ORDER BY expires_at ASC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| FreezeRequest {
+            order_id: row.get("order_id"),
+            user_address: row.get("user_address"),
+            market_id: row.get::<i64, _>("market_id") as u64,
+            side: row.get("side"),
+            order_type: row.get("order_type"),
+            size: Self::string_to_decimal(row.get::<&str, _>("size")),
+            price: Self::string_to_decimal(row.get::<&str, _>("price")),
+            order_expires_at: row.get("order_expires_at"),
+            required_collateral: row.get::<i64, _>("required_collateral") as u64,
+            amount: row.get::<i64, _>("amount") as u64,
+            status: row.get("status"),
+            tx_hash: row.get("tx_hash"),
+            refund_tx_hash: row.get("refund_tx_hash"),
+            created_at: row.get("created_at"),
+            expires_at: row.get("expires_at"),
+        }).collect())
+    }
+
+    /// Move a pending, unexpired freeze request to confirmed. Returns false if
+    /// it has expired or was already confirmed.
+    pub async fn confirm_freeze_request(&self, order_id: Uuid, tx_hash: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE freeze_requests
+            SET status = 'confirmed', tx_hash = $2
+            WHERE order_id = $1 AND status = 'pending' AND expires_at > NOW()
+            "#,
+        )
+        .bind(order_id)
+        .bind(tx_hash.to_lowercase())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Expire pending freeze requests past their deadline, returning how many.
+    pub async fn expire_freeze_requests(&self) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            UPDATE freeze_requests
+            SET status = 'expired'
+            WHERE status = 'pending' AND expires_at <= NOW()
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Queue a refund for a deposit that arrived after its request expired.
+    pub async fn mark_freeze_refund_pending(&self, order_id: Uuid, tx_hash: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE freeze_requests
+            SET status = 'refund_pending', tx_hash = $2
+            WHERE order_id = $1 AND status IN ('pending', 'expired')
+            "#,
+        )
+        .bind(order_id)
+        .bind(tx_hash.to_lowercase())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn mark_freeze_refunded(&self, order_id: Uuid, refund_tx_hash: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE freeze_requests
+            SET status = 'refunded', refund_tx_hash = $2
+            WHERE order_id = $1 AND status = 'refund_pending'
+            "#,
+        )
+        .bind(order_id)
+        .bind(refund_tx_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record that a freeze transaction backs an order. Returns false when the
+    /// hash, or the order, has already been claimed.
+    pub async fn claim_freeze_transaction(&self, tx_hash: &str, request: &FreezeRequest) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO freeze_transactions (tx_hash, order_id, user_address, amount)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(tx_hash.to_lowercase())
+        .bind(request.order_id)
+        .bind(&request.user_address)
+        .bind(request.amount as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn insert_withdrawal(&self, withdrawal: &Withdrawal) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO withdrawals (id, user_address, amount, status, attempts, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(withdrawal.id)
+        .bind(&withdrawal.user_address)
+        .bind(withdrawal.amount as i64)
+        .bind(&withdrawal.status)
+        .bind(withdrawal.attempts as i32)
+        .bind(withdrawal.created_at)
+        .bind(withdrawal.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_withdrawal(&self, id: Uuid) -> Result<Option<Withdrawal>> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, user_address, amount, status, tx_hash, sequence_number, error, attempts, created_at, updated_at
+            FROM withdrawals
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| Withdrawal {
+            id: row.get("id"),
+            user_address: row.get("user_address"),
+            amount: row.get::<i64, _>("amount") as u64,
+            status: row.get("status"),
+            tx_hash: row.get("tx_hash"),
+            sequence_number: row.get::<Option<i64>, _>("sequence_number").map(|n| n as u64),
+            error: row.get("error"),
+            attempts: row.get::<i32, _>("attempts") as u32,
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        }))
+    }
+
+    pub async fn get_user_withdrawals(&self, user_address: &str, limit: i64) -> Result<Vec<Withdrawal>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, user_address, amount, status, tx_hash, sequence_number, error, attempts, created_at, updated_at
+            FROM withdrawals
+            WHERE user_address = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(user_address)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| Withdrawal {
+            id: row.get("id"),
+            user_address: row.get("user_address"),
+            amount: row.get::<i64, _>("amount") as u64,
+            status: row.get("status"),
+            tx_hash: row.get("tx_hash"),
+            sequence_number: row.get::<Option<i64>, _>("sequence_number").map(|n| n as u64),
+            error: row.get("error"),
+            attempts: row.get::<i32, _>("attempts") as u32,
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        }).collect())
+    }
+
+    /// Withdrawals still holding collateral: queued or submitted, oldest first.
+    pub async fn get_active_withdrawals(&self) -> Result<Vec<Withdrawal>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, user_address, amount, status, tx_hash, sequence_number, error, attempts, created_at, updated_at
+            FROM withdrawals
+            WHERE status IN ('queued', 'submitted')
+            ORDER BY created_at ASC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| Withdrawal {
+            id: row.get("id"),
+            user_address: row.get("user_address"),
+            amount: row.get::<i64, _>("amount") as u64,
+            status: row.get("status"),
+            tx_hash: row.get("tx_hash"),
+            sequence_number: row.get::<Option<i64>, _>("sequence_number").map(|n| n as u64),
+            error: row.get("error"),
+            attempts: row.get::<i32, _>("attempts") as u32,
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        }).collect())
+    }
+
+    /// Record a signed withdrawal transaction; done before it is submitted.
+    pub async fn mark_withdrawal_submitted(&self, id: Uuid, tx_hash: &str, sequence_number: u64) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE withdrawals
+            SET status = 'submitted', tx_hash = $2, sequence_number = $3, error = NULL,
+                attempts = attempts + 1, updated_at = NOW()
+            WHERE id = $1 AND status = 'queued'
+            "#,
+        )
+        .bind(id)
+        .bind(tx_hash)
+        .bind(sequence_number as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Queue a submitted withdrawal again once its transaction can no longer commit.
+    pub async fn requeue_withdrawal(&self, id: Uuid, error: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE withdrawals
+            SET status = 'queued', error = $2, updated_at = NOW()
+            WHERE id = $1 AND status = 'submitted'
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed submission attempt, leaving the withdrawal queued for a retry.
+    pub async fn record_withdrawal_attempt(&self, id: Uuid, error: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE withdrawals
+            SET error = $2, attempts = attempts + 1, updated_at = NOW()
+            WHERE id = $1 AND status = 'queued'
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn complete_withdrawal(&self, id: Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE withdrawals
+            SET status = 'completed', error = NULL, updated_at = NOW()
+            WHERE id = $1 AND status = 'submitted'
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn fail_withdrawal(&self, id: Uuid, error: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE withdrawals
+            SET status = 'failed', error = $2, updated_at = NOW()
+            WHERE id = $1 AND status IN ('queued', 'submitted')
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn upsert_settlement_retry(&self, retry: &SettlementRetry) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO settlement_retries (
+                trade_id, retry_group, batch_id, attempts, next_attempt_at, last_error, dead_lettered_at, updated_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (trade_id)
+            DO UPDATE SET retry_group = EXCLUDED.retry_group, batch_id = EXCLUDED.batch_id, attempts = EXCLUDED.attempts,
+                          next_attempt_at = EXCLUDED.next_attempt_at, last_error = EXCLUDED.last_error,
+                          dead_lettered_at = EXCLUDED.dead_lettered_at, updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(retry.trade_id)
+        .bind(retry.retry_group)
+        .bind(retry.batch_id)
+        .bind(retry.attempts as i32)
+        .bind(retry.next_attempt_at)
+        .bind(&retry.last_error)
+        .bind(retry.dead_lettered_at)
+        .bind(retry.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_settlement_retries(&self, trade_ids: &[Uuid]) -> Result<Vec<SettlementRetry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT trade_id, retry_group, batch_id, attempts, next_attempt_at, last_error, dead_lettered_at, updated_at
+            FROM settlement_retries
+            WHERE trade_id = ANY($1)
+            "#,
+        )
+        .bind(trade_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let retries = rows.into_iter().map(|row| SettlementRetry {
+            trade_id: row.get("trade_id"),
+            retry_group: row.get("retry_group"),
+            batch_id: row.get("batch_id"),
+            attempts: row.get::<i32, _>("attempts") as u32,
+            next_attempt_at: row.get("next_attempt_at"),
+            last_error: row.get("last_error"),
+            dead_lettered_at: row.get("dead_lettered_at"),
+            updated_at: row.get("updated_at"),
+        }).collect();
+
+        Ok(retries)
+    }
+
+    pub async fn get_dead_lettered_trades(&self, limit: i64, offset: i64) -> Result<Vec<DeadLetteredTrade>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT t.id, t.market_id, t.taker_order_id, t.maker_order_id,
+                   t.taker_address, t.maker_address, CAST(t.size AS TEXT) as size,
+                   CAST(t.price AS TEXT) as price, t.side, t.created_at, t.settlement_batch_id,
+                   r.attempts, r.last_error, r.dead_lettered_at
+            FROM settlement_retries r
+            JOIN trades t ON t.id = r.trade_id
+            WHERE r.dead_lettered_at IS NOT NULL
+            ORDER BY r.dead_lettered_at DESC
+            LIMIT $1 OFFSET $2
+            "#,
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let trades = rows.into_iter().map(|row| DeadLetteredTrade {
+            trade: Trade {
+                id: row.get("id"),
+                market_id: row.get::<i64, _>("market_id") as u64,
+                taker_order_id: row.get("taker_order_id"),
+                maker_order_id: row.get("maker_order_id"),
+                taker_address: row.get("taker_address"),
+                maker_address: row.get("maker_address"),
+                size: Self::string_to_decimal(row.get::<&str, _>("size")),
+                price: Self::string_to_decimal(row.get::<&str, _>("price")),
+                side: row.get("side"),
+                created_at: row.get("created_at"),
+                settlement_batch_id: row.get("settlement_batch_id"),
+            },
+            attempts: row.get::<i32, _>("attempts") as u32,
+            last_error: row.get("last_error"),
+            dead_lettered_at: row.get("dead_lettered_at"),
+        }).collect();
+
+        Ok(trades)
+    }
+
+    /// Clear the retry state of dead-lettered trades so they settle as fresh fills.
+    /// Returns the number of trades requeued.
+    pub async fn requeue_dead_lettered_trades(&self, trade_ids: &[Uuid]) -> Result<u64> {
+        let result = sqlx::query(
+            "DELETE FROM settlement_retries WHERE dead_lettered_at IS NOT NULL AND trade_id = ANY($1)",
+        )
+        .bind(trade_ids)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn insert_settlement_hold(&self, hold: &SettlementHold) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO settlement_holds (
+                trade_id, reference_price, min_price, max_price, held_at, released_at
+            ) VALUES ($1, CAST($2 AS numeric), CAST($3 AS numeric), CAST($4 AS numeric), $5, $6)
+            ON CONFLICT (trade_id) DO NOTHING
+            "#,
+        )
+        .bind(hold.trade_id)
+        .bind(Self::decimal_to_string(&hold.reference_price))
+        .bind(Self::decimal_to_string(&hold.min_price))
+        .bind(Self::decimal_to_string(&hold.max_price))
+        .bind(hold.held_at)
+        .bind(hold.released_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_settlement_holds(&self, trade_ids: &[Uuid]) -> Result<Vec<SettlementHold>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT trade_id, CAST(reference_price AS TEXT) as reference_price,
+                   CAST(min_price AS TEXT) as min_price, CAST(max_price AS TEXT) as max_price,
+                   held_at, released_at
+            FROM settlement_holds
+            WHERE trade_id = ANY($1)
+            "#,
+        )
+        .bind(trade_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let holds = rows.into_iter().map(|row| SettlementHold {
+            trade_id: row.get("trade_id"),
+            reference_price: Self::string_to_decimal(row.get::<&str, _>("reference_price")),
+            min_price: Self::string_to_decimal(row.get::<&str, _>("min_price")),
+            max_price: Self::string_to_decimal(row.get::<&str, _>("max_price")),
+            held_at: row.get("held_at"),
+            released_at: row.get("released_at"),
+        }).collect();
+
+        Ok(holds)
+    }
+
+    pub async fn get_held_trades(&self, limit: i64, offset: i64) -> Result<Vec<HeldTrade>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT t.id, t.market_id, t.taker_order_id, t.maker_order_id,
+                   t.taker_address, t.maker_address, CAST(t.size AS TEXT) as size,
+                   CAST(t.price AS TEXT) as price, t.side, t.created_at, t.settlement_batch_id,
+                   CAST(h.reference_price AS TEXT) as reference_price,
+                   CAST(h.min_price AS TEXT) as min_price, CAST(h.max_price AS TEXT) as max_price,
+                   h.held_at
+            FROM settlement_holds h
+            JOIN trades t ON t.id = h.trade_id
+            WHERE h.released_at IS NULL
+            ORDER BY h.held_at DESC
+            LIMIT $1 OFFSET $2
+            "#,
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let trades = rows.into_iter().map(|row| HeldTrade {
+            trade: Trade {
+                id: row.get("id"),
+                market_id: row.get::<i64, _>("market_id") as u64,
+                taker_order_id: row.get("taker_order_id"),
+                maker_order_id: row.get("maker_order_id"),
+                taker_address: row.get("taker_address"),
+                maker_address: row.get("maker_address"),
+                size: Self::string_to_decimal(row.get::<&str, _>("size")),
+                price: Self::string_to_decimal(row.get::<&str, _>("price")),
+                side: row.get("side"),
+                created_at: row.get("created_at"),
+                settlement_batch_id: row.get("settlement_batch_id"),
+            },
+            reference_price: Self::string_to_decimal(row.get::<&str, _>("reference_price")),
+            min_price: Self::string_to_decimal(row.get::<&str, _>("min_price")),
+            max_price: Self::string_to_decimal(row.get::<&str, _>("max_price")),
+            held_at: row.get("held_at"),
+        }).collect();
+
+        Ok(trades)
+    }
+
+    /// Approve held trades for settlement at their own price.
+    /// Returns the number of trades released.
+    pub async fn release_held_trades(&self, trade_ids: &[Uuid]) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE settlement_holds SET released_at = NOW() WHERE released_at IS NULL AND trade_id = ANY($1)",
+        )
+        .bind(trade_ids)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Drop the retry state of trades, including dead-lettering, so they settle as fresh fills.
+    /// Returns the number of trades cleared.
+    pub async fn clear_settlement_retries(&self, trade_ids: &[Uuid]) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM settlement_retries WHERE trade_id = ANY($1)")
+            .bind(trade_ids)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn insert_net_deltas(&self, deltas: &[NetDelta]) -> Result<()> {
+        for delta in deltas {
+            sqlx::query(
+                r#"
+                INSERT INTO settlement_net_deltas (
+                    batch_id, user_address, market_id, size_delta, price, min_price, max_price,
+                    entry_notional, quote_delta, taker_fee, maker_fee, trade_ids
+                ) VALUES ($1, $2, $3, CAST($4 AS numeric), CAST($5 AS numeric), CAST($6 AS numeric),
+                          CAST($7 AS numeric), CAST($8 AS numeric), CAST($9 AS numeric),
+                          CAST($10 AS numeric), CAST($11 AS numeric), $12)
+                ON CONFLICT (batch_id, user_address, market_id) DO NOTHING
+                "#,
+            )
+            .bind(delta.batch_id)
+            .bind(&delta.user_address)
+            .bind(delta.market_id as i64)
+            .bind(Self::decimal_to_string(&delta.size_delta))
+            .bind(Self::decimal_to_string(&delta.price))
+            .bind(Self::decimal_to_string(&delta.min_price))
+            .bind(Self::decimal_to_string(&delta.max_price))
+            .bind(Self::decimal_to_string(&delta.entry_notional))
+            .bind(Self::decimal_to_string(&delta.quote_delta))
+            .bind(Self::decimal_to_string(&delta.taker_fee))
+            .bind(Self::decimal_to_string(&delta.maker_fee))
+            .bind(&delta.trade_ids)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_net_deltas(&self, batch_id: Uuid) -> Result<Vec<NetDelta>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT batch_id, user_address, market_id, CAST(size_delta AS TEXT) as size_delta,
+                   CAST(price AS TEXT) as price, CAST(min_price AS TEXT) as min_price,
+                   CAST(max_price AS TEXT) as max_price, CAST(entry_notional AS TEXT) as entry_notional,
+                   CAST(quote_delta AS TEXT) as quote_delta,
+                   CAST(taker_fee AS TEXT) as taker_fee, CAST(maker_fee AS TEXT) as maker_fee, trade_ids
+            FROM settlement_net_deltas
+            WHERE batch_id = $1
+            ORDER BY market_id, user_address
+            "#,
+        )
+        .bind(batch_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let deltas = rows.into_iter().map(|row| NetDelta {
+            batch_id: row.get("batch_id"),
+            user_address: row.get("user_address"),
+            market_id: row.get::<i64, _>("market_id") as u64,
+            size_delta: Self::string_to_decimal(row.get::<&str, _>("size_delta")),
+            price: Self::string_to_decimal(row.get::<&str, _>("price")),
+            min_price: Self::string_to_decimal(row.get::<&str, _>("min_price")),
+            max_price: Self::string_to_decimal(row.get::<&str, _>("max_price")),
+            entry_notional: Self::string_to_decimal(row.get::<&str, _>("entry_notional")),
+            quote_delta: Self::string_to_decimal(row.get::<&str, _>("quote_delta")),
+            taker_fee: Self::string_to_decimal(row.get::<&str, _>("taker_fee")),
+            maker_fee: Self::string_to_decimal(row.get::<&str, _>("maker_fee")),
+            trade_ids: row.get("trade_ids"),
+        }).collect();
+
+        Ok(deltas)
+    }
 }
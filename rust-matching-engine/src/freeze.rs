@@ -0,0 +1,244 @@
+use anyhow::{bail, Result};
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+use crate::{
+    chain::{normalize_address, ChainClient, ChainError, CommittedTransaction},
+    collateral::CollateralLedger,
+    config::{AptosConfig, FreezeConfig},
+    database::Database,
+    models::FreezeRequest,
+};
+
+/// Expires freeze requests nobody confirmed in time and returns deposits
+/// that were confirmed after their request had expired.
+///
+/// A late deposit is only seen when the user confirms it, so `confirm_order`
+/// queues the refund and this loop submits it, retrying on the next sweep
+/// if the withdrawal fails.
+pub struct FreezeSweeper {
+    chain_client: Arc<dyn ChainClient>,
+    database: Arc<Database>,
+    collateral_ledger: Arc<CollateralLedger>,
+    config: FreezeConfig,
+}
+
+impl FreezeSweeper {
+    pub async fn new(
+        chain_client: Arc<dyn ChainClient>,
+        database: Arc<Database>,
+        collateral_ledger: Arc<CollateralLedger>,
+        config: FreezeConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            chain_client,
+            database,
+            collateral_ledger,
+            config,
+        })
+    }
+
+    pub async fn start_sweep_loop(&self) -> Result<()> {
+        info!("Starting freeze request sweeper");
+        let mut interval = interval(Duration::from_secs(self.config.sweep_interval_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+
+            match self.database.expire_freeze_requests().await {
+                Ok(0) => {}
+                Ok(expired) => info!("Expired {} unconfirmed freeze requests", expired),
+                Err(e) => warn!("Failed to expire freeze requests: {}", e),
+            }
+
+            if let Err(e) = self.refund_late_deposits().await {
+                warn!("Failed to refund late freeze deposits: {}", e);
+            }
+        }
+    }
+
+    async fn refund_late_deposits(&self) -> Result<()> {
+        for request in self.database.get_refund_pending_freeze_requests().await? {
+            let tx_hash = match self.chain_client.unfreeze_user_funds(&request.user_address, request.amount).await {
+                Ok(tx_hash) => tx_hash,
+                Err(e) => {
+                    warn!("Refund of {} to {} for order {} failed, will retry: {}",
+                        request.amount, request.user_address, request.order_id, e);
+                    continue;
+                }
+            };
+
+            // The withdrawal is on its way; a failure here would refund twice, so shout about it
+            if let Err(e) = self.database.mark_freeze_refunded(request.order_id, &tx_hash).await {
+                error!("Refund {} for order {} submitted but not recorded: {}", tx_hash, request.order_id, e);
+                continue;
+            }
+            info!("Refunded late freeze deposit of {} to {} for order {}: {}",
+                request.amount, request.user_address, request.order_id, tx_hash);
+
+            if let Err(e) = self.collateral_ledger.sync_user(&request.user_address).await {
+                warn!("Failed to sync collateral for {}: {}", request.user_address, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wait up to `max_attempts` seconds for a transaction to be committed.
+pub async fn wait_for_committed_transaction(
+    chain_client: &dyn ChainClient,
+    tx_hash: &str,
+    max_attempts: u32,
+) -> Result<Option<CommittedTransaction>> {
+    for _ in 0..max_attempts {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        if let Some(tx) = chain_client.get_transaction(tx_hash).await? {
+            return Ok(Some(tx));
+        }
+    }
+    Ok(None)
+}
+
+/// Check a freeze transaction against the freeze quoted for its order: a
+/// successful `vault_coin::deposit` of the configured coin, sent by the
+/// order's user, into the admin vault, for exactly the quoted amount.
+pub fn verify_freeze_transaction(
+    tx: &CommittedTransaction,
+    request: &FreezeRequest,
+    config: &AptosConfig,
+) -> Result<()> {
+    if !tx.success {
+        return Err(anyhow::Error::new(ChainError::from_vm_status(&tx.vm_status, &config.contract_address))
+            .context(format!("freeze transaction {} failed", tx.hash)));
+    }
+
+    if normalize_address(&tx.sender) != normalize_address(&request.user_address) {
+        bail!("transaction {} was sent by {}, not {}", tx.hash, tx.sender, request.user_address);
+    }
+
+    let expected_function = format!("{}::vault_coin::deposit", config.contract_address);
+    if !same_qualified_name(&tx.function, &expected_function) {
+        bail!("transaction {} calls {}, not {}", tx.hash, tx.function, expected_function);
+    }
+
+    if tx.type_arguments.len() != 1 || !same_qualified_name(&tx.type_arguments[0], &config.usdc_token_type) {
+        bail!("transaction {} deposits {:?}, not {}", tx.hash, tx.type_arguments, config.usdc_token_type);
+    }
+
+    let vault = tx.arguments.first().and_then(|a| a.as_str()).unwrap_or_default();
+    if normalize_address(vault) != normalize_address(&config.admin_address) {
+        bail!("transaction {} deposits into {}, not the admin vault", tx.hash, vault);
+    }
+
+    let amount = tx.arguments.get(1)
+        .and_then(|a| a.as_str())
+        .and_then(|a| a.parse::<u128>().ok());
+    if amount != Some(request.amount as u128) {
+        bail!("transaction {} deposits {:?}, not the quoted {}", tx.hash, amount, request.amount);
+    }
+
+    Ok(())
+}
+
+/// Compare `<address>::<module>::<name>` identifiers, ignoring how the address is written.
+fn same_qualified_name(a: &str, b: &str) -> bool {
+    match (a.split_once("::"), b.split_once("::")) {
+        (Some((addr_a, rest_a)), Some((addr_b, rest_b))) => {
+            normalize_address(addr_a) == normalize_address(addr_b) && rest_a == rest_b
+        }
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock_chain::{testing::{mock_chain, test_config}, MockChain},
+        models::{FreezeRequestStatus, OrderSide, OrderType},
+    };
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+
+    const USER: &str = "0xa11ce";
+
+    /// A mock chain that commits instantly and aborts every `fail_every`-th transaction.
+    async fn chain(fail_every: u64) -> (MockChain, AptosConfig) {
+        let mut config = test_config().aptos;
+        config.mock_chain.fail_every = fail_every;
+        let (chain, _) = mock_chain(&config).await;
+        (chain, config)
+    }
+
+    fn request(amount: u64) -> FreezeRequest {
+        let now = chrono::Utc::now();
+        FreezeRequest {
+            order_id: Uuid::new_v4(),
+            user_address: USER.to_string(),
+            market_id: 1,
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            size: Decimal::ONE,
+            price: Decimal::from(100),
+            order_expires_at: None,
+            required_collateral: amount,
+            amount,
+            status: FreezeRequestStatus::Pending,
+            tx_hash: None,
+            refund_tx_hash: None,
+            created_at: now,
+            expires_at: now + chrono::Duration::minutes(5),
+        }
+    }
+
+    /// Submit the user's freeze transaction and wait for it to commit.
+    async fn freeze(chain: &MockChain, user: &str, amount: u64) -> CommittedTransaction {
+        let tx_hash = chain.freeze_user_funds(user, amount, 1).await.unwrap();
+        wait_for_committed_transaction(chain, &tx_hash, 2).await.unwrap()
+            .expect("freeze transaction was not committed")
+    }
+
+    #[tokio::test]
+    async fn confirms_a_matching_freeze_transaction() {
+        let (chain, config) = chain(0).await;
+        let request = request(5_000_000);
+
+        let tx = freeze(&chain, USER, request.amount).await;
+
+        verify_freeze_transaction(&tx, &request, &config).unwrap();
+        assert_eq!(chain.get_user_collateral(USER).await.unwrap(), request.amount);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_freeze_of_another_amount_or_sender() {
+        let (chain, config) = chain(0).await;
+        let request = request(5_000_000);
+
+        let short = freeze(&chain, USER, request.amount - 1).await;
+        assert!(verify_freeze_transaction(&short, &request, &config).is_err());
+
+        let other_user = freeze(&chain, "0xb0b", request.amount).await;
+        assert!(verify_freeze_transaction(&other_user, &request, &config).is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_a_failed_freeze_and_accepts_a_retry() {
+        // Every second transaction aborts: the first freeze fails, its retry lands
+        let (chain, config) = chain(2).await;
+        let request = request(5_000_000);
+        freeze(&chain, "0xb0b", 1).await;
+
+        let failed = freeze(&chain, USER, request.amount).await;
+        let error = verify_freeze_transaction(&failed, &request, &config).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ChainError>(),
+            Some(ChainError::Other(status)) if status == "Injected mock chain failure"
+        ));
+        assert_eq!(chain.get_user_collateral(USER).await.unwrap(), 0);
+
+        let retried = freeze(&chain, USER, request.amount).await;
+        verify_freeze_transaction(&retried, &request, &config).unwrap();
+        assert_eq!(chain.get_user_collateral(USER).await.unwrap(), request.amount);
+    }
+}
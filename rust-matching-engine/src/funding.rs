@@ -0,0 +1,338 @@
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+use tokio::time::interval;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    chain::{ChainClient, TransactionStatus},
+    config::FundingConfig,
+    database::Database,
+    models::{FundingRate, FundingStatus},
+    pricing::{MarketPrice, PriceService},
+};
+
+/// Premium samples collected for one market during the current epoch.
+#[derive(Debug, Default)]
+struct PremiumSamples {
+    premiums: Vec<Decimal>,
+    last_price: Option<MarketPrice>,
+}
+
+/// Samples the mark/index premium each interval and, at every funding epoch,
+/// publishes the clamped funding rate on-chain and records it in Postgres.
+/// A rate counts as published only once its transaction has committed; rates
+/// that fail are resubmitted on later ticks until `max_publish_attempts` is used up.
+pub struct FundingService {
+    chain_client: Arc<dyn ChainClient>,
+    database: Arc<Database>,
+    price_service: Arc<PriceService>,
+    market_ids: Vec<u64>,
+    config: FundingConfig,
+    samples: Mutex<HashMap<u64, PremiumSamples>>,
+}
+
+impl FundingService {
+    pub async fn new(
+        chain_client: Arc<dyn ChainClient>,
+        database: Arc<Database>,
+        price_service: Arc<PriceService>,
+        market_ids: Vec<u64>,
+        config: FundingConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            chain_client,
+            database,
+            price_service,
+            market_ids,
+            config,
+            samples: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub async fn start_funding_loop(&self) -> Result<()> {
+        info!("Starting funding service loop");
+        let mut interval = interval(Duration::from_secs(self.config.sample_interval_secs.max(1)));
+        let mut next_funding_time = self.next_funding_time(Utc::now());
+
+        loop {
+            interval.tick().await;
+
+            self.sample_premiums().await;
+            if let Err(e) = self.track_unpublished_rates().await {
+                error!("Funding rate publishing error: {}", e);
+            }
+
+            let now = Utc::now();
+            if now >= next_funding_time {
+                if let Err(e) = self.settle_epoch(next_funding_time).await {
+                    error!("Funding epoch processing error: {}", e);
+                    // Continue running despite errors
+                }
+                next_funding_time = self.next_funding_time(now);
+            }
+        }
+    }
+
+    /// Next epoch boundary, aligned to multiples of the funding interval.
+    fn next_funding_time(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let interval_secs = self.config.interval_secs.max(1) as i64;
+        let next = (now.timestamp() / interval_secs + 1) * interval_secs;
+        Utc.timestamp_opt(next, 0).single().unwrap_or(now)
+    }
+
+    async fn sample_premiums(&self) {
+        let mut samples = self.samples.lock().await;
+
+        for market_id in &self.market_ids {
+            let Some(price) = self.price_service.get_price(*market_id).await else {
+                debug!("No mark price yet for market {}, skipping premium sample", market_id);
+                continue;
+            };
+
+            let premium = (price.mark_price - price.index_price) / price.index_price;
+            let entry = samples.entry(*market_id).or_default();
+            entry.premiums.push(premium);
+            entry.last_price = Some(price);
+        }
+    }
+
+    async fn settle_epoch(&self, funding_time: DateTime<Utc>) -> Result<()> {
+        let epoch_samples: HashMap<u64, PremiumSamples> = std::mem::take(&mut *self.samples.lock().await);
+
+        for (market_id, samples) in epoch_samples {
+            let Some(last_price) = samples.last_price else {
+                continue;
+            };
+            if samples.premiums.is_empty() {
+                continue;
+            }
+
+            let premium = samples.premiums.iter().sum::<Decimal>()
+                / Decimal::from(samples.premiums.len());
+            let rate = calculate_funding_rate(&self.config, premium);
+
+            let mut funding_rate = FundingRate {
+                id: Uuid::new_v4(),
+                market_id,
+                rate,
+                premium,
+                mark_price: last_price.mark_price,
+                index_price: last_price.index_price,
+                funding_time,
+                transaction_hash: None,
+                status: FundingStatus::Failed,
+                attempts: 0,
+                error: None,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            };
+            // Record the rate even if publishing fails so history stays complete
+            publish(self.chain_client.as_ref(), &mut funding_rate).await;
+            self.database.insert_funding_rate(&funding_rate).await?;
+
+            info!("Funding rate for market {} at {}: {} (premium {}, {:?})",
+                market_id, funding_time, rate, premium, funding_rate.status);
+        }
+
+        Ok(())
+    }
+
+    /// Confirm submitted rates and resubmit failed ones.
+    async fn track_unpublished_rates(&self) -> Result<()> {
+        let unpublished = self.database.get_unpublished_funding_rates(self.config.max_publish_attempts).await?;
+        let timeout = chrono::Duration::seconds(self.config.confirmation_timeout_secs as i64);
+
+        for mut funding_rate in unpublished {
+            match funding_rate.status {
+                FundingStatus::Submitted => {
+                    if let Err(e) = confirm(self.chain_client.as_ref(), &mut funding_rate, timeout).await {
+                        warn!("Failed to check funding rate {}: {}", funding_rate.id, e);
+                        continue;
+                    }
+                }
+                _ => publish(self.chain_client.as_ref(), &mut funding_rate).await,
+            }
+            self.database.update_funding_rate(&funding_rate).await?;
+
+            if funding_rate.status == FundingStatus::Failed && funding_rate.attempts >= self.config.max_publish_attempts {
+                error!("Giving up on funding rate for market {} at {} after {} attempts",
+                    funding_rate.market_id, funding_rate.funding_time, funding_rate.attempts);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Submit the rate on-chain once; it stays submitted until `confirm` sees it commit.
+async fn publish(chain_client: &dyn ChainClient, funding_rate: &mut FundingRate) {
+    funding_rate.attempts += 1;
+    funding_rate.updated_at = Utc::now();
+    match chain_client.submit_funding_rate(funding_rate.market_id, funding_rate.rate).await {
+        Ok(tx_hash) => {
+            funding_rate.transaction_hash = Some(tx_hash);
+            funding_rate.status = FundingStatus::Submitted;
+            funding_rate.error = None;
+        }
+        Err(e) => {
+            warn!("Failed to publish funding rate for market {} (attempt {}): {}",
+                funding_rate.market_id, funding_rate.attempts, e);
+            funding_rate.status = FundingStatus::Failed;
+            funding_rate.error = Some(e.to_string());
+        }
+    }
+}
+
+/// Move a submitted rate on by its transaction's outcome. One still pending
+/// after `timeout` has expired and is failed, so it is submitted again.
+async fn confirm(chain_client: &dyn ChainClient, funding_rate: &mut FundingRate, timeout: chrono::Duration) -> Result<()> {
+    let Some(tx_hash) = funding_rate.transaction_hash.clone() else {
+        funding_rate.status = FundingStatus::Failed;
+        funding_rate.error = Some("submitted without a transaction hash".to_string());
+        funding_rate.updated_at = Utc::now();
+        return Ok(());
+    };
+
+    match chain_client.check_transaction_status(&tx_hash).await? {
+        TransactionStatus::Success { .. } => {
+            funding_rate.status = FundingStatus::Published;
+            info!("Funding rate for market {} at {} published: {}",
+                funding_rate.market_id, funding_rate.funding_time, tx_hash);
+        }
+        TransactionStatus::Failed { vm_status, .. } => {
+            let error = chain_client.decode_vm_status(&vm_status);
+            warn!("Funding rate transaction {} for market {} failed: {}", tx_hash, funding_rate.market_id, error);
+            funding_rate.status = FundingStatus::Failed;
+            funding_rate.error = Some(error.to_string());
+        }
+        TransactionStatus::Pending => {
+            if Utc::now() - funding_rate.updated_at <= timeout {
+                return Ok(());
+            }
+            funding_rate.status = FundingStatus::Failed;
+            funding_rate.error = Some(format!("transaction {} was not committed in time", tx_hash));
+        }
+    }
+    funding_rate.updated_at = Utc::now();
+    Ok(())
+}
+
+/// rate = clamp(premium + clamp(interest - premium, ±damper), ±max_rate)
+fn calculate_funding_rate(config: &FundingConfig, premium: Decimal) -> Decimal {
+    let interest = Decimal::from_f64(config.interest_rate).unwrap_or_default();
+    let damper = Decimal::from_f64(config.premium_damper).unwrap_or_default();
+    let max_rate = Decimal::from_f64(config.max_rate).unwrap_or_default();
+
+    let rate = premium + (interest - premium).clamp(-damper, damper);
+    rate.clamp(-max_rate, max_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_chain::{testing::{mock_chain, test_config}, MockChain};
+
+    fn timeout() -> chrono::Duration {
+        chrono::Duration::seconds(60)
+    }
+
+    /// A mock chain that commits after `confirmation_delay_ms` and aborts every `fail_every`-th transaction.
+    async fn chain(confirmation_delay_ms: u64, fail_every: u64) -> MockChain {
+        let mut config = test_config().aptos;
+        config.mock_chain.confirmation_delay_ms = confirmation_delay_ms;
+        config.mock_chain.fail_every = fail_every;
+        mock_chain(&config).await.0
+    }
+
+    fn funding_rate(rate: Decimal) -> FundingRate {
+        FundingRate {
+            id: Uuid::new_v4(),
+            market_id: 1,
+            rate,
+            premium: rate,
+            mark_price: Decimal::from(100),
+            index_price: Decimal::from(100),
+            funding_time: Utc::now(),
+            transaction_hash: None,
+            status: FundingStatus::Failed,
+            attempts: 0,
+            error: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_funding_rate_clamping() {
+        let config = FundingConfig::default();
+
+        // Within the damper the rate settles on the interest rate
+        assert_eq!(calculate_funding_rate(&config, Decimal::new(3, 4)), Decimal::new(1, 4));
+        // Beyond it the premium is only pulled back by the damper
+        assert_eq!(calculate_funding_rate(&config, Decimal::new(2, 3)), Decimal::new(15, 4));
+        assert_eq!(calculate_funding_rate(&config, Decimal::new(-2, 3)), Decimal::new(-15, 4));
+        // And the result never exceeds the maximum rate
+        assert_eq!(calculate_funding_rate(&config, Decimal::new(5, 2)), Decimal::new(75, 4));
+        assert_eq!(calculate_funding_rate(&config, Decimal::new(-5, 2)), Decimal::new(-75, 4));
+    }
+
+    #[tokio::test]
+    async fn test_published_once_committed() {
+        let chain = chain(0, 0).await;
+        let mut rate = funding_rate(Decimal::new(1, 4));
+
+        publish(&chain, &mut rate).await;
+        assert_eq!(rate.status, FundingStatus::Submitted);
+        assert_eq!(rate.attempts, 1);
+        assert!(rate.transaction_hash.is_some());
+
+        confirm(&chain, &mut rate, timeout()).await.unwrap();
+        assert_eq!(rate.status, FundingStatus::Published);
+        assert!(rate.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_transaction_is_resubmitted() {
+        // The second transaction aborts
+        let chain = chain(0, 2).await;
+        let mut first = funding_rate(Decimal::new(1, 4));
+        publish(&chain, &mut first).await;
+        confirm(&chain, &mut first, timeout()).await.unwrap();
+        assert_eq!(first.status, FundingStatus::Published);
+
+        let mut rate = funding_rate(Decimal::new(2, 4));
+        publish(&chain, &mut rate).await;
+        let failed_hash = rate.transaction_hash.clone();
+        confirm(&chain, &mut rate, timeout()).await.unwrap();
+        assert_eq!(rate.status, FundingStatus::Failed);
+        assert!(rate.error.is_some());
+
+        publish(&chain, &mut rate).await;
+        assert_ne!(rate.transaction_hash, failed_hash);
+        confirm(&chain, &mut rate, timeout()).await.unwrap();
+        assert_eq!(rate.status, FundingStatus::Published);
+        assert_eq!(rate.attempts, 2);
+        assert!(rate.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_uncommitted_transaction_times_out() {
+        let chain = chain(60_000, 0).await;
+        let mut rate = funding_rate(Decimal::new(1, 4));
+        publish(&chain, &mut rate).await;
+
+        // Still within the timeout: left submitted
+        confirm(&chain, &mut rate, timeout()).await.unwrap();
+        assert_eq!(rate.status, FundingStatus::Submitted);
+
+        rate.updated_at -= timeout() + chrono::Duration::seconds(1);
+        confirm(&chain, &mut rate, timeout()).await.unwrap();
+        assert_eq!(rate.status, FundingStatus::Failed);
+        assert!(rate.error.as_deref().unwrap().contains("not committed in time"));
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::Result;
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    config::InsuranceConfig,
+    database::Database,
+    models::{InsuranceEntryKind, InsuranceFundEntry, Trade},
+};
+
+/// Off-chain insurance fund ledger.
+///
+/// Credited with liquidation penalties once settlement has charged them
+/// on-chain and with the insurance share of trading fees, debited with
+/// liquidation losses beyond bankruptcy price. Every
+/// movement is written to `insurance_fund` together with the running balance.
+pub struct InsuranceFund {
+    database: Arc<Database>,
+    config: InsuranceConfig,
+    balance: Mutex<Decimal>,
+}
+
+impl InsuranceFund {
+    pub async fn new(database: Arc<Database>, config: InsuranceConfig) -> Result<Self> {
+        let balance = database.get_insurance_fund_balance().await?;
+        info!("Insurance fund loaded with balance {}", balance);
+
+        Ok(Self {
+            database,
+            config,
+            balance: Mutex::new(balance),
+        })
+    }
+
+    pub async fn balance(&self) -> Decimal {
+        *self.balance.lock().await
+    }
+
+    pub fn config(&self) -> &InsuranceConfig {
+        &self.config
+    }
+
+    pub async fn credit(
+        &self,
+        kind: InsuranceEntryKind,
+        market_id: u64,
+        amount: Decimal,
+        reference_id: Option<Uuid>,
+    ) -> Result<()> {
+        if amount <= Decimal::ZERO {
+            return Ok(());
+        }
+
+        let mut balance = self.balance.lock().await;
+        self.record(&mut balance, kind, market_id, amount, reference_id).await
+    }
+
+    /// Cover as much of `loss` as the fund allows and return the uncovered remainder.
+    pub async fn cover_loss(&self, market_id: u64, loss: Decimal, reference_id: Option<Uuid>) -> Result<Decimal> {
+        if loss <= Decimal::ZERO {
+            return Ok(Decimal::ZERO);
+        }
+
+        let mut balance = self.balance.lock().await;
+        let covered = loss.min(*balance);
+        if covered > Decimal::ZERO {
+            self.record(&mut balance, InsuranceEntryKind::LiquidationLoss, market_id, -covered, reference_id)
+                .await?;
+        }
+
+        let uncovered = loss - covered;
+        if uncovered > Decimal::ZERO {
+            warn!("Insurance fund exhausted: {} of loss in market {} uncovered", uncovered, market_id);
+        }
+        Ok(uncovered)
+    }
+
+    async fn record(
+        &self,
+        balance: &mut Decimal,
+        kind: InsuranceEntryKind,
+        market_id: u64,
+        amount: Decimal,
+        reference_id: Option<Uuid>,
+    ) -> Result<()> {
+        let entry = InsuranceFundEntry {
+            id: Uuid::new_v4(),
+            kind,
+            market_id,
+            amount,
+            balance: *balance + amount,
+            reference_id,
+            created_at: chrono::Utc::now(),
+        };
+        self.database.insert_insurance_fund_entry(&entry).await?;
+        *balance = entry.balance;
+
+        debug!("Insurance fund {:?} {} -> balance {}", entry.kind, amount, entry.balance);
+        Ok(())
+    }
+
+    /// Insurance share of the taker fee on a fill.
+    pub fn fee_share(&self, trade: &Trade) -> Decimal {
+        trade.size * trade.price
+            * Decimal::from(self.config.trading_fee_bps) / Decimal::from(10_000)
+            * Decimal::from(self.config.insurance_bps) / Decimal::from(10_000)
+    }
+
+    /// Accrue the insurance share of fees for every trade the matching engine broadcasts.
+    pub async fn start_fee_accrual_loop(&self, mut trade_receiver: broadcast::Receiver<Trade>) -> Result<()> {
+        info!("Starting insurance fee accrual loop");
+
+        loop {
+            match trade_receiver.recv().await {
+                Ok(trade) => {
+                    let share = self.fee_share(&trade);
+                    if let Err(e) = self.credit(InsuranceEntryKind::TradingFee, trade.market_id, share, Some(trade.id)).await {
+                        error!("Failed to accrue insurance fee for trade {}: {}", trade.id, e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Insurance fee accrual lagged, skipped {} trades", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    warn!("Trade channel closed, stopping insurance fee accrual");
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
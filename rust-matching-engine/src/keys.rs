@@ -0,0 +1,299 @@
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use aptos_crypto::ed25519::{Ed25519PrivateKey, Ed25519PublicKey, Ed25519Signature};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt, io::Write, path::Path, sync::Arc};
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+use crate::config::KeySource;
+
+const MAINNET_CHAIN_ID: u8 = 1;
+
+/// Keys that ship in this repository's sample configs and docs. Anyone can
+/// sign with them, so they are refused on mainnet.
+const PUBLISHED_KEYS: &[&str] = &[
+    "ed25519-priv-0x85087dfcba441854291cbec993d857ac4118efaf2d2ed5665a4f92c7664eadba",
+    "ed25519-priv-0xc919a81b08fd79a13936d4c8cde5aef42c79b4bf4b9493c7d9915758bf07ddfb",
+];
+
+/// Signs transactions for one account.
+///
+/// `LocalSigner` holds the key in process memory; `RemoteSigner` forwards
+/// signing messages to a daemon so the key never enters the engine.
+#[async_trait]
+pub trait TransactionSigner: Send + Sync + fmt::Debug {
+    fn public_key(&self) -> &Ed25519PublicKey;
+
+    async fn sign(&self, message: &[u8]) -> Result<Ed25519Signature>;
+}
+
+pub struct LocalSigner {
+    private_key: Ed25519PrivateKey,
+    public_key: Ed25519PublicKey,
+}
+
+impl LocalSigner {
+    pub fn new(private_key: Ed25519PrivateKey) -> Self {
+        let public_key = Ed25519PublicKey::from(&private_key);
+        Self { private_key, public_key }
+    }
+}
+
+impl fmt::Debug for LocalSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalSigner")
+            .field("public_key", &self.public_key)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for LocalSigner {
+    fn public_key(&self) -> &Ed25519PublicKey {
+        &self.public_key
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Ed25519Signature> {
+        Ok(self.private_key.sign_message(message))
+    }
+}
+
+/// Client for a signing daemon serving
+/// `GET {url}/accounts/{address}` -> `{"public_key": "0x..."}` and
+/// `POST {url}/accounts/{address}/sign` `{"message": "0x..."}` -> `{"signature": "0x..."}`.
+#[derive(Debug)]
+pub struct RemoteSigner {
+    url: String,
+    address: String,
+    public_key: Ed25519PublicKey,
+    http: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct RemotePublicKey {
+    public_key: String,
+}
+
+#[derive(Deserialize)]
+struct RemoteSignature {
+    signature: String,
+}
+
+impl RemoteSigner {
+    pub async fn connect(url: &str, address: &str) -> Result<Self> {
+        let url = url.trim_end_matches('/').to_string();
+        let http = reqwest::Client::new();
+        let response: RemotePublicKey = http
+            .get(format!("{}/accounts/{}", url, address))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Signing daemon at {} has no key for {}", url, address))?;
+        let public_key = Ed25519PublicKey::try_from(decode_hex(&response.public_key)?.as_slice())
+            .context("Signing daemon returned an invalid public key")?;
+
+        Ok(Self {
+            url,
+            address: address.to_string(),
+            public_key,
+            http,
+        })
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for RemoteSigner {
+    fn public_key(&self) -> &Ed25519PublicKey {
+        &self.public_key
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Ed25519Signature> {
+        let response: RemoteSignature = self
+            .http
+            .post(format!("{}/accounts/{}/sign", self.url, self.address))
+            .json(&serde_json::json!({ "message": format!("0x{}", hex::encode(message)) }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ed25519Signature::try_from(decode_hex(&response.signature)?.as_slice())
+            .context("Signing daemon returned an invalid signature")
+    }
+}
+
+/// Passphrase-encrypted private key: scrypt derives an AES-256-GCM key that
+/// seals the 32 raw key bytes.
+#[derive(Serialize, Deserialize)]
+struct Keystore {
+    version: u32,
+    scrypt_log_n: u8,
+    scrypt_r: u32,
+    scrypt_p: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Keystore {
+    const VERSION: u32 = 1;
+
+    fn cipher(passphrase: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<Aes256Gcm> {
+        let params = scrypt::Params::new(log_n, r, p, 32).map_err(|e| anyhow!("Invalid scrypt parameters: {}", e))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        scrypt::scrypt(passphrase.as_bytes(), salt, &params, key.as_mut())
+            .map_err(|e| anyhow!("Failed to derive keystore key: {}", e))?;
+        Aes256Gcm::new_from_slice(key.as_ref()).map_err(|e| anyhow!("Invalid keystore key: {}", e))
+    }
+
+    fn encrypt(private_key: &[u8], passphrase: &str) -> Result<Self> {
+        let (log_n, r, p) = (17, 8, 1);
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = Self::cipher(passphrase, &salt, log_n, r, p)?
+            .encrypt(&nonce, private_key)
+            .map_err(|_| anyhow!("Failed to encrypt private key"))?;
+
+        Ok(Self {
+            version: Self::VERSION,
+            scrypt_log_n: log_n,
+            scrypt_r: r,
+            scrypt_p: p,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    fn decrypt(&self, passphrase: &str) -> Result<Zeroizing<Vec<u8>>> {
+        if self.version != Self::VERSION {
+            bail!("Unsupported keystore version {}", self.version);
+        }
+        let nonce = hex::decode(&self.nonce).context("Invalid keystore nonce")?;
+        if nonce.len() != 12 {
+            bail!("Invalid keystore nonce length {}", nonce.len());
+        }
+        let cipher = Self::cipher(
+            passphrase,
+            &hex::decode(&self.salt).context("Invalid keystore salt")?,
+            self.scrypt_log_n,
+            self.scrypt_r,
+            self.scrypt_p,
+        )?;
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                hex::decode(&self.ciphertext).context("Invalid keystore ciphertext")?.as_slice(),
+            )
+            .map_err(|_| anyhow!("Wrong passphrase or corrupted keystore"))?;
+        Ok(Zeroizing::new(plaintext))
+    }
+}
+
+/// Parse an `ed25519-priv-0x...` (or bare hex) private key.
+pub fn parse_private_key(key_str: &str) -> Result<Ed25519PrivateKey> {
+    let bytes = decode_hex(key_str.trim().trim_start_matches("ed25519-priv-"))
+        .context("Failed to decode private key hex string")?;
+    Ed25519PrivateKey::try_from(bytes.as_slice())
+        .context("Failed to create Ed25519PrivateKey from bytes")
+}
+
+fn decode_hex(value: &str) -> Result<Zeroizing<Vec<u8>>> {
+    Ok(Zeroizing::new(hex::decode(value.trim().trim_start_matches("0x"))?))
+}
+
+/// Read a key file, refusing files that group or other users can access.
+fn read_key_file(path: &Path) -> Result<Zeroizing<String>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path)
+            .with_context(|| format!("Cannot read key file {}", path.display()))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            bail!("Key file {} is accessible by other users (mode {:o}), chmod 600 it", path.display(), mode & 0o777);
+        }
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read key file {}", path.display()))?;
+    Ok(Zeroizing::new(contents))
+}
+
+/// Load the signer for `address` from its configured source.
+///
+/// Fails on mainnet when the key is one of the keys published with this
+/// repository.
+pub async fn load_signer(source: &KeySource, address: &str, chain_id: u8) -> Result<Arc<dyn TransactionSigner>> {
+    let signer: Arc<dyn TransactionSigner> = match source {
+        KeySource::Inline { key } => {
+            if key.expose().is_empty() {
+                bail!("No signing key configured for {}", address);
+            }
+            if chain_id == MAINNET_CHAIN_ID {
+                warn!("Signing key for {} is written into config; use a key file, keystore or signing daemon on mainnet", address);
+            }
+            Arc::new(LocalSigner::new(parse_private_key(key.expose())?))
+        }
+        KeySource::File { path } => {
+            Arc::new(LocalSigner::new(parse_private_key(&read_key_file(path)?)?))
+        }
+        KeySource::Keystore { path, passphrase_env } => {
+            let passphrase = Zeroizing::new(std::env::var(passphrase_env)
+                .with_context(|| format!("Keystore passphrase variable {} is not set", passphrase_env))?);
+            let keystore: Keystore = serde_json::from_str(&read_key_file(path)?)
+                .with_context(|| format!("Invalid keystore {}", path.display()))?;
+            let bytes = keystore.decrypt(&passphrase)?;
+            Arc::new(LocalSigner::new(
+                Ed25519PrivateKey::try_from(bytes.as_slice()).context("Keystore does not hold an Ed25519 key")?,
+            ))
+        }
+        KeySource::Remote { url } => Arc::new(RemoteSigner::connect(url, address).await?),
+    };
+
+    if chain_id == MAINNET_CHAIN_ID && is_published_key(signer.public_key()) {
+        bail!("Refusing to start on mainnet: the signing key for {} is a published development key", address);
+    }
+
+    info!("Loaded signing key for {}", address);
+    Ok(signer)
+}
+
+fn is_published_key(public_key: &Ed25519PublicKey) -> bool {
+    PUBLISHED_KEYS
+        .iter()
+        .filter_map(|key| parse_private_key(key).ok())
+        .any(|key| Ed25519PublicKey::from(&key) == *public_key)
+}
+
+/// Encrypt the key in `key_path` into a keystore at `keystore_path`, for
+/// `admin_key.source = "keystore"`.
+pub fn encrypt_key_file(key_path: &Path, keystore_path: &Path, passphrase: &str) -> Result<()> {
+    let private_key = read_key_file(key_path)?;
+    let bytes = decode_hex(private_key.trim().trim_start_matches("ed25519-priv-"))
+        .context("Failed to decode private key hex string")?;
+    Ed25519PrivateKey::try_from(bytes.as_slice()).context("Not an Ed25519 private key")?;
+
+    let keystore = Keystore::encrypt(&bytes, passphrase)?;
+    // Created owner-only from the start, and never over an existing keystore
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(keystore_path)
+        .with_context(|| format!("Failed to create keystore {}", keystore_path.display()))?;
+    file.write_all(serde_json::to_string_pretty(&keystore)?.as_bytes())?;
+    Ok(())
+}
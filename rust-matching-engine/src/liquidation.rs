@@ -0,0 +1,470 @@
+use anyhow::{Context, Result};
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+use serde::Serialize;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::RwLock;
+use tokio::time::interval;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    chain::{USDC_DECIMALS, USDC_UNIT},
+    collateral::CollateralLedger,
+    config::LiquidationConfig,
+    database::Database,
+    insurance::InsuranceFund,
+    margin::{AccountHealth, MarginEngine},
+    matching_engine::MatchingEngine,
+    models::{
+        AdlEvent, Liquidation, LiquidationPenalty, MarginMode, Order, OrderSide, OrderStatus, OrderType,
+        PenaltyStatus, Trade,
+    },
+    pricing::PriceService,
+};
+
+/// Where a user's position sits in the auto-deleveraging queue.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdlIndicator {
+    pub market_id: u64,
+    pub size: Decimal,
+    pub score: Decimal,
+    /// 1 (last to be deleveraged) up to the configured number of levels
+    pub level: u8,
+}
+
+/// Watches account equity against maintenance margin at mark prices and
+/// closes positions of accounts that fall below it.
+///
+/// Liquidation orders are plain market orders from the liquidated account, so
+/// their fills land in `trades` and are settled like any other trade. Fills
+/// above bankruptcy price owe a penalty, which settlement charges on-chain
+/// once the fills have settled and only then credits to the insurance fund;
+/// fills below it are covered by the fund. When the fund cannot cover the
+/// expected loss, or the book cannot absorb the position, the remainder is
+/// closed at bankruptcy price against the highest ranked profitable opposite
+/// positions.
+pub struct LiquidationEngine {
+    matching_engine: Arc<RwLock<MatchingEngine>>,
+    margin_engine: Arc<RwLock<MarginEngine>>,
+    price_service: Arc<PriceService>,
+    collateral_ledger: Arc<CollateralLedger>,
+    database: Arc<Database>,
+    insurance_fund: Arc<InsuranceFund>,
+    config: LiquidationConfig,
+    adl_indicators: RwLock<HashMap<String, Vec<AdlIndicator>>>,
+}
+
+impl LiquidationEngine {
+    pub async fn new(
+        matching_engine: Arc<RwLock<MatchingEngine>>,
+        margin_engine: Arc<RwLock<MarginEngine>>,
+        price_service: Arc<PriceService>,
+        collateral_ledger: Arc<CollateralLedger>,
+        database: Arc<Database>,
+        insurance_fund: Arc<InsuranceFund>,
+        config: LiquidationConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            matching_engine,
+            margin_engine,
+            price_service,
+            collateral_ledger,
+            database,
+            insurance_fund,
+            config,
+            adl_indicators: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub async fn adl_indicators(&self, user_address: &str) -> Vec<AdlIndicator> {
+        self.adl_indicators
+            .read()
+            .await
+            .get(user_address)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn start_liquidation_loop(&self) -> Result<()> {
+        info!("Starting liquidation monitor loop");
+        let mut interval = interval(Duration::from_secs(self.config.check_interval_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.check_accounts().await {
+                error!("Liquidation check error: {}", e);
+                // Continue running despite errors
+            }
+        }
+    }
+
+    async fn mark_prices(&self) -> HashMap<u64, Decimal> {
+        self.price_service
+            .get_all_prices()
+            .await
+            .into_iter()
+            .map(|p| (p.market_id, p.mark_price))
+            .collect()
+    }
+
+    async fn check_accounts(&self) -> Result<()> {
+        let mark_prices = self.mark_prices().await;
+        let users = self.margin_engine.read().await.users_with_positions();
+
+        let mut healths = HashMap::new();
+        for user_address in users {
+            let collateral = match self.collateral_ledger.balance(&user_address).await {
+                Ok(collateral) => collateral,
+                Err(e) => {
+                    warn!("Skipping liquidation check for {}: {}", user_address, e);
+                    continue;
+                }
+            };
+
+            let health = self
+                .margin_engine
+                .read()
+                .await
+                .account_health(&user_address, collateral, &mark_prices);
+            debug!("Account {} equity {} maintenance {}",
+                user_address, health.equity, health.maintenance_margin);
+            healths.insert(user_address, health);
+        }
+
+        let mut equities: HashMap<String, Decimal> = healths
+            .iter()
+            .map(|(user, health)| (user.clone(), health.equity))
+            .collect();
+        self.update_adl_indicators(&mark_prices, &equities).await;
+
+        for (user_address, health) in &healths {
+            if health.is_liquidatable() {
+                warn!("Account {} below maintenance margin: equity {} < {}",
+                    user_address, health.equity, health.maintenance_margin);
+                let cross_markets = self.markets_in_mode(user_address, MarginMode::Cross).await;
+                if let Err(e) = self.liquidate_account(user_address, health, &cross_markets, &mark_prices, &mut equities).await {
+                    error!("Failed to liquidate account {}: {}", user_address, e);
+                }
+            }
+
+            let isolated = self.margin_engine.read().await.isolated_health(user_address, &mark_prices);
+            for (market_id, health) in isolated {
+                if health.is_liquidatable() {
+                    warn!("Isolated position of {} in market {} below maintenance margin: equity {} < {}",
+                        user_address, market_id, health.equity, health.maintenance_margin);
+                    if let Err(e) = self.liquidate_account(user_address, &health, &[market_id], &mark_prices, &mut equities).await {
+                        error!("Failed to liquidate isolated position of {} in market {}: {}", user_address, market_id, e);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rank profitable positions per market and side into `adl_levels` buckets.
+    async fn update_adl_indicators(&self, mark_prices: &HashMap<u64, Decimal>, equities: &HashMap<String, Decimal>) {
+        let levels = self.insurance_fund.config().adl_levels.max(1);
+        let mut indicators: HashMap<String, Vec<AdlIndicator>> = HashMap::new();
+
+        let margin_engine = self.margin_engine.read().await;
+        for (market_id, mark_price) in mark_prices {
+            for long in [true, false] {
+                let ranking = margin_engine.adl_ranking(*market_id, long, *mark_price, equities);
+                let count = ranking.len();
+                for (rank, candidate) in ranking.into_iter().enumerate() {
+                    let level = levels - (rank * levels as usize / count) as u8;
+                    indicators.entry(candidate.user_address).or_default().push(AdlIndicator {
+                        market_id: *market_id,
+                        size: candidate.size,
+                        score: candidate.score,
+                        level,
+                    });
+                }
+            }
+        }
+
+        *self.adl_indicators.write().await = indicators;
+    }
+
+    /// Markets the user trades in the given margin mode, from positions and resting orders.
+    async fn markets_in_mode(&self, user_address: &str, mode: MarginMode) -> Vec<u64> {
+        let mut market_ids: Vec<u64> = self
+            .matching_engine
+            .read()
+            .await
+            .open_orders_for_user(user_address)
+            .into_iter()
+            .map(|o| o.market_id)
+            .collect();
+
+        let margin_engine = self.margin_engine.read().await;
+        market_ids.extend(margin_engine.positions_for_user(user_address).into_iter().map(|p| p.market_id));
+        market_ids.retain(|market_id| margin_engine.margin_mode(user_address, *market_id) == mode);
+        market_ids.sort();
+        market_ids.dedup();
+        market_ids
+    }
+
+    /// Close the user's positions in `market_ids`, which share the margin described by `health`.
+    async fn liquidate_account(
+        &self,
+        user_address: &str,
+        health: &AccountHealth,
+        market_ids: &[u64],
+        mark_prices: &HashMap<u64, Decimal>,
+        equities: &mut HashMap<String, Decimal>,
+    ) -> Result<()> {
+        // Cancel open orders first so they cannot re-open exposure
+        let open_order_ids: Vec<Uuid> = self
+            .matching_engine
+            .read()
+            .await
+            .open_orders_for_user(user_address)
+            .into_iter()
+            .filter(|o| market_ids.contains(&o.market_id))
+            .map(|o| o.id)
+            .collect();
+        for order_id in open_order_ids {
+            if self.matching_engine.write().await.cancel_order(order_id).await? {
+                self.margin_engine.write().await.release(order_id);
+            }
+        }
+
+        let positions: Vec<_> = self
+            .margin_engine
+            .read()
+            .await
+            .positions_for_user(user_address)
+            .into_iter()
+            .filter(|p| !p.size.is_zero() && market_ids.contains(&p.market_id))
+            .cloned()
+            .collect();
+        // Taken before any position is closed, so the shared equity is split once
+        let bankruptcy_prices = self
+            .margin_engine
+            .read()
+            .await
+            .bankruptcy_prices(user_address, health, market_ids, mark_prices);
+
+        for position in positions {
+            let mark_price = mark_prices.get(&position.market_id).copied().unwrap_or(position.entry_price);
+            let bankruptcy_price = bankruptcy_prices.get(&position.market_id).copied().unwrap_or(mark_price);
+            let long = position.size.is_sign_positive();
+            // Closing a long gains when the fill is above bankruptcy, closing a short when below
+            let direction = if long { Decimal::ONE } else { Decimal::NEGATIVE_ONE };
+            let side = if long { OrderSide::Sell } else { OrderSide::Buy };
+
+            let mut order = Order {
+                id: Uuid::new_v4(),
+                user_address: user_address.to_string(),
+                market_id: position.market_id,
+                side: side.clone(),
+                order_type: OrderType::Market,
+                size: position.size.abs(),
+                price: None,
+                filled_size: Decimal::ZERO,
+                status: OrderStatus::Pending,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+                expires_at: None,
+            };
+
+            // Only go to the book if the fund can absorb the expected loss
+            let estimated_price = self
+                .matching_engine
+                .read()
+                .await
+                .get_order_book(position.market_id)
+                .and_then(|book| book.estimate_fill_price(&side, order.size));
+            let fund_balance = self.insurance_fund.balance().await;
+            let use_book = match estimated_price {
+                Some(price) => ((bankruptcy_price - price) * direction).max(Decimal::ZERO) * order.size <= fund_balance,
+                None => false,
+            };
+
+            let trades = if use_book {
+                let trades = self.matching_engine.write().await.submit_order(order.clone()).await?;
+                self.margin_engine.write().await.apply_trades(&trades);
+                trades
+            } else {
+                warn!("Insurance fund cannot back liquidation of {} in market {}, deleveraging",
+                    user_address, position.market_id);
+                // Stored anyway, so the ADL fills have a real taker order
+                self.database.insert_order(&order).await?;
+                Vec::new()
+            };
+
+            let filled_size: Decimal = trades.iter().map(|t| t.size).sum();
+            let filled_notional: Decimal = trades.iter().map(|t| t.size * t.price).sum();
+            let average_price = if filled_size.is_zero() { None } else { Some(filled_notional / filled_size) };
+            let surplus: Decimal = trades
+                .iter()
+                .map(|t| t.size * (t.price - bankruptcy_price) * direction)
+                .sum();
+            // Whole USDC base units, the most the vault can move
+            let penalty = (filled_notional * Decimal::from(self.config.penalty_bps) / Decimal::from(10_000))
+                .min(surplus.max(Decimal::ZERO))
+                .round_dp_with_strategy(USDC_DECIMALS, RoundingStrategy::ToZero);
+
+            let liquidation = Liquidation {
+                id: Uuid::new_v4(),
+                user_address: user_address.to_string(),
+                market_id: position.market_id,
+                order_id: order.id,
+                side: side.clone(),
+                size: order.size,
+                filled_size,
+                average_price,
+                mark_price,
+                penalty,
+                equity: health.equity,
+                maintenance_margin: health.maintenance_margin,
+                created_at: chrono::Utc::now(),
+            };
+            self.database.insert_liquidation(&liquidation).await?;
+
+            if penalty > Decimal::ZERO {
+                let amount = (penalty * Decimal::from(USDC_UNIT))
+                    .to_u64()
+                    .context("Liquidation penalty out of range")?;
+                self.database.insert_liquidation_penalty(&LiquidationPenalty {
+                    liquidation_id: liquidation.id,
+                    order_id: order.id,
+                    user_address: user_address.to_string(),
+                    market_id: position.market_id,
+                    amount,
+                    status: PenaltyStatus::Queued,
+                    tx_hash: None,
+                    error: None,
+                    attempts: 0,
+                    created_at: liquidation.created_at,
+                    updated_at: liquidation.created_at,
+                }).await?;
+            } else if surplus < Decimal::ZERO {
+                self.insurance_fund
+                    .cover_loss(position.market_id, -surplus, Some(liquidation.id))
+                    .await?;
+            }
+
+            info!("Liquidated {} of {} in market {} for {} (penalty {})",
+                filled_size, order.size, position.market_id, user_address, penalty);
+
+            let remaining = order.size - filled_size;
+            if remaining > Decimal::ZERO {
+                let deleveraged = self
+                    .auto_deleverage(&liquidation, !long, remaining, bankruptcy_price, mark_prices, equities)
+                    .await?;
+                order.filled_size = filled_size + deleveraged;
+                order.status = if order.filled_size >= order.size { OrderStatus::Filled } else { OrderStatus::PartiallyFilled };
+                order.updated_at = chrono::Utc::now();
+                self.database.update_order(&order).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Close `size` of a bankrupt position at bankruptcy price against the
+    /// highest ranked profitable positions on the opposite side, and return
+    /// the size closed.
+    ///
+    /// Each counterparty gets a filled market order of its own as the maker
+    /// side of its fill. Its equity is recomputed afterwards, so later
+    /// rankings see the deleveraged position.
+    async fn auto_deleverage(
+        &self,
+        liquidation: &Liquidation,
+        counterparty_long: bool,
+        size: Decimal,
+        bankruptcy_price: Decimal,
+        mark_prices: &HashMap<u64, Decimal>,
+        equities: &mut HashMap<String, Decimal>,
+    ) -> Result<Decimal> {
+        let mark_price = mark_prices.get(&liquidation.market_id).copied().unwrap_or(bankruptcy_price);
+        let ranking = self.margin_engine.read().await.adl_ranking(
+            liquidation.market_id,
+            counterparty_long,
+            mark_price,
+            equities,
+        );
+
+        let mut remaining = size;
+        for candidate in ranking {
+            if remaining.is_zero() {
+                break;
+            }
+            if candidate.user_address == liquidation.user_address {
+                continue;
+            }
+
+            let fill_size = remaining.min(candidate.size.abs());
+            let now = chrono::Utc::now();
+            let maker_order = Order {
+                id: Uuid::new_v4(),
+                user_address: candidate.user_address.clone(),
+                market_id: liquidation.market_id,
+                side: match liquidation.side {
+                    OrderSide::Buy => OrderSide::Sell,
+                    OrderSide::Sell => OrderSide::Buy,
+                },
+                order_type: OrderType::Market,
+                size: fill_size,
+                price: Some(bankruptcy_price),
+                filled_size: fill_size,
+                status: OrderStatus::Filled,
+                created_at: now,
+                updated_at: now,
+                expires_at: None,
+            };
+            self.database.insert_order(&maker_order).await?;
+
+            let trade = Trade {
+                id: Uuid::new_v4(),
+                market_id: liquidation.market_id,
+                taker_order_id: liquidation.order_id,
+                maker_order_id: maker_order.id,
+                taker_address: liquidation.user_address.clone(),
+                maker_address: candidate.user_address.clone(),
+                size: fill_size,
+                price: bankruptcy_price,
+                side: liquidation.side.clone(),
+                created_at: now,
+                settlement_batch_id: None,
+            };
+            // Stored like any other fill so the settlement service picks it up
+            self.database.insert_trade(&trade).await?;
+            self.margin_engine.write().await.apply_trades(std::slice::from_ref(&trade));
+
+            let event = AdlEvent {
+                id: Uuid::new_v4(),
+                liquidation_id: liquidation.id,
+                trade_id: trade.id,
+                market_id: liquidation.market_id,
+                liquidated_address: liquidation.user_address.clone(),
+                counterparty_address: candidate.user_address.clone(),
+                side: liquidation.side.clone(),
+                size: fill_size,
+                bankruptcy_price,
+                created_at: trade.created_at,
+            };
+            self.database.insert_adl_event(&event).await?;
+
+            warn!("ADL: {} of {} in market {} closed against {} at {}",
+                fill_size, liquidation.user_address, liquidation.market_id, candidate.user_address, bankruptcy_price);
+            remaining -= fill_size;
+
+            let collateral = self.collateral_ledger.balance(&candidate.user_address).await?;
+            let health = self.margin_engine.read().await.account_health(&candidate.user_address, collateral, mark_prices);
+            equities.insert(candidate.user_address.clone(), health.equity);
+        }
+
+        if remaining > Decimal::ZERO {
+            error!("ADL could not close {} of {} in market {}: no profitable counterparties left",
+                remaining, liquidation.user_address, liquidation.market_id);
+        }
+
+        Ok(size - remaining)
+    }
+}
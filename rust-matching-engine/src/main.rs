@@ -3,12 +3,27 @@ mod matching_engine;
 mod models;
 mod api;
 mod aptos_client;
+mod chain;
+mod mock_chain;
 mod database;
 mod settlement;
+mod netting;
 mod redis_client;
+mod margin;
+mod pricing;
+mod funding;
+mod liquidation;
+mod insurance;
+mod oracle;
+mod collateral;
+mod signer_pool;
+mod freeze;
+mod keys;
+mod withdrawal;
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use axum::{
+    middleware,
     routing::{get, post},
     Router,
 };
@@ -25,12 +40,35 @@ use crate::{
         health::health_check,
         markets::{get_market, get_all_markets},
         deposit::deposit_funds,
+        withdraw::{get_user_withdrawals, get_withdrawal, withdraw_funds},
         user_queries::{get_user_orders, get_user_trades, get_all_trades, get_market_trades},
+        prices::{get_all_prices, get_price, stream_prices},
+        funding::get_funding_history,
+        liquidations::{get_insurance_fund, get_adl_indicators},
+        margin::{get_margin_setting, update_margin_setting},
+        accounts::get_account_summary,
+        settlement::{
+            abandon_settlement_batch, get_dead_lettered_trades, get_held_trades, get_net_deltas,
+            get_settlement_batch, get_settlement_metrics, list_settlement_batches, pause_settlement,
+            release_held_trades, requeue_dead_lettered_trades, resume_settlement,
+            retry_settlement_batch,
+        },
+        auth::require_admin,
     },
     database::Database,
-    aptos_client::AptosClient,
+    chain::{chain_client_from_config, ChainClient},
     settlement::SettlementService,
     redis_client::RedisClient,
+    margin::MarginEngine,
+    pricing::{price_source_from_config, PriceService},
+    funding::FundingService,
+    liquidation::LiquidationEngine,
+    insurance::InsuranceFund,
+    oracle::OraclePusher,
+    collateral::CollateralLedger,
+    freeze::FreezeSweeper,
+    withdrawal::WithdrawalService,
+    signer_pool::SignerPool,
 };
 pub type SharedState = Arc<AppState>;
 
@@ -38,8 +76,14 @@ pub struct AppState {
     pub matching_engine: Arc<RwLock<MatchingEngine>>,
     pub database: Arc<Database>,
     pub redis_client: Arc<RwLock<RedisClient>>,
-    pub aptos_client: Arc<AptosClient>,
+    pub chain_client: Arc<dyn ChainClient>,
     pub settlement_service: Arc<SettlementService>,
+    pub margin_engine: Arc<RwLock<MarginEngine>>,
+    pub price_service: Arc<PriceService>,
+    pub collateral_ledger: Arc<CollateralLedger>,
+    pub withdrawal_service: Arc<WithdrawalService>,
+    pub insurance_fund: Arc<InsuranceFund>,
+    pub liquidation_engine: Arc<LiquidationEngine>,
     pub config: Config,
 }
 
@@ -47,6 +91,19 @@ pub struct AppState {
 async fn main() -> Result<()> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
+
+    // `encrypt-key <key-file> <keystore>` writes a new encrypted keystore and exits
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("encrypt-key") {
+        let (Some(key_path), Some(keystore_path)) = (args.get(2), args.get(3)) else {
+            bail!("usage: encrypt-key <key-file> <keystore> (passphrase in HYPERPERP_KEYSTORE_PASSPHRASE)");
+        };
+        let passphrase = std::env::var("HYPERPERP_KEYSTORE_PASSPHRASE")
+            .map_err(|_| anyhow!("HYPERPERP_KEYSTORE_PASSPHRASE is not set"))?;
+        keys::encrypt_key_file(key_path.as_ref(), keystore_path.as_ref(), &passphrase)?;
+        info!("Keystore written to {}", keystore_path);
+        return Ok(());
+    }
     
     // Load configuration
     let config = Config::new()?;
@@ -60,9 +117,12 @@ async fn main() -> Result<()> {
     let redis_client = Arc::new(RwLock::new(RedisClient::new(&config.redis.url).await?));
     info!("Connected to Redis");
 
-    // Initialize Aptos client
-    let aptos_client = Arc::new(AptosClient::new(&config.aptos).await?);
-    info!("Connected to Aptos node");
+    // Load the admin and settlement signers; the chain client sends admin transactions through the pool's admin
+    let signer_pool = SignerPool::from_config(&config.aptos).await?;
+
+    // Initialize chain client
+    let chain_client = chain_client_from_config(&config.aptos, signer_pool.admin().clone()).await?;
+    info!("Chain client initialized");
 
     // Initialize matching engine
     let matching_engine = Arc::new(RwLock::new(
@@ -70,35 +130,199 @@ async fn main() -> Result<()> {
     ));
     info!("Matching engine initialized");
 
+    // Initialize price service
+    let price_service = Arc::new(PriceService::new(
+        price_source_from_config(&config.pricing),
+        matching_engine.clone(),
+        config.pricing.clone(),
+    ));
+    info!("Price service initialized");
+
+    // Initialize funding service
+    let funding_service = Arc::new(
+        FundingService::new(
+            chain_client.clone(),
+            database.clone(),
+            price_service.clone(),
+            config.markets.iter().map(|m| m.market_id).collect(),
+            config.funding.clone(),
+        ).await?
+    );
+    info!("Funding service initialized");
+
+    // Initialize oracle pusher
+    let oracle_pusher = Arc::new(
+        OraclePusher::new(
+            chain_client.clone(),
+            config.markets.iter().map(|m| m.market_id).collect(),
+            config.oracle.clone(),
+            &config.pricing,
+        ).await?
+    );
+    info!("Oracle pusher initialized");
+
+    // Initialize margin engine
+    let mut margin_engine = MarginEngine::new(&config.markets);
+    margin_engine.load(&database).await?;
+    let margin_engine = Arc::new(RwLock::new(margin_engine));
+    info!("Margin engine initialized");
+
+    // Initialize insurance fund
+    let insurance_fund = Arc::new(InsuranceFund::new(database.clone(), config.insurance.clone()).await?);
+    info!("Insurance fund initialized");
+
     // Initialize settlement service
     let settlement_service = Arc::new(
         SettlementService::new(
-            aptos_client.clone(),
+            chain_client.clone(),
+            signer_pool,
             database.clone(),
+            price_service.clone(),
+            insurance_fund.clone(),
+            config.markets.clone(),
             config.settlement.clone()
         ).await?
     );
     info!("Settlement service initialized");
 
+    // Initialize collateral ledger
+    let collateral_ledger = Arc::new(
+        CollateralLedger::new(
+            chain_client.clone(),
+            database.clone(),
+            config.collateral.clone(),
+        ).await?
+    );
+    info!("Collateral ledger initialized");
+
+    // Initialize freeze request sweeper
+    let freeze_sweeper = FreezeSweeper::new(
+        chain_client.clone(),
+        database.clone(),
+        collateral_ledger.clone(),
+        config.freeze.clone(),
+    ).await?;
+    info!("Freeze request sweeper initialized");
+
+    // Initialize withdrawal service
+    let withdrawal_service = Arc::new(
+        WithdrawalService::new(
+            chain_client.clone(),
+            database.clone(),
+            collateral_ledger.clone(),
+            margin_engine.clone(),
+            price_service.clone(),
+            config.withdrawal.clone(),
+        ).await?
+    );
+    info!("Withdrawal service initialized");
+
+    // Initialize liquidation engine
+    let liquidation_engine = Arc::new(
+        LiquidationEngine::new(
+            matching_engine.clone(),
+            margin_engine.clone(),
+            price_service.clone(),
+            collateral_ledger.clone(),
+            database.clone(),
+            insurance_fund.clone(),
+            config.liquidation.clone(),
+        ).await?
+    );
+    info!("Liquidation engine initialized");
+
+    let trade_receiver = matching_engine.read().await.get_trade_receiver();
+
     // Create shared application state
     let state = Arc::new(AppState {
         matching_engine,
         database,
         redis_client,
-        aptos_client,
+        chain_client,
         settlement_service: settlement_service.clone(),
+        margin_engine,
+        price_service: price_service.clone(),
+        collateral_ledger: collateral_ledger.clone(),
+        withdrawal_service: withdrawal_service.clone(),
+        insurance_fund: insurance_fund.clone(),
+        liquidation_engine: liquidation_engine.clone(),
         config: config.clone(),
     });
 
+    // Start settlement confirmation tracker background task
+    let confirmation_service = settlement_service.clone();
+    let confirmation_handle = tokio::spawn(async move {
+        confirmation_service.start_confirmation_loop().await
+    });
+
     // Start settlement service background task
     let settlement_handle = tokio::spawn(async move {
         settlement_service.start_settlement_loop().await
     });
 
+    // Start price service background task
+    let price_handle = tokio::spawn(async move {
+        price_service.start_price_loop().await
+    });
+
+    // Start funding service background task
+    let funding_handle = tokio::spawn(async move {
+        funding_service.start_funding_loop().await
+    });
+
+    // Start liquidation monitor background task
+    let liquidation_handle = tokio::spawn(async move {
+        liquidation_engine.start_liquidation_loop().await
+    });
+
+    // Start insurance fee accrual background task
+    let insurance_handle = tokio::spawn(async move {
+        insurance_fund.start_fee_accrual_loop(trade_receiver).await
+    });
+
+    // Start collateral sync background task
+    let collateral_handle = tokio::spawn(async move {
+        collateral_ledger.start_sync_loop().await
+    });
+
+    // Start freeze request sweeper background task
+    let freeze_handle = tokio::spawn(async move {
+        freeze_sweeper.start_sweep_loop().await
+    });
+
+    // Start withdrawal processing background task
+    let withdrawal_handle = tokio::spawn(async move {
+        withdrawal_service.start_processing_loop().await
+    });
+
+    // Start oracle pusher background task
+    let oracle_handle = tokio::spawn(async move {
+        oracle_pusher.start_oracle_loop().await
+    });
+
+    // Settlement operations, behind the admin API key
+    let admin_routes = Router::new()
+        .route("/settlement/batches", get(list_settlement_batches))
+        .route("/settlement/batches/:batch_id", get(get_settlement_batch))
+        .route("/settlement/batches/:batch_id/retry", post(retry_settlement_batch))
+        .route("/settlement/batches/:batch_id/abandon", post(abandon_settlement_batch))
+        .route("/settlement/batches/:batch_id/net-deltas", get(get_net_deltas))
+        .route("/settlement/pause", post(pause_settlement))
+        .route("/settlement/resume", post(resume_settlement))
+        .route("/settlement/metrics", get(get_settlement_metrics))
+        .route("/settlement/dead-letters", get(get_dead_lettered_trades))
+        .route("/settlement/dead-letters/requeue", post(requeue_dead_lettered_trades))
+        .route("/settlement/holds", get(get_held_trades))
+        .route("/settlement/holds/release", post(release_held_trades))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_admin));
+
     // Build router
     let app = Router::new()
         .route("/health", get(health_check))
         .route("/deposit", post(deposit_funds))
+        .route("/withdraw", post(withdraw_funds))
+        .route("/withdrawals/:withdrawal_id", get(get_withdrawal))
+        .route("/withdrawals/user/:user_address", get(get_user_withdrawals))
         .route("/orders", post(submit_order))
         .route("/orders/:order_id", post(cancel_order))
         .route("/orderbook/:market_id", get(get_order_book))
@@ -110,6 +334,16 @@ async fn main() -> Result<()> {
         .route("/trades/user/:user_address", get(get_user_trades))
         .route("/trades", get(get_all_trades))
         .route("/trades/:market_id", get(get_market_trades))
+        .route("/prices", get(get_all_prices))
+        .route("/prices/stream", get(stream_prices))
+        .route("/prices/:market_id", get(get_price))
+        .route("/funding/:market_id", get(get_funding_history))
+        .route("/insurance-fund", get(get_insurance_fund))
+        .route("/adl/:user_address", get(get_adl_indicators))
+        .route("/margin/settings", post(update_margin_setting))
+        .route("/margin/settings/:user_address/:market_id", get(get_margin_setting))
+        .route("/accounts/:user_address", get(get_account_summary))
+        .merge(admin_routes)
         .layer(CorsLayer::permissive())
         .with_state(state);
 
@@ -133,6 +367,33 @@ async fn main() -> Result<()> {
         result = settlement_handle => {
             warn!("Settlement service terminated: {:?}", result);
         }
+        result = confirmation_handle => {
+            warn!("Settlement confirmation tracker terminated: {:?}", result);
+        }
+        result = price_handle => {
+            warn!("Price service terminated: {:?}", result);
+        }
+        result = funding_handle => {
+            warn!("Funding service terminated: {:?}", result);
+        }
+        result = liquidation_handle => {
+            warn!("Liquidation engine terminated: {:?}", result);
+        }
+        result = insurance_handle => {
+            warn!("Insurance fee accrual terminated: {:?}", result);
+        }
+        result = collateral_handle => {
+            warn!("Collateral sync terminated: {:?}", result);
+        }
+        result = oracle_handle => {
+            warn!("Oracle pusher terminated: {:?}", result);
+        }
+        result = freeze_handle => {
+            warn!("Freeze request sweeper terminated: {:?}", result);
+        }
+        result = withdrawal_handle => {
+            warn!("Withdrawal processor terminated: {:?}", result);
+        }
     }
 
     Ok(())
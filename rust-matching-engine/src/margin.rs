@@ -0,0 +1,962 @@
+use anyhow::{anyhow, Result};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{
+    config::MarketConfig,
+    database::Database,
+    models::{MarginMode, MarginSetting, Order, OrderSide, Trade},
+};
+
+/// Margin held against the unfilled part of a resting order.
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    pub user_address: String,
+    pub market_id: u64,
+    pub side: OrderSide,
+    pub remaining_size: Decimal,
+    /// Part of the remaining size that adds exposure when filled
+    pub increasing_size: Decimal,
+    pub amount: Decimal,
+}
+
+/// Net position of a user in one market, rebuilt from engine trades.
+#[derive(Debug, Clone, Serialize)]
+pub struct Position {
+    pub user_address: String,
+    pub market_id: u64,
+    /// Signed size: positive is long, negative is short
+    pub size: Decimal,
+    pub entry_price: Decimal,
+    pub realized_pnl: Decimal,
+}
+
+impl Position {
+    fn new(user_address: &str, market_id: u64) -> Self {
+        Self {
+            user_address: user_address.to_string(),
+            market_id,
+            size: Decimal::ZERO,
+            entry_price: Decimal::ZERO,
+            realized_pnl: Decimal::ZERO,
+        }
+    }
+
+    pub fn notional(&self, price: Decimal) -> Decimal {
+        self.size.abs() * price
+    }
+
+    pub fn unrealized_pnl(&self, mark_price: Decimal) -> Decimal {
+        self.size * (mark_price - self.entry_price)
+    }
+
+    /// Apply a fill and return the PnL it realized.
+    fn apply_fill(&mut self, side: &OrderSide, size: Decimal, price: Decimal) -> Decimal {
+        let delta = match side {
+            OrderSide::Buy => size,
+            OrderSide::Sell => -size,
+        };
+
+        let mut realized = Decimal::ZERO;
+        if self.size.is_zero() || self.size.is_sign_positive() == delta.is_sign_positive() {
+            // Opening or adding: volume-weighted entry price
+            let new_size = self.size + delta;
+            self.entry_price = (self.entry_price * self.size.abs() + price * size) / new_size.abs();
+            self.size = new_size;
+        } else {
+            // Reducing, closing or flipping
+            let closed = size.min(self.size.abs());
+            let direction = if self.size.is_sign_positive() { Decimal::ONE } else { Decimal::NEGATIVE_ONE };
+            realized = closed * (price - self.entry_price) * direction;
+
+            let new_size = self.size + delta;
+            if new_size.is_zero() {
+                self.entry_price = Decimal::ZERO;
+            } else if new_size.is_sign_positive() != self.size.is_sign_positive() {
+                self.entry_price = price;
+            }
+            self.size = new_size;
+        }
+
+        self.realized_pnl += realized;
+        realized
+    }
+}
+
+/// Account equity against maintenance margin at current mark prices.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountHealth {
+    pub equity: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub maintenance_margin: Decimal,
+}
+
+impl AccountHealth {
+    pub fn is_liquidatable(&self) -> bool {
+        self.maintenance_margin > Decimal::ZERO && self.equity < self.maintenance_margin
+    }
+
+    /// Maintenance margin over equity; liquidation starts above 1. `None` once equity is gone.
+    pub fn margin_ratio(&self) -> Option<Decimal> {
+        if self.equity <= Decimal::ZERO {
+            return None;
+        }
+        Some(self.maintenance_margin / self.equity)
+    }
+}
+
+/// Risk view of one position, valued at mark.
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionRisk {
+    pub market_id: u64,
+    pub size: Decimal,
+    pub entry_price: Decimal,
+    pub mark_price: Decimal,
+    pub notional: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub realized_pnl: Decimal,
+    pub margin_mode: MarginMode,
+    /// Leverage setting the position was opened with
+    pub leverage: u64,
+    /// Notional over the equity backing the position
+    pub effective_leverage: Option<Decimal>,
+    pub initial_margin: Decimal,
+    pub maintenance_margin: Decimal,
+    /// Margin ratio of the cross account or of the isolated position
+    pub margin_ratio: Option<Decimal>,
+    /// Mark price at which the position becomes liquidatable, other marks unchanged
+    pub liquidation_price: Option<Decimal>,
+}
+
+/// A profitable position ranked for auto-deleveraging.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdlCandidate {
+    pub user_address: String,
+    pub market_id: u64,
+    pub size: Decimal,
+    /// PnL ratio times effective leverage; the highest score is deleveraged first
+    pub score: Decimal,
+}
+
+/// Pre-trade margin engine.
+///
+/// Initial margin for an order is charged only on the part of the order that
+/// increases exposure, after netting against the user's position and resting
+/// orders on the same side. The amount reserved at submit time is tracked per
+/// order, consumed pro rata on fills and the remainder released on cancel, so
+/// reserve and release always add up.
+///
+/// Each user picks a margin mode and leverage per market. Cross positions
+/// share the account's collateral and losses; an isolated position can only
+/// lose the initial margin allocated to it and is liquidated on its own.
+pub struct MarginEngine {
+    markets: HashMap<u64, MarketConfig>,
+    reservations: HashMap<Uuid, Reservation>,
+    positions: HashMap<(String, u64), Position>,
+    settings: HashMap<(String, u64), MarginSetting>,
+}
+
+impl MarginEngine {
+    pub fn new(markets: &[MarketConfig]) -> Self {
+        Self {
+            markets: markets.iter().map(|m| (m.market_id, m.clone())).collect(),
+            reservations: HashMap::new(),
+            positions: HashMap::new(),
+            settings: HashMap::new(),
+        }
+    }
+
+    /// Rebuild positions from trade history and reservations from resting orders.
+    pub async fn load(&mut self, database: &Database) -> Result<()> {
+        for setting in database.get_all_margin_settings().await? {
+            self.settings.insert((setting.user_address.clone(), setting.market_id), setting);
+        }
+
+        let trades = database.get_all_trades(None, None, None, None).await?;
+        // Trades come back newest first
+        for trade in trades.iter().rev() {
+            self.apply_trade_to_positions(trade);
+        }
+
+        let orders = database.get_pending_orders().await?;
+        for order in &orders {
+            if let Some(price) = order.price {
+                let amount = self.quote_order(order, price)?;
+                self.reserve(order, amount);
+            }
+        }
+
+        info!("Margin engine loaded {} positions and {} reservations",
+            self.positions.len(), self.reservations.len());
+        Ok(())
+    }
+
+    pub fn market(&self, market_id: u64) -> Result<&MarketConfig> {
+        self.markets
+            .get(&market_id)
+            .ok_or_else(|| anyhow!("Unknown market {}", market_id))
+    }
+
+    /// Leverage applied to a user's orders in a market.
+    pub fn leverage(&self, user_address: &str, market_id: u64) -> Result<u64> {
+        let market = self.market(market_id)?;
+        let leverage = self
+            .settings
+            .get(&(user_address.to_string(), market_id))
+            .map(|s| s.leverage)
+            .unwrap_or(market.default_leverage);
+        Ok(leverage.clamp(1, market.max_leverage.max(1)))
+    }
+
+    pub fn margin_mode(&self, user_address: &str, market_id: u64) -> MarginMode {
+        self.settings
+            .get(&(user_address.to_string(), market_id))
+            .map(|s| s.margin_mode)
+            .unwrap_or_default()
+    }
+
+    /// Effective settings for a user in a market, falling back to market defaults.
+    pub fn margin_setting(&self, user_address: &str, market_id: u64) -> Result<MarginSetting> {
+        if let Some(setting) = self.settings.get(&(user_address.to_string(), market_id)) {
+            return Ok(setting.clone());
+        }
+
+        Ok(MarginSetting {
+            user_address: user_address.to_string(),
+            market_id,
+            margin_mode: MarginMode::default(),
+            leverage: self.leverage(user_address, market_id)?,
+            updated_at: chrono::Utc::now(),
+        })
+    }
+
+    /// Whether the user has a position or resting orders in a market.
+    pub fn has_open_exposure(&self, user_address: &str, market_id: u64) -> bool {
+        let has_position = self
+            .position(user_address, market_id)
+            .is_some_and(|p| !p.size.is_zero());
+        let has_orders = self
+            .reservations
+            .values()
+            .any(|r| r.user_address == user_address && r.market_id == market_id);
+        has_position || has_orders
+    }
+
+    /// Build the new settings for a user in a market, validating leverage against the market cap.
+    pub fn prepare_margin_setting(
+        &self,
+        user_address: &str,
+        market_id: u64,
+        margin_mode: Option<MarginMode>,
+        leverage: Option<u64>,
+    ) -> Result<MarginSetting> {
+        let market = self.market(market_id)?;
+        let current = self.margin_setting(user_address, market_id)?;
+        let leverage = leverage.unwrap_or(current.leverage);
+        if leverage == 0 || leverage > market.max_leverage {
+            return Err(anyhow!("Leverage {} outside 1..={} for market {}",
+                leverage, market.max_leverage, market_id));
+        }
+
+        Ok(MarginSetting {
+            user_address: user_address.to_string(),
+            market_id,
+            margin_mode: margin_mode.unwrap_or(current.margin_mode),
+            leverage,
+            updated_at: chrono::Utc::now(),
+        })
+    }
+
+    pub fn apply_margin_setting(&mut self, setting: MarginSetting) {
+        info!("Margin setting for {} in market {}: {:?} {}x",
+            setting.user_address, setting.market_id, setting.margin_mode, setting.leverage);
+        self.settings.insert((setting.user_address.clone(), setting.market_id), setting);
+    }
+
+    /// Effective initial margin rate: the stricter of the market IMR and 1 / leverage.
+    pub fn initial_margin_rate(&self, user_address: &str, market_id: u64) -> Result<Decimal> {
+        let market = self.market(market_id)?;
+        let imr = bps_to_rate(market.imr_bps);
+        let leverage_rate = Decimal::ONE / Decimal::from(self.leverage(user_address, market_id)?);
+        Ok(imr.max(leverage_rate))
+    }
+
+    pub fn maintenance_margin_rate(&self, market_id: u64) -> Result<Decimal> {
+        Ok(bps_to_rate(self.market(market_id)?.mmr_bps))
+    }
+
+    /// Initial margin required to place the unfilled part of `order` at `reference_price`.
+    pub fn quote_order(&self, order: &Order, reference_price: Decimal) -> Result<Decimal> {
+        let imr = self.initial_margin_rate(&order.user_address, order.market_id)?;
+        Ok((self.increasing_size(order) * reference_price * imr).ceil())
+    }
+
+    /// Part of the unfilled size of `order` that adds exposure, after netting
+    /// against the user's position and resting orders on the same side.
+    fn increasing_size(&self, order: &Order) -> Decimal {
+        let remaining_size = order.size - order.filled_size;
+        let position_size = self.position_size(&order.user_address, order.market_id);
+        let resting_same_side = self.resting_same_side(order);
+
+        // Part of the opposite position this order can still close before it adds exposure
+        let reducible = match order.side {
+            OrderSide::Buy => (-position_size - resting_same_side).max(Decimal::ZERO),
+            OrderSide::Sell => (position_size - resting_same_side).max(Decimal::ZERO),
+        };
+        (remaining_size - reducible).max(Decimal::ZERO)
+    }
+
+    fn position_size(&self, user_address: &str, market_id: u64) -> Decimal {
+        self.position(user_address, market_id)
+            .map(|p| p.size)
+            .unwrap_or_default()
+    }
+
+    fn resting_same_side(&self, order: &Order) -> Decimal {
+        self.reservations
+            .values()
+            .filter(|r| {
+                r.user_address == order.user_address
+                    && r.market_id == order.market_id
+                    && r.side == order.side
+                    && r.remaining_size > Decimal::ZERO
+            })
+            .map(|r| r.remaining_size)
+            .sum()
+    }
+
+    /// Total long size in a market, which equals total short size.
+    pub fn open_interest(&self, market_id: u64) -> Decimal {
+        self.positions
+            .values()
+            .filter(|p| p.market_id == market_id && p.size.is_sign_positive())
+            .map(|p| p.size)
+            .sum()
+    }
+
+    /// Enforce the market's open-interest cap and per-user position limits
+    /// against the worst case where the order and every resting order that
+    /// could add exposure are filled.
+    pub fn check_risk_limits(&self, order: &Order, reference_price: Decimal) -> Result<()> {
+        let market = self.market(order.market_id)?;
+        let increasing = self.increasing_size(order);
+        if increasing.is_zero() {
+            return Ok(());
+        }
+
+        let direction = match order.side {
+            OrderSide::Buy => Decimal::ONE,
+            OrderSide::Sell => Decimal::NEGATIVE_ONE,
+        };
+        let remaining_size = order.size - order.filled_size;
+        let position_size = self.position_size(&order.user_address, order.market_id);
+        let worst_case_size =
+            (position_size + direction * (self.resting_same_side(order) + remaining_size)).abs();
+
+        if let Some(max_size) = to_limit(market.max_position_size) {
+            if worst_case_size > max_size {
+                return Err(anyhow!("Position size {} would exceed limit {} in market {}",
+                    worst_case_size, max_size, order.market_id));
+            }
+        }
+
+        if let Some(max_notional) = to_limit(market.max_position_notional) {
+            let notional = worst_case_size * reference_price;
+            if notional > max_notional {
+                return Err(anyhow!("Position notional {} would exceed limit {} in market {}",
+                    notional, max_notional, order.market_id));
+            }
+        }
+
+        if let Some(max_open_interest) = to_limit(market.max_open_interest) {
+            let resting_increasing: Decimal = self
+                .reservations
+                .values()
+                .filter(|r| r.market_id == order.market_id)
+                .map(|r| r.increasing_size)
+                .sum();
+            let projected = self.open_interest(order.market_id) + resting_increasing + increasing;
+            if projected > max_open_interest {
+                return Err(anyhow!("Open interest {} would exceed cap {} in market {}",
+                    projected, max_open_interest, order.market_id));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn reserve(&mut self, order: &Order, amount: Decimal) {
+        debug!("Reserving {} margin for order {}", amount, order.id);
+        let increasing_size = self.increasing_size(order);
+        self.reservations.insert(order.id, Reservation {
+            user_address: order.user_address.clone(),
+            market_id: order.market_id,
+            side: order.side.clone(),
+            remaining_size: order.size - order.filled_size,
+            increasing_size,
+            amount,
+        });
+    }
+
+    /// Drop the reservation of an order that is no longer resting and return what was left of it.
+    pub fn release(&mut self, order_id: Uuid) -> Decimal {
+        match self.reservations.remove(&order_id) {
+            Some(reservation) => {
+                debug!("Released {} margin for order {}", reservation.amount, order_id);
+                reservation.amount
+            }
+            None => Decimal::ZERO,
+        }
+    }
+
+    /// Update positions for a set of fills and consume the matching part of each order's reservation.
+    pub fn apply_trades(&mut self, trades: &[Trade]) {
+        for trade in trades {
+            self.apply_trade_to_positions(trade);
+            self.consume(trade.taker_order_id, trade.size);
+            self.consume(trade.maker_order_id, trade.size);
+        }
+    }
+
+    fn apply_trade_to_positions(&mut self, trade: &Trade) {
+        let maker_side = match trade.side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        };
+
+        self.positions
+            .entry((trade.taker_address.clone(), trade.market_id))
+            .or_insert_with(|| Position::new(&trade.taker_address, trade.market_id))
+            .apply_fill(&trade.side, trade.size, trade.price);
+        self.positions
+            .entry((trade.maker_address.clone(), trade.market_id))
+            .or_insert_with(|| Position::new(&trade.maker_address, trade.market_id))
+            .apply_fill(&maker_side, trade.size, trade.price);
+    }
+
+    fn consume(&mut self, order_id: Uuid, fill_size: Decimal) {
+        let Some(reservation) = self.reservations.get_mut(&order_id) else {
+            return;
+        };
+
+        if fill_size >= reservation.remaining_size {
+            self.reservations.remove(&order_id);
+            return;
+        }
+
+        let consumed = reservation.amount * fill_size / reservation.remaining_size;
+        reservation.amount -= consumed;
+        reservation.increasing_size -= reservation.increasing_size * fill_size / reservation.remaining_size;
+        reservation.remaining_size -= fill_size;
+    }
+
+    pub fn position(&self, user_address: &str, market_id: u64) -> Option<&Position> {
+        self.positions.get(&(user_address.to_string(), market_id))
+    }
+
+    pub fn positions_for_user(&self, user_address: &str) -> Vec<&Position> {
+        self.positions
+            .values()
+            .filter(|p| p.user_address == user_address)
+            .collect()
+    }
+
+    /// Users holding a non-zero position in any market.
+    pub fn users_with_positions(&self) -> Vec<String> {
+        let mut users: Vec<String> = self
+            .positions
+            .values()
+            .filter(|p| !p.size.is_zero())
+            .map(|p| p.user_address.clone())
+            .collect();
+        users.sort();
+        users.dedup();
+        users
+    }
+
+    /// Health of the cross-margin account: collateral not allocated to
+    /// isolated positions plus cross unrealized PnL, against the maintenance
+    /// margin of cross positions. Markets without a mark price are valued at entry.
+    pub fn account_health(
+        &self,
+        user_address: &str,
+        collateral: Decimal,
+        mark_prices: &HashMap<u64, Decimal>,
+    ) -> AccountHealth {
+        let mut unrealized_pnl = Decimal::ZERO;
+        let mut maintenance_margin = Decimal::ZERO;
+        let mut isolated_margin = Decimal::ZERO;
+
+        for position in self.positions_for_user(user_address) {
+            if self.margin_mode(user_address, position.market_id) == MarginMode::Isolated {
+                isolated_margin += self.initial_margin(position);
+                continue;
+            }
+
+            let mark_price = mark_price_or_entry(position, mark_prices);
+            unrealized_pnl += position.unrealized_pnl(mark_price);
+            maintenance_margin += self.maintenance_margin(position, mark_price);
+        }
+
+        AccountHealth {
+            equity: collateral - isolated_margin + unrealized_pnl,
+            unrealized_pnl,
+            maintenance_margin,
+        }
+    }
+
+    /// Health of each isolated position: its allocated margin plus unrealized
+    /// PnL against its own maintenance margin.
+    pub fn isolated_health(
+        &self,
+        user_address: &str,
+        mark_prices: &HashMap<u64, Decimal>,
+    ) -> Vec<(u64, AccountHealth)> {
+        self.positions_for_user(user_address)
+            .into_iter()
+            .filter(|p| !p.size.is_zero())
+            .filter(|p| self.margin_mode(user_address, p.market_id) == MarginMode::Isolated)
+            .map(|p| {
+                let mark_price = mark_price_or_entry(p, mark_prices);
+                let unrealized_pnl = p.unrealized_pnl(mark_price);
+                (p.market_id, AccountHealth {
+                    equity: self.initial_margin(p) + unrealized_pnl,
+                    unrealized_pnl,
+                    maintenance_margin: self.maintenance_margin(p, mark_price),
+                })
+            })
+            .collect()
+    }
+
+    /// Collateral an account must hold for an order needing `order_margin` to be
+    /// accepted. Cross orders must also cover unrealized losses on cross
+    /// positions; isolated orders are walled off from them.
+    pub fn pre_trade_requirement(
+        &self,
+        order: &Order,
+        order_margin: Decimal,
+        mark_prices: &HashMap<u64, Decimal>,
+    ) -> Decimal {
+        let mut required = self.used_margin(&order.user_address) + order_margin;
+
+        if self.margin_mode(&order.user_address, order.market_id) == MarginMode::Cross {
+            let cross_pnl: Decimal = self
+                .positions_for_user(&order.user_address)
+                .into_iter()
+                .filter(|p| self.margin_mode(&order.user_address, p.market_id) == MarginMode::Cross)
+                .map(|p| p.unrealized_pnl(mark_price_or_entry(p, mark_prices)))
+                .sum();
+            required += (-cross_pnl).max(Decimal::ZERO);
+        }
+
+        required
+    }
+
+    /// Check that `collateral` covers the pre-trade requirement of an order
+    /// needing `order_margin`.
+    pub fn check_collateral(
+        &self,
+        order: &Order,
+        order_margin: Decimal,
+        mark_prices: &HashMap<u64, Decimal>,
+        collateral: Decimal,
+    ) -> Result<()> {
+        let required = self.pre_trade_requirement(order, order_margin, mark_prices);
+        if required > collateral {
+            return Err(anyhow!("required {}, available {}", required, collateral));
+        }
+        Ok(())
+    }
+
+    /// Risk view of each open position. Cross positions share the account
+    /// health, isolated ones are measured against their own margin.
+    pub fn position_risks(
+        &self,
+        user_address: &str,
+        collateral: Decimal,
+        mark_prices: &HashMap<u64, Decimal>,
+    ) -> Vec<PositionRisk> {
+        let cross_health = self.account_health(user_address, collateral, mark_prices);
+        let isolated_health: HashMap<u64, AccountHealth> = self
+            .isolated_health(user_address, mark_prices)
+            .into_iter()
+            .collect();
+
+        let mut risks: Vec<PositionRisk> = self
+            .positions_for_user(user_address)
+            .into_iter()
+            .filter(|p| !p.size.is_zero())
+            .map(|p| {
+                let mark_price = mark_price_or_entry(p, mark_prices);
+                let margin_mode = self.margin_mode(user_address, p.market_id);
+                let health = match margin_mode {
+                    MarginMode::Cross => &cross_health,
+                    MarginMode::Isolated => isolated_health.get(&p.market_id).unwrap_or(&cross_health),
+                };
+                let mmr = self.maintenance_margin_rate(p.market_id).unwrap_or(Decimal::ONE);
+                let notional = p.notional(mark_price);
+
+                PositionRisk {
+                    market_id: p.market_id,
+                    size: p.size,
+                    entry_price: p.entry_price,
+                    mark_price,
+                    notional,
+                    unrealized_pnl: p.unrealized_pnl(mark_price),
+                    realized_pnl: p.realized_pnl,
+                    margin_mode,
+                    leverage: self.leverage(user_address, p.market_id).unwrap_or(1),
+                    effective_leverage: (health.equity > Decimal::ZERO).then(|| notional / health.equity),
+                    initial_margin: self.initial_margin(p),
+                    maintenance_margin: self.maintenance_margin(p, mark_price),
+                    margin_ratio: health.margin_ratio(),
+                    liquidation_price: liquidation_price(p, mark_price, mmr, health),
+                }
+            })
+            .collect();
+
+        risks.sort_by_key(|r| r.market_id);
+        risks
+    }
+
+    fn initial_margin(&self, position: &Position) -> Decimal {
+        let imr = self
+            .initial_margin_rate(&position.user_address, position.market_id)
+            .unwrap_or(Decimal::ONE);
+        position.notional(position.entry_price) * imr
+    }
+
+    fn maintenance_margin(&self, position: &Position, mark_price: Decimal) -> Decimal {
+        let mmr = self
+            .maintenance_margin_rate(position.market_id)
+            .unwrap_or(Decimal::ONE);
+        position.notional(mark_price) * mmr
+    }
+
+    /// Price at which each of the user's positions in `market_ids` exhausts its
+    /// share of `health.equity`. The equity is shared out by maintenance margin,
+    /// so closing every position at its bankruptcy price loses exactly the equity.
+    pub fn bankruptcy_prices(
+        &self,
+        user_address: &str,
+        health: &AccountHealth,
+        market_ids: &[u64],
+        mark_prices: &HashMap<u64, Decimal>,
+    ) -> HashMap<u64, Decimal> {
+        let positions: Vec<&Position> = self
+            .positions_for_user(user_address)
+            .into_iter()
+            .filter(|p| !p.size.is_zero() && market_ids.contains(&p.market_id))
+            .collect();
+        let margins: Vec<Decimal> = positions
+            .iter()
+            .map(|p| self.maintenance_margin(p, mark_price_or_entry(p, mark_prices)))
+            .collect();
+        let total: Decimal = margins.iter().sum();
+
+        positions
+            .iter()
+            .zip(margins)
+            .map(|(p, margin)| {
+                let share = if total.is_zero() {
+                    Decimal::ONE / Decimal::from(positions.len())
+                } else {
+                    margin / total
+                };
+                let mark_price = mark_price_or_entry(p, mark_prices);
+                (p.market_id, (mark_price - health.equity * share / p.size).max(Decimal::ZERO))
+            })
+            .collect()
+    }
+
+    /// Profitable positions on one side of a market, ordered by ADL priority.
+    /// Cross positions are levered against the account equity in `equities`,
+    /// isolated ones against their own margin; positions without positive
+    /// equity are left out.
+    pub fn adl_ranking(
+        &self,
+        market_id: u64,
+        long: bool,
+        mark_price: Decimal,
+        equities: &HashMap<String, Decimal>,
+    ) -> Vec<AdlCandidate> {
+        let mut candidates: Vec<AdlCandidate> = self
+            .positions
+            .values()
+            .filter(|p| p.market_id == market_id && !p.size.is_zero())
+            .filter(|p| p.size.is_sign_positive() == long)
+            .filter_map(|p| {
+                let pnl = p.unrealized_pnl(mark_price);
+                let cost = p.notional(p.entry_price);
+                let equity = match self.margin_mode(&p.user_address, p.market_id) {
+                    MarginMode::Cross => equities.get(&p.user_address).copied().unwrap_or_default(),
+                    MarginMode::Isolated => self.initial_margin(p) + pnl,
+                };
+                if pnl <= Decimal::ZERO || cost.is_zero() || equity <= Decimal::ZERO {
+                    return None;
+                }
+
+                let leverage = p.notional(mark_price) / equity;
+                Some(AdlCandidate {
+                    user_address: p.user_address.clone(),
+                    market_id,
+                    size: p.size,
+                    score: pnl / cost * leverage,
+                })
+            })
+            .collect();
+
+        candidates.sort_by(|a, b| b.score.cmp(&a.score));
+        candidates
+    }
+
+    /// Margin reserved for a user's resting orders.
+    pub fn reserved_margin(&self, user_address: &str) -> Decimal {
+        self.reservations
+            .values()
+            .filter(|r| r.user_address == user_address)
+            .map(|r| r.amount)
+            .sum()
+    }
+
+    /// Initial margin held by a user's open positions, valued at entry.
+    pub fn position_margin(&self, user_address: &str) -> Decimal {
+        self.positions_for_user(user_address)
+            .into_iter()
+            .map(|p| self.initial_margin(p))
+            .sum()
+    }
+
+    pub fn used_margin(&self, user_address: &str) -> Decimal {
+        self.reserved_margin(user_address) + self.position_margin(user_address)
+    }
+
+    /// Collateral that can leave the vault: cross equity without unrealized
+    /// profits, less open-order reservations and cross maintenance margin.
+    /// Isolated positions keep their allocated margin.
+    pub fn withdrawable(
+        &self,
+        user_address: &str,
+        collateral: Decimal,
+        mark_prices: &HashMap<u64, Decimal>,
+    ) -> Decimal {
+        let health = self.account_health(user_address, collateral, mark_prices);
+        health.equity
+            - health.unrealized_pnl.max(Decimal::ZERO)
+            - self.reserved_margin(user_address)
+            - health.maintenance_margin
+    }
+}
+
+/// Mark price at which `health` reaches maintenance if only this position's
+/// market moves: equity and maintenance both change linearly with the mark.
+fn liquidation_price(
+    position: &Position,
+    mark_price: Decimal,
+    mmr: Decimal,
+    health: &AccountHealth,
+) -> Option<Decimal> {
+    let slope = position.size - position.size.abs() * mmr;
+    if slope.is_zero() {
+        return None;
+    }
+
+    let price = mark_price + (health.maintenance_margin - health.equity) / slope;
+    (price > Decimal::ZERO).then_some(price)
+}
+
+fn mark_price_or_entry(position: &Position, mark_prices: &HashMap<u64, Decimal>) -> Decimal {
+    mark_prices
+        .get(&position.market_id)
+        .copied()
+        .unwrap_or(position.entry_price)
+}
+
+fn to_limit(value: Option<f64>) -> Option<Decimal> {
+    value.and_then(Decimal::from_f64)
+}
+
+fn bps_to_rate(bps: u64) -> Decimal {
+    Decimal::from(bps) / Decimal::from(10_000)
+}
+
+/// Round a margin amount up to whole collateral units for a vault deposit.
+pub fn to_collateral_units(amount: Decimal) -> u64 {
+    amount.ceil().to_u64().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{OrderStatus, OrderType};
+
+    fn engine() -> MarginEngine {
+        MarginEngine::new(&[MarketConfig {
+            market_id: 1,
+            imr_bps: 500,
+            mmr_bps: 250,
+            max_leverage: 20,
+            default_leverage: 10,
+            max_open_interest: None,
+            max_position_size: None,
+            max_position_notional: None,
+            settlement_price_tolerance: None,
+        }])
+    }
+
+    fn order(user: &str, side: OrderSide, size: i64) -> Order {
+        Order {
+            id: Uuid::new_v4(),
+            user_address: user.to_string(),
+            market_id: 1,
+            side,
+            order_type: OrderType::Limit,
+            size: Decimal::from(size),
+            price: Some(Decimal::from(100)),
+            filled_size: Decimal::ZERO,
+            status: OrderStatus::Pending,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            expires_at: None,
+        }
+    }
+
+    fn fill(taker: &Order, maker: &Order, size: i64) -> Trade {
+        Trade {
+            id: Uuid::new_v4(),
+            market_id: 1,
+            taker_order_id: taker.id,
+            maker_order_id: maker.id,
+            taker_address: taker.user_address.clone(),
+            maker_address: maker.user_address.clone(),
+            size: Decimal::from(size),
+            price: Decimal::from(100),
+            side: taker.side.clone(),
+            created_at: chrono::Utc::now(),
+            settlement_batch_id: None,
+        }
+    }
+
+    #[test]
+    fn quote_uses_the_stricter_of_imr_and_leverage() {
+        let mut engine = engine();
+        let buy = order("alice", OrderSide::Buy, 2);
+        // 1 / 10x is stricter than the 5% IMR
+        assert_eq!(engine.quote_order(&buy, Decimal::from(100)).unwrap(), Decimal::from(20));
+
+        engine.apply_margin_setting(engine.prepare_margin_setting("alice", 1, None, Some(20)).unwrap());
+        assert_eq!(engine.quote_order(&buy, Decimal::from(100)).unwrap(), Decimal::from(10));
+    }
+
+    #[test]
+    fn reducing_an_opposite_position_is_not_charged() {
+        let mut engine = engine();
+        let sell = order("alice", OrderSide::Sell, 3);
+        let counter = order("bob", OrderSide::Buy, 3);
+        engine.apply_trades(&[fill(&counter, &sell, 3)]);
+
+        // Closes the 3 short first, so only 2 add exposure
+        let buy = order("alice", OrderSide::Buy, 5);
+        assert_eq!(engine.quote_order(&buy, Decimal::from(100)).unwrap(), Decimal::from(20));
+    }
+
+    #[test]
+    fn resting_orders_use_up_the_reducible_size() {
+        let mut engine = engine();
+        let sell = order("alice", OrderSide::Sell, 3);
+        let counter = order("bob", OrderSide::Buy, 3);
+        engine.apply_trades(&[fill(&counter, &sell, 3)]);
+
+        let resting = order("alice", OrderSide::Buy, 2);
+        let amount = engine.quote_order(&resting, Decimal::from(100)).unwrap();
+        assert_eq!(amount, Decimal::ZERO);
+        engine.reserve(&resting, amount);
+
+        // Only 1 of the short is left for this order to close
+        let buy = order("alice", OrderSide::Buy, 2);
+        assert_eq!(engine.quote_order(&buy, Decimal::from(100)).unwrap(), Decimal::from(10));
+    }
+
+    #[test]
+    fn partial_fill_consumes_pro_rata_and_release_returns_the_rest() {
+        let mut engine = engine();
+        let buy = order("alice", OrderSide::Buy, 4);
+        let amount = engine.quote_order(&buy, Decimal::from(100)).unwrap();
+        engine.reserve(&buy, amount);
+        assert_eq!(engine.reserved_margin("alice"), Decimal::from(40));
+
+        let sell = order("bob", OrderSide::Sell, 1);
+        engine.apply_trades(&[fill(&sell, &buy, 1)]);
+        assert_eq!(engine.reserved_margin("alice"), Decimal::from(30));
+
+        assert_eq!(engine.release(buy.id), Decimal::from(30));
+        assert_eq!(engine.reserved_margin("alice"), Decimal::ZERO);
+        assert_eq!(engine.release(buy.id), Decimal::ZERO);
+    }
+
+    #[test]
+    fn full_fill_drops_the_reservation() {
+        let mut engine = engine();
+        let buy = order("alice", OrderSide::Buy, 2);
+        let amount = engine.quote_order(&buy, Decimal::from(100)).unwrap();
+        engine.reserve(&buy, amount);
+
+        let sell = order("bob", OrderSide::Sell, 2);
+        engine.apply_trades(&[fill(&sell, &buy, 2)]);
+
+        assert_eq!(engine.reserved_margin("alice"), Decimal::ZERO);
+        assert_eq!(engine.position("alice", 1).unwrap().size, Decimal::from(2));
+        // The filled size is now held as position margin instead
+        assert_eq!(engine.used_margin("alice"), Decimal::from(20));
+    }
+
+    #[test]
+    fn withdrawable_excludes_reservations_and_unrealized_profit() {
+        let mut engine = engine();
+        let buy = order("alice", OrderSide::Buy, 2);
+        let sell = order("bob", OrderSide::Sell, 2);
+        engine.apply_trades(&[fill(&buy, &sell, 2)]);
+
+        let resting = order("alice", OrderSide::Buy, 1);
+        let amount = engine.quote_order(&resting, Decimal::from(100)).unwrap();
+        engine.reserve(&resting, amount);
+
+        // Long 2 from 100 marked at 110: 20 profit, 5.5 maintenance, 10 reserved
+        let marks = HashMap::from([(1, Decimal::from(110))]);
+        assert_eq!(
+            engine.withdrawable("alice", Decimal::from(1_000), &marks),
+            Decimal::from(1_000) - Decimal::new(55, 1) - Decimal::from(10)
+        );
+    }
+
+    #[test]
+    fn bankruptcy_prices_share_the_equity_by_maintenance_margin() {
+        let mut engine = MarginEngine::new(&[
+            MarketConfig { market_id: 1, mmr_bps: 250, ..engine().markets[&1].clone() },
+            MarketConfig { market_id: 2, mmr_bps: 500, ..engine().markets[&1].clone() },
+        ]);
+        let buy = order("alice", OrderSide::Buy, 2);
+        let sell = order("bob", OrderSide::Sell, 2);
+        engine.apply_trades(&[
+            fill(&buy, &sell, 2),
+            Trade { market_id: 2, ..fill(&buy, &sell, 1) },
+        ]);
+
+        // Long 2 and long 1 at 100: 5 maintenance each, so each gets half the 30 equity
+        let marks = HashMap::from([(1, Decimal::from(100)), (2, Decimal::from(100))]);
+        let health = engine.account_health("alice", Decimal::from(30), &marks);
+        let prices = engine.bankruptcy_prices("alice", &health, &[1, 2], &marks);
+        assert_eq!(prices[&1], Decimal::new(925, 1));
+        assert_eq!(prices[&2], Decimal::from(85));
+
+        let loss: Decimal = [(1, 2), (2, 1)]
+            .iter()
+            .map(|(market_id, size)| (marks[market_id] - prices[market_id]) * Decimal::from(*size))
+            .sum();
+        assert_eq!(loss, health.equity);
+    }
+}
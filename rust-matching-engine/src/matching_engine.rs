@@ -251,6 +251,15 @@ impl MatchingEngine {
         Ok(trades)
     }
 
+    /// A user's orders still resting on any book.
+    pub fn open_orders_for_user(&self, user_address: &str) -> Vec<&Order> {
+        self.order_books
+            .values()
+            .flat_map(|book| book.bids.iter().chain(book.asks.iter()))
+            .filter(|o| o.user_address == user_address)
+            .collect()
+    }
+
     pub fn get_order_book(&self, market_id: u64) -> Option<&OrderBook> {
         self.order_books.get(&market_id)
     }
@@ -314,6 +323,42 @@ impl OrderBook {
     pub fn get_asks(&self) -> &[Order] {
         &self.asks
     }
+
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.bids.first().and_then(|o| o.price)
+    }
+
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.asks.first().and_then(|o| o.price)
+    }
+
+    pub fn mid_price(&self) -> Option<Decimal> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / Decimal::TWO),
+            _ => None,
+        }
+    }
+
+    /// Worst price a taker order of `size` would reach when sweeping the opposite side.
+    /// Falls back to the deepest resting price when the book cannot fill the whole size.
+    pub fn estimate_fill_price(&self, side: &OrderSide, size: Decimal) -> Option<Decimal> {
+        let opposing_orders = match side {
+            OrderSide::Buy => &self.asks,
+            OrderSide::Sell => &self.bids,
+        };
+
+        let mut remaining_size = size;
+        let mut worst_price = None;
+        for order in opposing_orders {
+            if remaining_size <= Decimal::ZERO {
+                break;
+            }
+            worst_price = order.price;
+            remaining_size -= order.size - order.filled_size;
+        }
+
+        worst_price
+    }
 }
 
 impl MatchingEngine {
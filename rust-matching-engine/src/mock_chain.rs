@@ -0,0 +1,618 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    chain::{
+        ChainClient, ChainError, ChainPosition, CommittedTransaction, FeeParams, GasEstimate,
+        MarketParams, OraclePrice, PreparedTransaction, TransactionStatus,
+    },
+    config::{AptosConfig, MockChainConfig},
+    models::{LiquidationPenalty, NetDelta, SettlementBatch},
+    signer_pool::SettlementSigner,
+};
+
+// Abort codes from hyperperp::errors
+const E_INSUFFICIENT_MARGIN: u64 = 10;
+const E_PRICE_OUT_OF_BOUNDS: u64 = 11;
+const E_BATCH_EXPIRED: u64 = 13;
+const E_BATCH_ALREADY_APPLIED: u64 = 14;
+const E_INSUFFICIENT_COLLATERAL: u64 = 23;
+/// 0x1::coin::EINSUFFICIENT_BALANCE
+const E_INSUFFICIENT_BALANCE: u64 = 0x10006;
+
+/// Fixed gas of any transaction, and BCS sizes of the settlement arguments
+const BASE_GAS: u64 = 500;
+const BASE_PAYLOAD_BYTES: usize = 96;
+const FILL_BYTES: usize = 112;
+const NET_DELTA_BYTES: usize = 139;
+
+#[derive(Debug, Default)]
+struct MockAccount {
+    balance: u64,
+    collateral: u64,
+    sequence_number: u64,
+}
+
+/// Entry function call of a mock transaction, as the fullnode would report it.
+struct MockCall {
+    function: String,
+    type_arguments: Vec<String>,
+    arguments: Vec<serde_json::Value>,
+}
+
+#[derive(Debug)]
+struct MockTransaction {
+    transaction: CommittedTransaction,
+    status: TransactionStatus,
+    committed_at: Instant,
+}
+
+/// An admin transaction signed by `sign_withdrawal` and not submitted yet.
+struct MockPrepared {
+    sequence_number: u64,
+    call: MockCall,
+    user_address: String,
+    amount: u64,
+}
+
+#[derive(Default)]
+struct MockState {
+    accounts: HashMap<String, MockAccount>,
+    transactions: HashMap<String, MockTransaction>,
+    prepared: HashMap<String, MockPrepared>,
+    /// Batch and liquidation ids recorded by committed or pending settlement transactions
+    applied_batches: HashMap<Uuid, Instant>,
+    oracle_prices: HashMap<u64, OraclePrice>,
+    submitted: u64,
+}
+
+/// An in-process stand-in for the fullnode and the hyperperp contracts.
+///
+/// Wallet balances, vault collateral and sequence numbers live in memory.
+/// Submitted transactions stay pending for `confirmation_delay_ms`, then
+/// commit with the outcome the contract would have produced: settlement
+/// batches are checked for expiry, price bounds and replays, and every
+/// `fail_every`-th transaction aborts. The response to every
+/// `lose_response_every`-th admin transaction is lost after it was accepted.
+pub struct MockChain {
+    contract_address: String,
+    admin_address: String,
+    /// Admin account shared with the signer pool, as `AptosClient` uses it
+    admin: Arc<SettlementSigner>,
+    insurance_address: String,
+    usdc_token_type: String,
+    config: MockChainConfig,
+    state: Mutex<MockState>,
+}
+
+impl MockChain {
+    pub fn new(config: &AptosConfig, admin: Arc<SettlementSigner>) -> Result<Self> {
+        info!("Mock chain initialized for admin: {}", config.admin_address);
+        Ok(Self {
+            contract_address: config.contract_address.clone(),
+            admin_address: config.admin_address.clone(),
+            admin,
+            insurance_address: config.insurance_address.clone().unwrap_or_else(|| config.admin_address.clone()),
+            usdc_token_type: config.usdc_token_type.clone(),
+            config: config.mock_chain.clone(),
+            state: Mutex::new(MockState::default()),
+        })
+    }
+
+    async fn latency(&self) {
+        if self.config.latency_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(self.config.latency_ms)).await;
+        }
+    }
+
+    fn abort(&self, module: &str, code: u64) -> String {
+        format!("Move abort in {}::{}: 0x{:x}", self.contract_address, module, code)
+    }
+
+    fn call(&self, module: &str, function: &str, type_arguments: Vec<String>, arguments: Vec<serde_json::Value>) -> MockCall {
+        MockCall {
+            function: format!("{}::{}::{}", self.contract_address, module, function),
+            type_arguments,
+            arguments,
+        }
+    }
+
+    fn settlement_gas(&self, batch: &SettlementBatch, net_deltas: Option<&[NetDelta]>) -> u64 {
+        let items = net_deltas.map_or(batch.trades.len(), |d| d.len()) as u64;
+        BASE_GAS + items * self.config.gas_per_fill
+    }
+
+    /// The abort a settlement batch would hit on-chain, if any.
+    fn settlement_abort(&self, state: &MockState, batch: &SettlementBatch, net_deltas: Option<&[NetDelta]>) -> Option<String> {
+        if state.applied_batches.contains_key(&batch.id) {
+            return Some(self.abort("perp_engine", E_BATCH_ALREADY_APPLIED));
+        }
+        if batch.expiry_timestamp < chrono::Utc::now().timestamp() as u64 {
+            return Some(self.abort("perp_engine", E_BATCH_EXPIRED));
+        }
+        // Netted batches are checked over the price range of each delta's fills
+        let out_of_bounds = match net_deltas {
+            Some(deltas) => deltas.iter().any(|d| d.min_price < batch.min_price || d.max_price > batch.max_price),
+            None => batch.trades.iter().any(|t| t.price < batch.min_price || t.price > batch.max_price),
+        };
+        if out_of_bounds {
+            return Some(self.abort("perp_engine", E_PRICE_OUT_OF_BOUNDS));
+        }
+        None
+    }
+
+    /// Record a transaction from `sender`, consuming its sequence number.
+    ///
+    /// `apply` runs the state change and returns the abort status on failure;
+    /// it is skipped when the transaction is picked for an injected failure.
+    fn commit(
+        &self,
+        state: &mut MockState,
+        sender: &str,
+        call: MockCall,
+        gas_used: u64,
+        apply: impl FnOnce(&mut MockState) -> Option<String>,
+    ) -> String {
+        let tx_hash = format!("0x{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        self.commit_as(state, tx_hash, sender, call, gas_used, apply)
+    }
+
+    /// `commit` under a hash the transaction was given when it was signed.
+    fn commit_as(
+        &self,
+        state: &mut MockState,
+        tx_hash: String,
+        sender: &str,
+        call: MockCall,
+        gas_used: u64,
+        apply: impl FnOnce(&mut MockState) -> Option<String>,
+    ) -> String {
+        state.submitted += 1;
+        let injected = self.config.fail_every > 0 && state.submitted.is_multiple_of(self.config.fail_every);
+
+        let abort = if injected {
+            Some("Injected mock chain failure".to_string())
+        } else {
+            apply(state)
+        };
+
+        self.account(state, sender).sequence_number += 1;
+
+        let status = match abort {
+            Some(vm_status) => {
+                warn!("Mock transaction from {} will fail: {}", sender, vm_status);
+                TransactionStatus::Failed { vm_status, gas_used }
+            }
+            None => TransactionStatus::Success { vm_status: "Executed successfully".to_string(), gas_used },
+        };
+
+        let (success, vm_status) = match &status {
+            TransactionStatus::Success { vm_status, .. } => (true, vm_status.clone()),
+            TransactionStatus::Failed { vm_status, .. } => (false, vm_status.clone()),
+            TransactionStatus::Pending => (false, String::new()),
+        };
+        state.transactions.insert(tx_hash.clone(), MockTransaction {
+            transaction: CommittedTransaction {
+                hash: tx_hash.clone(),
+                sender: sender.to_string(),
+                success,
+                vm_status,
+                function: call.function,
+                type_arguments: call.type_arguments,
+                arguments: call.arguments,
+            },
+            status,
+            committed_at: Instant::now() + Duration::from_millis(self.config.confirmation_delay_ms),
+        });
+        tx_hash
+    }
+
+    /// Record a transaction from an account with a locally tracked sequence
+    /// number, rejecting it when that number does not match the chain's.
+    async fn commit_tracked(
+        &self,
+        signer: &SettlementSigner,
+        call: MockCall,
+        gas_used: u64,
+        apply: impl FnOnce(&mut MockState) -> Option<String>,
+    ) -> Result<String> {
+        let mut next_sequence = signer.next_sequence.lock().await;
+        let mut state = self.state.lock().await;
+
+        let sender = signer.address.to_string();
+        let on_chain = self.account(&mut state, &sender).sequence_number;
+        let sequence_number = next_sequence.unwrap_or(on_chain);
+        if sequence_number != on_chain {
+            *next_sequence = None;
+            return Err(anyhow!(
+                "Transaction from {} rejected: sequence number {} does not match {}",
+                sender, sequence_number, on_chain
+            ));
+        }
+
+        let tx_hash = self.commit(&mut state, &sender, call, gas_used, apply);
+        *next_sequence = Some(sequence_number + 1);
+        debug!("Mock transaction {} from {} uses sequence {}", tx_hash, sender, sequence_number);
+        self.respond(&state, tx_hash)
+    }
+
+    /// The submitter's view of a transaction just accepted: its hash, unless
+    /// this is a `lose_response_every`-th transaction whose response is lost.
+    fn respond(&self, state: &MockState, tx_hash: String) -> Result<String> {
+        let every = self.config.lose_response_every;
+        if every > 0 && state.submitted.is_multiple_of(every) {
+            warn!("Mock response for transaction {} lost", tx_hash);
+            return Err(anyhow!("Connection closed before the submission response arrived"));
+        }
+        Ok(tx_hash)
+    }
+
+    fn account<'a>(&self, state: &'a mut MockState, address: &str) -> &'a mut MockAccount {
+        state.accounts.entry(address.to_string()).or_insert_with(|| MockAccount {
+            balance: self.config.initial_balance,
+            collateral: self.config.initial_collateral,
+            sequence_number: 0,
+        })
+    }
+
+    /// Move `amount` from the user's wallet into vault collateral.
+    async fn move_to_vault(&self, user_address: &str, amount: u64, call: MockCall) -> Result<String> {
+        self.latency().await;
+        let mut state = self.state.lock().await;
+        self.account(&mut state, user_address);
+
+        let abort = format!("Move abort in 0x1::coin: 0x{:x}", E_INSUFFICIENT_BALANCE);
+        let user = user_address.to_string();
+        let tx_hash = self.commit(&mut state, user_address, call, BASE_GAS, |state| {
+            let account = state.accounts.get_mut(&user)?;
+            if account.balance < amount {
+                return Some(abort);
+            }
+            account.balance -= amount;
+            account.collateral += amount;
+            None
+        });
+        Ok(tx_hash)
+    }
+
+    /// Move `amount` of the user's vault collateral back to their wallet, from the admin account.
+    async fn withdraw_from_vault(&self, user_address: &str, amount: u64, call: MockCall) -> Result<String> {
+        self.commit_tracked(&self.admin, call, BASE_GAS, self.vault_withdrawal(user_address, amount)).await
+    }
+
+    /// State change of `vault_coin::withdraw_for`.
+    fn vault_withdrawal(&self, user_address: &str, amount: u64) -> impl FnOnce(&mut MockState) -> Option<String> + '_ {
+        let abort = self.abort("vault_coin", E_INSUFFICIENT_COLLATERAL);
+        let user = user_address.to_string();
+        move |state| {
+            let account = self.account(state, &user);
+            if account.collateral < amount {
+                return Some(abort);
+            }
+            account.collateral -= amount;
+            account.balance += amount;
+            None
+        }
+    }
+
+    fn withdraw_call(&self, user_address: &str, amount: u64) -> MockCall {
+        self.call(
+            "vault_coin",
+            "withdraw_for",
+            vec![self.usdc_token_type.clone()],
+            vec![user_address.into(), amount.to_string().into()],
+        )
+    }
+}
+
+#[async_trait]
+impl ChainClient for MockChain {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn decode_vm_status(&self, vm_status: &str) -> ChainError {
+        ChainError::from_vm_status(vm_status, &self.contract_address)
+    }
+
+    async fn get_account_balance(&self, address: &str) -> Result<u64> {
+        self.latency().await;
+        let mut state = self.state.lock().await;
+        Ok(self.account(&mut state, address).balance)
+    }
+
+    async fn validate_user_balance(&self, user_address: &str, required_amount: u64) -> Result<bool> {
+        let balance = self.get_account_balance(user_address).await? / 1_000_000;
+        Ok(balance >= required_amount)
+    }
+
+    async fn get_user_collateral(&self, user_address: &str) -> Result<u64> {
+        self.latency().await;
+        let mut state = self.state.lock().await;
+        Ok(self.account(&mut state, user_address).collateral)
+    }
+
+    async fn freeze_user_funds(&self, user_address: &str, amount: u64, market_id: u64) -> Result<String> {
+        debug!("Mock freeze for user {}: {} in market {}", user_address, amount, market_id);
+        let call = self.call(
+            "vault_coin",
+            "deposit",
+            vec![self.usdc_token_type.clone()],
+            vec![self.admin_address.clone().into(), amount.to_string().into()],
+        );
+        self.move_to_vault(user_address, amount, call).await
+    }
+
+    async fn unfreeze_user_funds(&self, user_address: &str, amount: u64) -> Result<String> {
+        self.latency().await;
+        self.withdraw_from_vault(user_address, amount, self.withdraw_call(user_address, amount)).await
+    }
+
+    async fn deposit_funds(&self, user_address: &str, amount: u64) -> Result<String> {
+        let call = self.call(
+            "vault",
+            "deposit",
+            vec![],
+            vec![amount.to_string().into(), self.contract_address.clone().into()],
+        );
+        self.move_to_vault(user_address, amount, call).await
+    }
+
+    async fn sign_withdrawal(&self, user_address: &str, amount: u64) -> Result<PreparedTransaction> {
+        self.latency().await;
+        let mut next_sequence = self.admin.next_sequence.lock().await;
+        let mut state = self.state.lock().await;
+
+        let sender = self.admin.address.to_string();
+        let sequence_number = next_sequence.unwrap_or(self.account(&mut state, &sender).sequence_number);
+        let tx_hash = format!("0x{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        state.prepared.insert(tx_hash.clone(), MockPrepared {
+            sequence_number,
+            call: self.withdraw_call(user_address, amount),
+            user_address: user_address.to_string(),
+            amount,
+        });
+        // A signed transaction holds its sequence number until it is submitted or rejected
+        *next_sequence = Some(sequence_number + 1);
+
+        Ok(PreparedTransaction { tx_hash, sequence_number, bytes: Vec::new() })
+    }
+
+    async fn submit_prepared(&self, transaction: &PreparedTransaction) -> Result<()> {
+        self.latency().await;
+        let mut next_sequence = self.admin.next_sequence.lock().await;
+        let mut state = self.state.lock().await;
+
+        let Some(prepared) = state.prepared.remove(&transaction.tx_hash) else {
+            return Err(anyhow!("Transaction {} is unknown or was already submitted", transaction.tx_hash));
+        };
+        let sender = self.admin.address.to_string();
+        let on_chain = self.account(&mut state, &sender).sequence_number;
+        if prepared.sequence_number != on_chain {
+            *next_sequence = None;
+            return Err(anyhow!(
+                "Transaction from {} rejected: sequence number {} does not match {}",
+                sender, prepared.sequence_number, on_chain
+            ));
+        }
+
+        let apply = self.vault_withdrawal(&prepared.user_address, prepared.amount);
+        let tx_hash = self.commit_as(&mut state, transaction.tx_hash.clone(), &sender, prepared.call, BASE_GAS, apply);
+        debug!("Mock transaction {} from {} uses sequence {}", tx_hash, sender, prepared.sequence_number);
+        self.respond(&state, tx_hash).map(|_| ())
+    }
+
+    fn settlement_payload_size(&self, batch: &SettlementBatch, net_deltas: Option<&[NetDelta]>) -> Result<usize> {
+        Ok(BASE_PAYLOAD_BYTES + match net_deltas {
+            Some(deltas) => deltas.len() * NET_DELTA_BYTES,
+            None => batch.trades.len() * FILL_BYTES,
+        })
+    }
+
+    async fn simulate_settlement_batch(
+        &self,
+        _signer: &SettlementSigner,
+        batch: &SettlementBatch,
+        net_deltas: Option<&[NetDelta]>,
+        max_gas_amount: u64,
+    ) -> Result<GasEstimate> {
+        self.latency().await;
+        let state = self.state.lock().await;
+
+        let gas_needed = self.settlement_gas(batch, net_deltas);
+        let abort = if gas_needed > max_gas_amount {
+            Some("OUT_OF_GAS".to_string())
+        } else {
+            self.settlement_abort(&state, batch, net_deltas)
+        };
+
+        Ok(GasEstimate {
+            success: abort.is_none(),
+            vm_status: abort.unwrap_or_else(|| "Executed successfully".to_string()),
+            gas_used: gas_needed.min(max_gas_amount),
+        })
+    }
+
+    async fn submit_settlement_batch(
+        &self,
+        signer: &SettlementSigner,
+        batch: &SettlementBatch,
+        net_deltas: Option<&[NetDelta]>,
+        max_gas_amount: u64,
+    ) -> Result<String> {
+        self.latency().await;
+        let gas_needed = self.settlement_gas(batch, net_deltas);
+        let gas_used = gas_needed.min(max_gas_amount);
+        let committed_at = Instant::now() + Duration::from_millis(self.config.confirmation_delay_ms);
+        let function = if net_deltas.is_some() { "apply_net_batch_once_simple" } else { "apply_batch_once_simple" };
+        let call = self.call("perp_engine", function, vec![], vec![self.admin_address.clone().into()]);
+
+        let tx_hash = self.commit_tracked(signer, call, gas_used, |state| {
+            let abort = if gas_needed > max_gas_amount {
+                Some("OUT_OF_GAS".to_string())
+            } else {
+                self.settlement_abort(state, batch, net_deltas)
+            };
+            if abort.is_none() {
+                state.applied_batches.insert(batch.id, committed_at);
+            }
+            abort
+        }).await?;
+
+        info!("Mock settlement batch {} submitted: tx {}", batch.id, tx_hash);
+        Ok(tx_hash)
+    }
+
+    async fn is_settlement_batch_applied(&self, batch_id: Uuid) -> Result<bool> {
+        self.latency().await;
+        let state = self.state.lock().await;
+        Ok(state.applied_batches.get(&batch_id).is_some_and(|at| *at <= Instant::now()))
+    }
+
+    async fn submit_liquidation_penalty(&self, signer: &SettlementSigner, penalty: &LiquidationPenalty) -> Result<String> {
+        self.latency().await;
+        let committed_at = Instant::now() + Duration::from_millis(self.config.confirmation_delay_ms);
+        let call = self.call("perp_engine", "charge_penalty_once", vec![], vec![
+            self.admin_address.clone().into(),
+            penalty.user_address.clone().into(),
+            self.insurance_address.clone().into(),
+            penalty.amount.to_string().into(),
+        ]);
+
+        let tx_hash = self.commit_tracked(signer, call, BASE_GAS, |state| {
+            if state.applied_batches.contains_key(&penalty.liquidation_id) {
+                return Some(self.abort("perp_engine", E_BATCH_ALREADY_APPLIED));
+            }
+            let owner = self.account(state, &penalty.user_address);
+            if owner.collateral < penalty.amount {
+                return Some(self.abort("vault_coin", E_INSUFFICIENT_MARGIN));
+            }
+            owner.collateral -= penalty.amount;
+            self.account(state, &self.insurance_address).collateral += penalty.amount;
+            state.applied_batches.insert(penalty.liquidation_id, committed_at);
+            None
+        }).await?;
+
+        info!("Mock liquidation penalty {} submitted: tx {}", penalty.liquidation_id, tx_hash);
+        Ok(tx_hash)
+    }
+
+    async fn submit_funding_rate(&self, market_id: u64, rate: Decimal) -> Result<String> {
+        self.latency().await;
+        debug!("Mock funding rate for market {}: {}", market_id, rate);
+        let call = self.call("perp_engine", "apply_funding_simple", vec![], vec![market_id.to_string().into()]);
+        self.commit_tracked(&self.admin, call, BASE_GAS, |_| None).await
+    }
+
+    async fn push_oracle_price(
+        &self,
+        market_id: u64,
+        price: Decimal,
+        confidence: Decimal,
+        timestamp: u64,
+    ) -> Result<String> {
+        self.latency().await;
+        debug!("Mock oracle price for market {}: {} ± {}", market_id, price, confidence);
+        let call = self.call("oracle_adapter", "push_price", vec![], vec![market_id.to_string().into()]);
+        self.commit_tracked(&self.admin, call, BASE_GAS, |state| {
+            state.oracle_prices.insert(market_id, OraclePrice { market_id, price, confidence, timestamp });
+            None
+        }).await
+    }
+
+    async fn get_positions(&self, _user_address: &str, _market_ids: &[u64]) -> Result<Vec<ChainPosition>> {
+        Err(anyhow!("Mock chain does not model positions"))
+    }
+
+    async fn get_market_params(&self, market_id: u64) -> Result<MarketParams> {
+        Err(anyhow!("Mock chain does not model the market registry (market {})", market_id))
+    }
+
+    async fn get_oracle_price(&self, market_id: u64) -> Result<Option<OraclePrice>> {
+        self.latency().await;
+        Ok(self.state.lock().await.oracle_prices.get(&market_id).cloned())
+    }
+
+    async fn get_fee_params(&self) -> Result<FeeParams> {
+        Err(anyhow!("Mock chain does not model fee parameters"))
+    }
+
+    async fn get_transaction(&self, tx_hash: &str) -> Result<Option<CommittedTransaction>> {
+        self.latency().await;
+        let state = self.state.lock().await;
+        Ok(state.transactions.get(tx_hash)
+            .filter(|tx| tx.committed_at <= Instant::now())
+            .map(|tx| tx.transaction.clone()))
+    }
+
+    async fn check_transaction_status(&self, tx_hash: &str) -> Result<TransactionStatus> {
+        self.latency().await;
+        let state = self.state.lock().await;
+        Ok(match state.transactions.get(tx_hash) {
+            Some(tx) if tx.committed_at <= Instant::now() => tx.status.clone(),
+            _ => TransactionStatus::Pending,
+        })
+    }
+}
+
+/// Fixtures shared by the tests that run against the mock chain.
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use crate::{config::Config, database::Database, signer_pool::SignerPool};
+    use sqlx::{postgres::PgPoolOptions, Executor};
+
+    /// Default settings with a mock chain that commits instantly and signs with a throwaway key.
+    pub fn test_config() -> Config {
+        let mut config = Config::default();
+        config.aptos.mock_chain.latency_ms = 0;
+        config.aptos.mock_chain.confirmation_delay_ms = 0;
+        config.aptos.admin_key = serde_json::from_value(serde_json::json!({
+            "source": "inline",
+            "key": format!("0x{}", "11".repeat(32)),
+        })).unwrap();
+        config
+    }
+
+    /// A mock chain for `config`, and the signers it was built from.
+    pub async fn mock_chain(config: &AptosConfig) -> (MockChain, SignerPool) {
+        let signers = SignerPool::from_config(config).await.unwrap();
+        (MockChain::new(config, signers.admin().clone()).unwrap(), signers)
+    }
+
+    /// A database of its own on the server in `TEST_DATABASE_URL`.
+    pub struct ScratchDatabase {
+        pub database: Arc<Database>,
+        server_url: String,
+        name: String,
+    }
+
+    impl ScratchDatabase {
+        pub async fn create(prefix: &str) -> Self {
+            let server_url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set");
+            let name = format!("{}_test_{}", prefix, Uuid::new_v4().simple());
+            let server = PgPoolOptions::new().max_connections(1).connect(&server_url).await.unwrap();
+            server.execute(format!("CREATE DATABASE {}", name).as_str()).await.unwrap();
+            let (server_base, _) = server_url.rsplit_once('/').unwrap();
+            let database = Arc::new(Database::new(&format!("{}/{}", server_base, name)).await.unwrap());
+            Self { database, server_url, name }
+        }
+
+        /// Drop the database; anything else holding it should be dropped first.
+        pub async fn drop(self) {
+            let ScratchDatabase { database, server_url, name } = self;
+            drop(database);
+            let server = PgPoolOptions::new().max_connections(1).connect(&server_url).await.unwrap();
+            server.execute(format!("DROP DATABASE {} WITH (FORCE)", name).as_str()).await.unwrap();
+        }
+    }
+}
@@ -79,6 +79,10 @@ pub struct SettlementBatch {
     pub expiry_timestamp: u64,
     pub status: SettlementStatus,
     pub transaction_hash: Option<String>,
+    /// VM status reported once the transaction is committed
+    pub vm_status: Option<String>,
+    pub gas_used: Option<u64>,
+    pub submitted_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -89,6 +93,301 @@ pub enum SettlementStatus {
     Submitted,
     Confirmed,
     Failed,
+    /// Given up by an operator; its unsettled trades are dead-lettered
+    Abandoned,
+    /// Too large to submit; its trades moved to smaller batches
+    Split,
+}
+
+/// A settlement batch without its trades, for listing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementBatchSummary {
+    pub id: Uuid,
+    pub market_id: u64,
+    pub trade_count: i64,
+    pub min_price: Decimal,
+    pub max_price: Decimal,
+    pub status: SettlementStatus,
+    pub transaction_hash: Option<String>,
+    pub vm_status: Option<String>,
+    pub gas_used: Option<u64>,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// How far settlement is behind matching.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementLag {
+    /// Trades not yet in a confirmed batch
+    pub unsettled_trades: i64,
+    /// Unsettled trades not currently in any batch
+    pub pending_trades: i64,
+    pub held_trades: i64,
+    pub dead_lettered_trades: i64,
+    pub in_flight_batches: i64,
+    pub oldest_unsettled_trade_at: Option<DateTime<Utc>>,
+}
+
+/// Retry state of a trade whose settlement batch failed.
+///
+/// Trades sharing a `retry_group` are re-batched together, so each failure
+/// splits the group further until bad fills are isolated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementRetry {
+    pub trade_id: Uuid,
+    pub retry_group: Uuid,
+    /// Id the group is resubmitted under, whole, when its failed batch may still
+    /// land; the on-chain batch id record then rejects one of the two
+    pub batch_id: Option<Uuid>,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    /// Set once the trade exhausted its attempts; it is not retried until requeued
+    pub dead_lettered_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetteredTrade {
+    #[serde(flatten)]
+    pub trade: Trade,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub dead_lettered_at: DateTime<Utc>,
+}
+
+/// A trade kept out of settlement because its price was outside the oracle band.
+///
+/// Held trades wait for an operator; once released they settle at their own
+/// price, with the batch bounds widened to cover it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementHold {
+    pub trade_id: Uuid,
+    pub reference_price: Decimal,
+    pub min_price: Decimal,
+    pub max_price: Decimal,
+    pub held_at: DateTime<Utc>,
+    pub released_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeldTrade {
+    #[serde(flatten)]
+    pub trade: Trade,
+    pub reference_price: Decimal,
+    pub min_price: Decimal,
+    pub max_price: Decimal,
+    pub held_at: DateTime<Utc>,
+}
+
+/// Net change of one user in one market within a netted settlement batch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetDelta {
+    pub batch_id: Uuid,
+    pub user_address: String,
+    pub market_id: u64,
+    /// Signed position change, positive is long
+    pub size_delta: Decimal,
+    /// Average fill price on the side of the net change
+    pub price: Decimal,
+    /// Lowest and highest price among the netted fills, checked against the batch bounds
+    pub min_price: Decimal,
+    pub max_price: Decimal,
+    /// Sum of the per-fill `chain::fill_quote` of both sides, added to the entry notional
+    pub entry_notional: Decimal,
+    /// Signed quote change in on-chain quote units, the sum of the per-fill
+    /// `chain::fill_quote`; positive when the user receives quote
+    pub quote_delta: Decimal,
+    /// Sum of the per-fill fees in on-chain quote units, see `netting::fill_fees`
+    pub taker_fee: Decimal,
+    pub maker_fee: Decimal,
+    /// Trades netted into this delta
+    pub trade_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FundingRate {
+    pub id: Uuid,
+    pub market_id: u64,
+    /// Signed rate per epoch: positive means longs pay shorts
+    pub rate: Decimal,
+    pub premium: Decimal,
+    pub mark_price: Decimal,
+    pub index_price: Decimal,
+    pub funding_time: DateTime<Utc>,
+    pub transaction_hash: Option<String>,
+    pub status: FundingStatus,
+    /// Times `perp_engine::apply_funding_simple` has been submitted for this rate
+    pub attempts: u32,
+    /// Why the last submission failed
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// When the status last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
+#[sqlx(type_name = "funding_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum FundingStatus {
+    /// `perp_engine::apply_funding_simple` submitted, waiting for it to commit
+    Submitted,
+    /// Committed successfully on-chain
+    Published,
+    /// Not published; retried until the configured attempts run out
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Liquidation {
+    pub id: Uuid,
+    pub user_address: String,
+    pub market_id: u64,
+    pub order_id: Uuid,
+    pub side: OrderSide,
+    pub size: Decimal,
+    pub filled_size: Decimal,
+    pub average_price: Option<Decimal>,
+    pub mark_price: Decimal,
+    /// Truncated to whole USDC base units and charged on-chain as a `LiquidationPenalty`
+    pub penalty: Decimal,
+    pub equity: Decimal,
+    pub maintenance_margin: Decimal,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
+#[sqlx(type_name = "penalty_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum PenaltyStatus {
+    /// Waiting for the liquidation fills to settle before it is submitted
+    Queued,
+    /// `perp_engine::charge_penalty_once` submitted, waiting for it to commit
+    Submitted,
+    /// Moved to the insurance account and credited to the insurance fund
+    Charged,
+    Failed,
+}
+
+/// A liquidation penalty owed by the liquidated account. It is charged
+/// on-chain as a vault transfer to the insurance account, and only credited
+/// to the insurance fund once that transfer has committed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidationPenalty {
+    pub liquidation_id: Uuid,
+    /// Liquidation order whose fills settle before the penalty is charged
+    pub order_id: Uuid,
+    pub user_address: String,
+    pub market_id: u64,
+    /// USDC base units
+    pub amount: u64,
+    pub status: PenaltyStatus,
+    pub tx_hash: Option<String>,
+    /// Why the last submission attempt or the transaction failed
+    pub error: Option<String>,
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
+#[sqlx(type_name = "insurance_entry_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum InsuranceEntryKind {
+    LiquidationPenalty,
+    TradingFee,
+    LiquidationLoss,
+}
+
+/// One movement of the insurance fund. `amount` is signed: credits are
+/// positive, losses covered by the fund are negative.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InsuranceFundEntry {
+    pub id: Uuid,
+    pub kind: InsuranceEntryKind,
+    pub market_id: u64,
+    pub amount: Decimal,
+    /// Fund balance after this entry
+    pub balance: Decimal,
+    /// Liquidation or trade the entry came from
+    pub reference_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A forced close of a profitable position against a bankrupt one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdlEvent {
+    pub id: Uuid,
+    pub liquidation_id: Uuid,
+    pub trade_id: Uuid,
+    pub market_id: u64,
+    pub liquidated_address: String,
+    pub counterparty_address: String,
+    /// Side of the liquidated account's closing trade
+    pub side: OrderSide,
+    pub size: Decimal,
+    pub bankruptcy_price: Decimal,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, PartialEq, Eq, Default)]
+#[sqlx(type_name = "margin_mode", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MarginMode {
+    /// Margin and losses are shared across all cross positions
+    #[default]
+    Cross,
+    /// The position can only lose the margin allocated to it
+    Isolated,
+}
+
+/// A user's margin mode and leverage for one market.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarginSetting {
+    pub user_address: String,
+    pub market_id: u64,
+    pub margin_mode: MarginMode,
+    pub leverage: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A margin setting change signed by the user, as withdrawals are.
+#[derive(Debug, Deserialize)]
+pub struct UpdateMarginSettingRequest {
+    pub user_address: String,
+    pub market_id: u64,
+    pub margin_mode: Option<MarginMode>,
+    pub leverage: Option<u64>,
+    /// Unix seconds after which the signature is no longer accepted
+    pub expires_at: i64,
+    /// Hex Ed25519 public key of the account
+    pub public_key: String,
+    /// Hex Ed25519 signature over `signing_message`
+    pub signature: String,
+}
+
+impl UpdateMarginSettingRequest {
+    /// The exact bytes the user signs; fields left unchanged are signed as `-`.
+    pub fn signing_message(&self, contract_address: &str) -> String {
+        let margin_mode = match self.margin_mode {
+            Some(MarginMode::Cross) => "cross",
+            Some(MarginMode::Isolated) => "isolated",
+            None => "-",
+        };
+        let leverage = self.leverage.map_or("-".to_string(), |l| l.to_string());
+        format!(
+            "hyperperp margin setting\ncontract: {}\nuser: {}\nmarket: {}\nmargin_mode: {}\nleverage: {}\nexpires_at: {}",
+            contract_address, self.user_address, self.market_id, margin_mode, leverage, self.expires_at
+        )
+    }
+}
+
+/// Last known vault collateral of a user, as tracked by the collateral ledger.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollateralBalance {
+    pub user_address: String,
+    pub balance: Decimal,
+    pub synced_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -125,6 +424,8 @@ pub struct FreezeTransactionResponse {
     pub order_id: Uuid,
     pub freeze_transaction_payload: FreezeTransactionPayload,
     pub required_collateral: u64,
+    /// The order must be confirmed before this time
+    pub expires_at: DateTime<Utc>,
     pub message: String,
 }
 
@@ -137,15 +438,46 @@ pub struct FreezeTransactionPayload {
     pub gas_unit_price: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
+#[sqlx(type_name = "freeze_request_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum FreezeRequestStatus {
+    /// Quoted, waiting for the user's freeze transaction
+    Pending,
+    Confirmed,
+    /// Not confirmed before `expires_at`
+    Expired,
+    /// The deposit landed after expiry and is being returned
+    RefundPending,
+    Refunded,
+}
+
+/// An order quoted by `request_freeze_transaction`, kept until the user's
+/// signed freeze transaction confirms it or it expires.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ConfirmOrderRequest {
+pub struct FreezeRequest {
+    pub order_id: Uuid,
     pub user_address: String,
     pub market_id: u64,
     pub side: OrderSide,
     pub order_type: OrderType,
-    pub size: String,
-    pub price: Option<String>,
-    pub expires_at: Option<DateTime<Utc>>,
+    pub size: Decimal,
+    /// Limit price, zero for market orders
+    pub price: Decimal,
+    pub order_expires_at: Option<DateTime<Utc>>,
+    pub required_collateral: u64,
+    /// `vault_coin::deposit` amount in coin base units
+    pub amount: u64,
+    pub status: FreezeRequestStatus,
+    /// Freeze transaction that confirmed the order or is being refunded
+    pub tx_hash: Option<String>,
+    pub refund_tx_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmOrderRequest {
     pub order_id: Uuid,
     pub signed_transaction_hash: String,
 }
@@ -194,3 +526,66 @@ pub struct DepositResponse {
     pub amount: u64,
     pub user_address: String,
 }
+
+/// A withdrawal signed by the user; the nonce becomes the withdrawal ID, so
+/// a signed request is only ever queued once.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WithdrawRequest {
+    pub user_address: String,
+    /// Collateral to withdraw in coin base units
+    pub amount: u64,
+    pub nonce: Uuid,
+    /// Unix seconds after which the signature is no longer accepted
+    pub expires_at: i64,
+    /// Hex Ed25519 public key of the account
+    pub public_key: String,
+    /// Hex Ed25519 signature over `signing_message`
+    pub signature: String,
+}
+
+impl WithdrawRequest {
+    /// The exact bytes the user signs.
+    pub fn signing_message(&self, contract_address: &str) -> String {
+        format!(
+            "hyperperp withdrawal\ncontract: {}\nuser: {}\namount: {}\nnonce: {}\nexpires_at: {}",
+            contract_address, self.user_address, self.amount, self.nonce, self.expires_at
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
+#[sqlx(type_name = "withdrawal_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalStatus {
+    /// Accepted, waiting to be submitted
+    Queued,
+    /// `vault_coin::withdraw_for` submitted, waiting for it to commit
+    Submitted,
+    Completed,
+    Failed,
+}
+
+/// A withdrawal of vault collateral back to the user's wallet. Its amount is
+/// held in the collateral ledger until it completes or fails.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Withdrawal {
+    pub id: Uuid,
+    pub user_address: String,
+    pub amount: u64,
+    pub status: WithdrawalStatus,
+    pub tx_hash: Option<String>,
+    /// Admin sequence number the transaction was signed under
+    pub sequence_number: Option<u64>,
+    /// Why the last submission attempt or the transaction failed
+    pub error: Option<String>,
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WithdrawResponse {
+    pub withdrawal: Withdrawal,
+    /// Collateral still withdrawable after this request
+    pub withdrawable: Decimal,
+}
@@ -0,0 +1,410 @@
+use anyhow::{anyhow, Result};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::{
+    chain::{fill_quote, price_x, size_units, BPS_SCALE},
+    models::{NetDelta, OrderSide, Trade},
+};
+
+/// Per-fill taker fee and maker rebate in on-chain quote units, computed step
+/// by step as `perp_engine::calculate_fees` does, truncating at every division.
+pub fn fill_fees(trade: &Trade, fee_bps: u64) -> Result<(u64, u64)> {
+    let notional = fill_quote(size_units(trade.size)?, price_x(trade.price)?);
+    let fee_rate = fee_bps as u128 * 10_000 / BPS_SCALE as u128;
+    let taker_fee = notional * fee_rate / 1_000_000;
+    let maker_fee = taker_fee / 2;
+    Ok((u64::try_from(taker_fee)?, u64::try_from(maker_fee)?))
+}
+
+/// On-chain quote of a fill, `chain::fill_quote`, as a signed amount.
+fn signed_quote(trade: &Trade) -> Result<i128> {
+    let quote = fill_quote(size_units(trade.size)?, price_x(trade.price)?);
+    i128::try_from(quote).map_err(|_| anyhow!("Quote of trade {} out of range", trade.id))
+}
+
+fn whole_amount(amount: Decimal, user_address: &str) -> Result<i128> {
+    amount.to_i128().filter(|_| amount.fract().is_zero())
+        .ok_or_else(|| anyhow!("Net amount {} of {} is not a whole amount", amount, user_address))
+}
+
+#[derive(Default)]
+struct Leg {
+    size_delta: Decimal,
+    quote_delta: i128,
+    long_size: Decimal,
+    long_notional: Decimal,
+    short_size: Decimal,
+    short_notional: Decimal,
+    entry_notional: i128,
+    price_range: Option<(Decimal, Decimal)>,
+    taker_fee: u64,
+    maker_fee: u64,
+    trade_ids: Vec<Uuid>,
+}
+
+impl Leg {
+    /// `quote` is the fill's on-chain quote, `chain::fill_quote`.
+    fn add(&mut self, side: &OrderSide, trade: &Trade, quote: i128) {
+        let notional = trade.size * trade.price;
+        match side {
+            OrderSide::Buy => {
+                self.size_delta += trade.size;
+                self.quote_delta -= quote;
+                self.long_size += trade.size;
+                self.long_notional += notional;
+            }
+            OrderSide::Sell => {
+                self.size_delta -= trade.size;
+                self.quote_delta += quote;
+                self.short_size += trade.size;
+                self.short_notional += notional;
+            }
+        }
+        self.entry_notional += quote;
+        self.price_range = Some(match self.price_range {
+            Some((low, high)) => (low.min(trade.price), high.max(trade.price)),
+            None => (trade.price, trade.price),
+        });
+        self.trade_ids.push(trade.id);
+    }
+
+    fn average_price(&self) -> Decimal {
+        let (size, notional) = if self.size_delta >= Decimal::ZERO {
+            (self.long_size, self.long_notional)
+        } else {
+            (self.short_size, self.short_notional)
+        };
+        if size.is_zero() {
+            Decimal::ZERO
+        } else {
+            notional / size
+        }
+    }
+}
+
+/// Collapse the fills of a batch into one delta per user and market.
+///
+/// Every trade contributes a taker leg and a maker leg, so each trade id
+/// appears in exactly two deltas (or twice in one, for a self-trade).
+pub fn net_trades(batch_id: Uuid, trades: &[Trade], fee_bps: u64) -> Result<Vec<NetDelta>> {
+    let mut order: Vec<(String, u64)> = Vec::new();
+    let mut legs: HashMap<(String, u64), Leg> = HashMap::new();
+
+    for trade in trades {
+        let maker_side = match trade.side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        };
+        let (taker_fee, maker_fee) = fill_fees(trade, fee_bps)?;
+        let quote = signed_quote(trade)?;
+
+        for (address, side, is_taker) in [
+            (&trade.taker_address, &trade.side, true),
+            (&trade.maker_address, &maker_side, false),
+        ] {
+            let key = (address.clone(), trade.market_id);
+            if !legs.contains_key(&key) {
+                order.push(key.clone());
+            }
+            let leg = legs.entry(key).or_default();
+            leg.add(side, trade, quote);
+            if is_taker {
+                leg.taker_fee += taker_fee;
+            } else {
+                leg.maker_fee += maker_fee;
+            }
+        }
+    }
+
+    Ok(order
+        .into_iter()
+        .map(|key| {
+            let leg = legs.remove(&key).unwrap_or_default();
+            let (min_price, max_price) = leg.price_range.unwrap_or_default();
+            NetDelta {
+                batch_id,
+                price: leg.average_price(),
+                min_price,
+                max_price,
+                entry_notional: Decimal::from(leg.entry_notional),
+                user_address: key.0,
+                market_id: key.1,
+                size_delta: leg.size_delta,
+                quote_delta: Decimal::from(leg.quote_delta),
+                taker_fee: Decimal::from(leg.taker_fee),
+                maker_fee: Decimal::from(leg.maker_fee),
+                trade_ids: leg.trade_ids,
+            }
+        })
+        .collect())
+}
+
+/// Check that `deltas` settle exactly what `trades` would settle fill by fill:
+/// every trade is mapped twice, each user's size, integer quote and entry
+/// notional changes equal the gross per-fill result, quote nets to zero per market, and fee
+/// totals match the gross per-fill fees.
+pub fn verify_netting(trades: &[Trade], deltas: &[NetDelta], fee_bps: u64) -> Result<()> {
+    let mut legs: HashMap<Uuid, usize> = HashMap::new();
+    for delta in deltas {
+        for trade_id in &delta.trade_ids {
+            *legs.entry(*trade_id).or_default() += 1;
+        }
+    }
+    if legs.len() != trades.len() || trades.iter().any(|t| legs.get(&t.id) != Some(&2)) {
+        return Err(anyhow!("Net deltas do not map each trade to its taker and maker"));
+    }
+
+    // Size, quote and entry notional change of each user and market, fill by fill
+    let mut gross: HashMap<(&str, u64), (Decimal, i128, i128)> = HashMap::new();
+    for trade in trades {
+        let quote = signed_quote(trade)?;
+        let (taker_size, taker_quote) = match trade.side {
+            OrderSide::Buy => (trade.size, -quote),
+            OrderSide::Sell => (-trade.size, quote),
+        };
+        let taker = gross.entry((trade.taker_address.as_str(), trade.market_id)).or_default();
+        taker.0 += taker_size;
+        taker.1 += taker_quote;
+        taker.2 += quote;
+        let maker = gross.entry((trade.maker_address.as_str(), trade.market_id)).or_default();
+        maker.0 -= taker_size;
+        maker.1 -= taker_quote;
+        maker.2 += quote;
+    }
+
+    let mut market_quotes: HashMap<u64, i128> = HashMap::new();
+    for delta in deltas {
+        let quote = whole_amount(delta.quote_delta, &delta.user_address)?;
+        let entry_notional = whole_amount(delta.entry_notional, &delta.user_address)?;
+        if gross.remove(&(delta.user_address.as_str(), delta.market_id)) != Some((delta.size_delta, quote, entry_notional)) {
+            return Err(anyhow!(
+                "Net delta of {} in market {} differs from its gross fills",
+                delta.user_address, delta.market_id
+            ));
+        }
+        *market_quotes.entry(delta.market_id).or_default() += quote;
+    }
+    if !gross.is_empty() {
+        return Err(anyhow!("Net deltas are missing {} users of the batch", gross.len()));
+    }
+    if let Some((market_id, _)) = market_quotes.iter().find(|(_, quote)| **quote != 0) {
+        return Err(anyhow!("Net deltas for market {} do not balance", market_id));
+    }
+
+    let (mut gross_taker, mut gross_maker) = (Decimal::ZERO, Decimal::ZERO);
+    for trade in trades {
+        let (taker_fee, maker_fee) = fill_fees(trade, fee_bps)?;
+        gross_taker += Decimal::from(taker_fee);
+        gross_maker += Decimal::from(maker_fee);
+    }
+    let net_taker: Decimal = deltas.iter().map(|d| d.taker_fee).sum();
+    let net_maker: Decimal = deltas.iter().map(|d| d.maker_fee).sum();
+    if net_taker != gross_taker || net_maker != gross_maker {
+        return Err(anyhow!(
+            "Net fees {}/{} differ from gross fees {}/{}",
+            net_taker, net_maker, gross_taker, gross_maker
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEE_BPS: u64 = 10;
+
+    fn trade(market_id: u64, taker: &str, maker: &str, side: OrderSide, size: i64, price: i64) -> Trade {
+        Trade {
+            id: Uuid::new_v4(),
+            market_id,
+            taker_order_id: Uuid::new_v4(),
+            maker_order_id: Uuid::new_v4(),
+            taker_address: taker.to_string(),
+            maker_address: maker.to_string(),
+            size: Decimal::from(size),
+            price: Decimal::from(price),
+            side,
+            created_at: chrono::Utc::now(),
+            settlement_batch_id: None,
+        }
+    }
+
+    fn delta<'a>(deltas: &'a [NetDelta], user: &str, market_id: u64) -> &'a NetDelta {
+        deltas.iter().find(|d| d.user_address == user && d.market_id == market_id).unwrap()
+    }
+
+    #[test]
+    fn offsetting_fills_net_to_zero_size() {
+        let trades = vec![
+            trade(1, "alice", "bob", OrderSide::Buy, 2, 100),
+            trade(1, "alice", "bob", OrderSide::Sell, 2, 110),
+        ];
+        let deltas = net_trades(Uuid::new_v4(), &trades, FEE_BPS).unwrap();
+
+        assert_eq!(deltas.len(), 2);
+        let alice = delta(&deltas, "alice", 1);
+        assert_eq!(alice.size_delta, Decimal::ZERO);
+        // Bought 2 at 100 and sold 2 at 110
+        assert_eq!(alice.quote_delta, Decimal::from(20));
+        assert_eq!(alice.trade_ids.len(), 2);
+        assert_eq!(delta(&deltas, "bob", 1).quote_delta, Decimal::from(-20));
+        verify_netting(&trades, &deltas, FEE_BPS).unwrap();
+    }
+
+    #[test]
+    fn net_price_averages_the_side_of_the_net_change() {
+        let trades = vec![
+            trade(1, "alice", "bob", OrderSide::Buy, 1, 100),
+            trade(1, "alice", "carol", OrderSide::Buy, 3, 200),
+            trade(1, "alice", "bob", OrderSide::Sell, 1, 150),
+        ];
+        let deltas = net_trades(Uuid::new_v4(), &trades, FEE_BPS).unwrap();
+
+        let alice = delta(&deltas, "alice", 1);
+        assert_eq!(alice.size_delta, Decimal::from(3));
+        assert_eq!(alice.price, Decimal::from(175));
+        assert_eq!(alice.quote_delta, Decimal::from(-550));
+        verify_netting(&trades, &deltas, FEE_BPS).unwrap();
+    }
+
+    #[test]
+    fn fractional_prices_net_the_truncated_per_fill_quotes() {
+        let mut trades = vec![
+            trade(1, "alice", "bob", OrderSide::Buy, 1, 0),
+            trade(1, "carol", "alice", OrderSide::Buy, 1, 0),
+            trade(1, "bob", "carol", OrderSide::Sell, 7, 0),
+        ];
+        trades[0].price = Decimal::new(1009, 1);
+        trades[1].price = Decimal::new(991, 1);
+        trades[2].price = Decimal::new(1_000_000_001, 7);
+        let deltas = net_trades(Uuid::new_v4(), &trades, FEE_BPS).unwrap();
+
+        // alice pays fill_quote(1, 100.9) = 100 and receives fill_quote(1, 99.1) = 99;
+        // her netted notional of -1.8 would round to -2
+        let alice = delta(&deltas, "alice", 1);
+        assert_eq!(alice.quote_delta, Decimal::from(-1));
+        // Both fills add their notional to the entry, as they do fill by fill
+        assert_eq!(alice.entry_notional, Decimal::from(100 + 99));
+        assert_eq!((alice.min_price, alice.max_price), (Decimal::new(991, 1), Decimal::new(1009, 1)));
+        assert_eq!(delta(&deltas, "bob", 1).quote_delta, Decimal::from(100 + 700));
+        assert_eq!(delta(&deltas, "carol", 1).quote_delta, Decimal::from(-99 - 700));
+        let total: Decimal = deltas.iter().map(|d| d.quote_delta).sum();
+        assert_eq!(total, Decimal::ZERO);
+        verify_netting(&trades, &deltas, FEE_BPS).unwrap();
+    }
+
+    #[test]
+    fn verify_rejects_fractional_quote() {
+        let mut trades = vec![trade(1, "alice", "bob", OrderSide::Buy, 3, 0)];
+        trades[0].price = Decimal::new(1009, 1);
+        let mut deltas = net_trades(Uuid::new_v4(), &trades, FEE_BPS).unwrap();
+        // The unrounded notional, as netting in human units would produce it
+        deltas[0].quote_delta = -trades[0].size * trades[0].price;
+        deltas[1].quote_delta = trades[0].size * trades[0].price;
+
+        assert!(verify_netting(&trades, &deltas, FEE_BPS).is_err());
+    }
+
+    #[test]
+    fn fees_match_perp_engine_calculate_fees() {
+        let mut fill = trade(1, "alice", "bob", OrderSide::Buy, 7, 0);
+        fill.price = Decimal::new(150_000_123, 3);
+
+        // calculate_fees(7, 15_000_012_300_000, 10): notional 1_050_000 (from 1_050_000.861),
+        // fee rate 10, taker fee 10 (from 10.5), maker rebate 5
+        assert_eq!(fill_fees(&fill, FEE_BPS).unwrap(), (10, 5));
+    }
+
+    #[test]
+    fn fractional_sizes_are_rejected() {
+        let mut fill = trade(1, "alice", "bob", OrderSide::Buy, 1, 100);
+        fill.size = Decimal::new(15, 1);
+
+        assert!(fill_fees(&fill, FEE_BPS).is_err());
+        assert!(net_trades(Uuid::new_v4(), &[fill], FEE_BPS).is_err());
+    }
+
+    #[test]
+    fn self_trade_maps_the_trade_twice_into_one_delta() {
+        let trades = vec![trade(1, "alice", "alice", OrderSide::Buy, 5, 100_000)];
+        let deltas = net_trades(Uuid::new_v4(), &trades, FEE_BPS).unwrap();
+
+        assert_eq!(deltas.len(), 1);
+        let alice = &deltas[0];
+        assert_eq!(alice.size_delta, Decimal::ZERO);
+        assert_eq!(alice.quote_delta, Decimal::ZERO);
+        assert_eq!(alice.trade_ids, vec![trades[0].id, trades[0].id]);
+        // Pays the taker fee and receives the maker rebate
+        assert_eq!(alice.taker_fee, Decimal::from(5));
+        assert_eq!(alice.maker_fee, Decimal::from(2));
+        verify_netting(&trades, &deltas, FEE_BPS).unwrap();
+    }
+
+    #[test]
+    fn markets_are_netted_separately() {
+        let trades = vec![
+            trade(1, "alice", "bob", OrderSide::Buy, 2, 100),
+            trade(2, "bob", "alice", OrderSide::Buy, 2, 50),
+        ];
+        let deltas = net_trades(Uuid::new_v4(), &trades, FEE_BPS).unwrap();
+
+        assert_eq!(deltas.len(), 4);
+        assert_eq!(delta(&deltas, "alice", 1).size_delta, Decimal::from(2));
+        assert_eq!(delta(&deltas, "alice", 2).size_delta, Decimal::from(-2));
+        assert_eq!(delta(&deltas, "bob", 1).size_delta, Decimal::from(-2));
+        assert_eq!(delta(&deltas, "bob", 2).size_delta, Decimal::from(2));
+        verify_netting(&trades, &deltas, FEE_BPS).unwrap();
+    }
+
+    #[test]
+    fn fee_totals_match_per_fill_fees() {
+        let trades = vec![
+            trade(1, "alice", "bob", OrderSide::Buy, 2, 100_000),
+            trade(1, "carol", "alice", OrderSide::Sell, 1, 300_000),
+        ];
+        let deltas = net_trades(Uuid::new_v4(), &trades, FEE_BPS).unwrap();
+
+        // 10 bps of 200_000 and of 300_000; makers are rebated half, truncated per fill
+        let taker_fees: Decimal = deltas.iter().map(|d| d.taker_fee).sum();
+        let maker_fees: Decimal = deltas.iter().map(|d| d.maker_fee).sum();
+        assert_eq!(taker_fees, Decimal::from(5));
+        assert_eq!(maker_fees, Decimal::from(2));
+        assert_eq!(delta(&deltas, "alice", 1).taker_fee, Decimal::from(2));
+        assert_eq!(delta(&deltas, "alice", 1).maker_fee, Decimal::from(1));
+        verify_netting(&trades, &deltas, FEE_BPS).unwrap();
+    }
+
+    #[test]
+    fn verify_rejects_missing_legs() {
+        let trades = vec![
+            trade(1, "alice", "bob", OrderSide::Buy, 2, 100),
+            trade(1, "carol", "bob", OrderSide::Buy, 1, 100),
+        ];
+        let mut deltas = net_trades(Uuid::new_v4(), &trades, FEE_BPS).unwrap();
+        deltas.retain(|d| d.user_address != "carol");
+
+        assert!(verify_netting(&trades, &deltas, FEE_BPS).is_err());
+    }
+
+    #[test]
+    fn verify_rejects_unbalanced_quote() {
+        let trades = vec![trade(1, "alice", "bob", OrderSide::Buy, 2, 100)];
+        let mut deltas = net_trades(Uuid::new_v4(), &trades, FEE_BPS).unwrap();
+        deltas[0].quote_delta += Decimal::ONE;
+
+        assert!(verify_netting(&trades, &deltas, FEE_BPS).is_err());
+    }
+
+    #[test]
+    fn verify_rejects_changed_fees() {
+        let trades = vec![trade(1, "alice", "bob", OrderSide::Buy, 2, 100_000)];
+        let mut deltas = net_trades(Uuid::new_v4(), &trades, FEE_BPS).unwrap();
+        deltas[1].maker_fee = Decimal::ZERO;
+
+        assert!(verify_netting(&trades, &deltas, FEE_BPS).is_err());
+    }
+}
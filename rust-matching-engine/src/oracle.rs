@@ -0,0 +1,178 @@
+use anyhow::Result;
+use chrono::Utc;
+use rust_decimal::Decimal;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+use tokio::time::interval;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    chain::ChainClient,
+    config::{OracleConfig, PricingConfig},
+    pricing::{build_price_source, PriceSource},
+};
+
+/// Median of the source prices for one market, with the median absolute
+/// deviation as confidence.
+#[derive(Debug, Clone)]
+pub struct AggregatedPrice {
+    pub market_id: u64,
+    pub price: Decimal,
+    pub confidence: Decimal,
+    pub sources: usize,
+}
+
+#[derive(Debug, Clone)]
+struct PushedPrice {
+    price: Decimal,
+    timestamp: i64,
+}
+
+/// Feeds `oracle_adapter::push_price` so `read_price` never goes stale.
+///
+/// Every poll the configured sources are queried and aggregated per market.
+/// A price is pushed when the heartbeat elapses or the median deviates from
+/// the last pushed price by more than `deviation_bps`.
+pub struct OraclePusher {
+    chain_client: Arc<dyn ChainClient>,
+    sources: Vec<Arc<dyn PriceSource>>,
+    market_ids: Vec<u64>,
+    config: OracleConfig,
+    last_pushed: Mutex<HashMap<u64, PushedPrice>>,
+}
+
+impl OraclePusher {
+    pub async fn new(
+        chain_client: Arc<dyn ChainClient>,
+        market_ids: Vec<u64>,
+        config: OracleConfig,
+        pricing: &PricingConfig,
+    ) -> Result<Self> {
+        let sources = config
+            .sources
+            .iter()
+            .map(|s| build_price_source(s.kind, &s.path, &s.url, &pricing.mock_prices))
+            .collect();
+
+        Ok(Self {
+            chain_client,
+            sources,
+            market_ids,
+            config,
+            last_pushed: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub async fn start_oracle_loop(&self) -> Result<()> {
+        if !self.config.enabled {
+            info!("Oracle pusher disabled");
+            return std::future::pending().await;
+        }
+
+        info!("Starting oracle pusher loop with {} sources", self.sources.len());
+        let mut interval = interval(Duration::from_secs(self.config.poll_interval_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.push_prices().await {
+                error!("Oracle push error: {}", e);
+                // Continue running despite errors
+            }
+        }
+    }
+
+    async fn push_prices(&self) -> Result<()> {
+        let aggregated = self.aggregate().await;
+        let now = Utc::now().timestamp();
+
+        for price in aggregated {
+            if !self.should_push(&price, now).await {
+                continue;
+            }
+            debug!("Oracle price for market {}: {} ± {} from {} sources",
+                price.market_id, price.price, price.confidence, price.sources);
+
+            match self.chain_client
+                .push_oracle_price(price.market_id, price.price, price.confidence, now as u64)
+                .await
+            {
+                Ok(_) => {
+                    self.last_pushed.lock().await.insert(price.market_id, PushedPrice {
+                        price: price.price,
+                        timestamp: now,
+                    });
+                }
+                Err(e) => warn!("Failed to push oracle price for market {}: {}", price.market_id, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Query every source and aggregate the markets reported by at least `min_sources`.
+    async fn aggregate(&self) -> Vec<AggregatedPrice> {
+        let mut samples: HashMap<u64, Vec<Decimal>> = HashMap::new();
+
+        for source in &self.sources {
+            match source.fetch_prices().await {
+                Ok(prices) => {
+                    for (market_id, price) in prices {
+                        if self.market_ids.contains(&market_id) && price > Decimal::ZERO {
+                            samples.entry(market_id).or_default().push(price);
+                        }
+                    }
+                }
+                Err(e) => warn!("Oracle source {} failed: {}", source.name(), e),
+            }
+        }
+
+        samples
+            .into_iter()
+            .filter_map(|(market_id, prices)| {
+                if prices.len() < self.config.min_sources.max(1) {
+                    debug!("Market {} has {} oracle sources, need {}",
+                        market_id, prices.len(), self.config.min_sources);
+                    return None;
+                }
+                let sources = prices.len();
+                let (price, confidence) = median_with_confidence(prices)?;
+                Some(AggregatedPrice { market_id, price, confidence, sources })
+            })
+            .collect()
+    }
+
+    async fn should_push(&self, price: &AggregatedPrice, now: i64) -> bool {
+        let last_pushed = self.last_pushed.lock().await;
+        let Some(last) = last_pushed.get(&price.market_id) else {
+            return true;
+        };
+
+        if now - last.timestamp >= self.config.heartbeat_secs as i64 {
+            return true;
+        }
+
+        let deviation = (price.price - last.price).abs() / last.price;
+        deviation * Decimal::from(10_000) >= Decimal::from(self.config.deviation_bps)
+    }
+}
+
+fn median(mut values: Vec<Decimal>) -> Option<Decimal> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort();
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        Some((values[mid - 1] + values[mid]) / Decimal::TWO)
+    } else {
+        Some(values[mid])
+    }
+}
+
+/// Median price and the median absolute deviation around it.
+fn median_with_confidence(prices: Vec<Decimal>) -> Option<(Decimal, Decimal)> {
+    let price = median(prices.clone())?;
+    let confidence = median(prices.iter().map(|p| (*p - price).abs()).collect())?;
+    Some((price, confidence))
+}
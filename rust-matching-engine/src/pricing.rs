@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{broadcast, RwLock};
+use tokio::time::interval;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    config::{MockPriceConfig, PriceSourceKind, PricingConfig},
+    matching_engine::MatchingEngine,
+};
+
+/// A feed of index prices keyed by market id.
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn fetch_prices(&self) -> Result<HashMap<u64, Decimal>>;
+}
+
+/// Fixed prices, for local development and tests.
+pub struct MockPriceSource {
+    prices: HashMap<u64, Decimal>,
+}
+
+impl MockPriceSource {
+    pub fn new(prices: HashMap<u64, Decimal>) -> Self {
+        Self { prices }
+    }
+}
+
+#[async_trait]
+impl PriceSource for MockPriceSource {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn fetch_prices(&self) -> Result<HashMap<u64, Decimal>> {
+        Ok(self.prices.clone())
+    }
+}
+
+/// Reads a JSON object of `{"<market_id>": <price>}` on every fetch, so prices
+/// can be changed by editing the file while the engine runs.
+pub struct FilePriceSource {
+    path: String,
+}
+
+impl FilePriceSource {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl PriceSource for FilePriceSource {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn fetch_prices(&self) -> Result<HashMap<u64, Decimal>> {
+        let contents = tokio::fs::read_to_string(&self.path)
+            .await
+            .with_context(|| format!("Failed to read price file {}", self.path))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse price file {}", self.path))
+    }
+}
+
+/// Fetches a JSON object of `{"<market_id>": <price>}` over HTTP, so a local
+/// stub server can stand in for an exchange feed.
+pub struct HttpPriceSource {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpPriceSource {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for HttpPriceSource {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn fetch_prices(&self) -> Result<HashMap<u64, Decimal>> {
+        self.client
+            .get(&self.url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch prices from {}", self.url))?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse prices from {}", self.url))
+    }
+}
+
+/// Build the price source selected in config.
+pub fn price_source_from_config(config: &PricingConfig) -> Arc<dyn PriceSource> {
+    build_price_source(config.source, &config.file_path, &config.url, &config.mock_prices)
+}
+
+/// Build a price source of the given kind; `mock_prices` only apply to the mock source.
+pub fn build_price_source(
+    kind: PriceSourceKind,
+    file_path: &str,
+    url: &str,
+    mock_prices: &[MockPriceConfig],
+) -> Arc<dyn PriceSource> {
+    match kind {
+        PriceSourceKind::Mock => {
+            let prices = mock_prices
+                .iter()
+                .filter_map(|p| Decimal::from_f64(p.price).map(|price| (p.market_id, price)))
+                .collect();
+            Arc::new(MockPriceSource::new(prices))
+        }
+        PriceSourceKind::File => Arc::new(FilePriceSource::new(file_path)),
+        PriceSourceKind::Http => Arc::new(HttpPriceSource::new(url)),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketPrice {
+    pub market_id: u64,
+    pub index_price: Decimal,
+    pub mark_price: Decimal,
+    /// EMA of (book mid - index)
+    pub premium_ema: Decimal,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Keeps an index price per market from a `PriceSource` and derives the mark
+/// price as index plus an EMA of the order book mid premium.
+pub struct PriceService {
+    source: Arc<dyn PriceSource>,
+    matching_engine: Arc<RwLock<MatchingEngine>>,
+    config: PricingConfig,
+    prices: RwLock<HashMap<u64, MarketPrice>>,
+    price_sender: broadcast::Sender<MarketPrice>,
+}
+
+impl PriceService {
+    pub fn new(
+        source: Arc<dyn PriceSource>,
+        matching_engine: Arc<RwLock<MatchingEngine>>,
+        config: PricingConfig,
+    ) -> Self {
+        let (price_sender, _) = broadcast::channel(1000);
+
+        Self {
+            source,
+            matching_engine,
+            config,
+            prices: RwLock::new(HashMap::new()),
+            price_sender,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<MarketPrice> {
+        self.price_sender.subscribe()
+    }
+
+    pub async fn start_price_loop(&self) -> Result<()> {
+        info!("Starting price service loop with {} source", self.source.name());
+        let mut interval = interval(Duration::from_secs(self.config.update_interval_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.update_prices().await {
+                error!("Price update error: {}", e);
+                // Keep serving the last known prices
+            }
+        }
+    }
+
+    pub(crate) async fn update_prices(&self) -> Result<()> {
+        let index_prices = self.source.fetch_prices().await?;
+        let alpha = Decimal::TWO / Decimal::from(self.config.premium_ema_periods.max(1) + 1);
+
+        let mid_prices: HashMap<u64, Decimal> = {
+            let matching_engine = self.matching_engine.read().await;
+            index_prices
+                .keys()
+                .filter_map(|market_id| {
+                    matching_engine
+                        .get_order_book(*market_id)
+                        .and_then(|book| book.mid_price())
+                        .map(|mid| (*market_id, mid))
+                })
+                .collect()
+        };
+
+        let mut prices = self.prices.write().await;
+        for (market_id, index_price) in index_prices {
+            if index_price <= Decimal::ZERO {
+                warn!("Ignoring non-positive index price {} for market {}", index_price, market_id);
+                continue;
+            }
+
+            let previous_ema = prices
+                .get(&market_id)
+                .map(|p| p.premium_ema)
+                .unwrap_or(Decimal::ZERO);
+            // Without a two-sided book there is no premium sample; carry the EMA forward
+            let premium_ema = match mid_prices.get(&market_id) {
+                Some(mid) => previous_ema + alpha * ((*mid - index_price) - previous_ema),
+                None => previous_ema,
+            };
+
+            let price = MarketPrice {
+                market_id,
+                index_price,
+                mark_price: index_price + premium_ema,
+                premium_ema,
+                updated_at: Utc::now(),
+            };
+            debug!("Market {} index {} mark {}", market_id, price.index_price, price.mark_price);
+
+            let _ = self.price_sender.send(price.clone());
+            prices.insert(market_id, price);
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_price(&self, market_id: u64) -> Option<MarketPrice> {
+        self.prices.read().await.get(&market_id).cloned()
+    }
+
+    pub async fn get_all_prices(&self) -> Vec<MarketPrice> {
+        let mut prices: Vec<_> = self.prices.read().await.values().cloned().collect();
+        prices.sort_by_key(|p| p.market_id);
+        prices
+    }
+
+    pub async fn mark_price(&self, market_id: u64) -> Option<Decimal> {
+        self.get_price(market_id).await.map(|p| p.mark_price)
+    }
+
+    pub async fn index_price(&self, market_id: u64) -> Option<Decimal> {
+        self.get_price(market_id).await.map(|p| p.index_price)
+    }
+}
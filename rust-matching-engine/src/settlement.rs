@@ -1,33 +1,66 @@
 use anyhow::Result;
-use rust_decimal::prelude::FromPrimitive;
-use std::{sync::Arc, time::Duration};
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use tokio::time::{interval, timeout};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 use crate::{
-    aptos_client::AptosClient,
-    config::SettlementConfig,
+    aptos_client::SETTLEMENT_FEE_BPS,
+    chain::{ChainClient, ChainError, TransactionStatus, USDC_UNIT},
+    config::{MarketConfig, SettlementConfig},
     database::Database,
-    models::{SettlementBatch, SettlementStatus, Trade},
+    insurance::InsuranceFund,
+    models::{
+        InsuranceEntryKind, LiquidationPenalty, PenaltyStatus, SettlementBatch, SettlementHold, SettlementRetry,
+        SettlementStatus, Trade,
+    },
+    netting,
+    pricing::PriceService,
+    signer_pool::{SettlementSigner, SignerPool},
 };
 
 pub struct SettlementService {
-    aptos_client: Arc<tokio::sync::Mutex<Arc<AptosClient>>>,
+    chain_client: Arc<dyn ChainClient>,
+    signers: SignerPool,
     database: Arc<Database>,
+    price_service: Arc<PriceService>,
+    /// Credited with liquidation penalties once they are charged on-chain
+    insurance_fund: Arc<InsuranceFund>,
+    markets: Vec<MarketConfig>,
     config: SettlementConfig,
+    sizing: tokio::sync::Mutex<BatchSizing>,
+    /// Set by operators to stop new batches; submitted ones are still tracked
+    paused: AtomicBool,
 }
 
 impl SettlementService {
     pub async fn new(
-        aptos_client: Arc<AptosClient>,
+        chain_client: Arc<dyn ChainClient>,
+        signers: SignerPool,
         database: Arc<Database>,
+        price_service: Arc<PriceService>,
+        insurance_fund: Arc<InsuranceFund>,
+        markets: Vec<MarketConfig>,
         config: SettlementConfig,
     ) -> Result<Self> {
         Ok(Self {
-            aptos_client: Arc::new(tokio::sync::Mutex::new(aptos_client)),
+            chain_client,
+            signers,
             database,
+            price_service,
+            insurance_fund,
+            markets,
             config,
+            sizing: tokio::sync::Mutex::new(BatchSizing::default()),
+            paused: AtomicBool::new(false),
         })
     }
 
@@ -38,11 +71,213 @@ impl SettlementService {
         loop {
             interval.tick().await;
 
+            if self.is_paused() {
+                debug!("Settlement paused, skipping batch processing");
+                continue;
+            }
+
             if let Err(e) = self.process_settlement_batch().await {
                 error!("Settlement batch processing error: {}", e);
                 // Continue running despite errors
             }
+
+            if let Err(e) = self.charge_liquidation_penalties().await {
+                error!("Liquidation penalty processing error: {}", e);
+            }
+        }
+    }
+
+    pub fn pause(&self) {
+        if !self.paused.swap(true, Ordering::SeqCst) {
+            warn!("Settlement paused by operator");
+        }
+    }
+
+    pub fn resume(&self) {
+        if self.paused.swap(false, Ordering::SeqCst) {
+            info!("Settlement resumed by operator");
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Send the unsettled trades of a failed batch back for settlement right away,
+    /// skipping any backoff and reviving dead-lettered ones. Returns the trades requeued.
+    pub async fn retry_failed_batch(&self, batch: &SettlementBatch) -> Result<u64> {
+        let trade_ids = Self::unsettled_trade_ids(batch);
+        self.database.clear_settlement_retries(&trade_ids).await?;
+        info!("Operator retried failed settlement batch {}: {} trades requeued", batch.id, trade_ids.len());
+        Ok(trade_ids.len() as u64)
+    }
+
+    /// Give up on a failed batch: its unsettled trades are dead-lettered, where they
+    /// stay until requeued. Returns the trades dead-lettered.
+    pub async fn abandon_failed_batch(&self, batch: &mut SettlementBatch) -> Result<u64> {
+        let trade_ids = Self::unsettled_trade_ids(batch);
+        let retries: HashMap<Uuid, SettlementRetry> = self.database
+            .get_settlement_retries(&trade_ids)
+            .await?
+            .into_iter()
+            .map(|r| (r.trade_id, r))
+            .collect();
+
+        let now = chrono::Utc::now();
+        for trade_id in &trade_ids {
+            let retry = match retries.get(trade_id) {
+                Some(retry) => SettlementRetry {
+                    last_error: Some("abandoned by operator".to_string()),
+                    dead_lettered_at: Some(now),
+                    updated_at: now,
+                    ..retry.clone()
+                },
+                None => SettlementRetry {
+                    trade_id: *trade_id,
+                    retry_group: Uuid::new_v4(),
+                    batch_id: None,
+                    attempts: 0,
+                    next_attempt_at: now,
+                    last_error: Some("abandoned by operator".to_string()),
+                    dead_lettered_at: Some(now),
+                    updated_at: now,
+                },
+            };
+            self.database.upsert_settlement_retry(&retry).await?;
+        }
+
+        batch.status = SettlementStatus::Abandoned;
+        self.database.update_settlement_batch(batch).await?;
+        warn!("Operator abandoned settlement batch {}: {} trades dead-lettered", batch.id, trade_ids.len());
+        Ok(trade_ids.len() as u64)
+    }
+
+    /// Trades of a batch not since picked up by another batch.
+    fn unsettled_trade_ids(batch: &SettlementBatch) -> Vec<Uuid> {
+        batch.trades.iter()
+            .filter(|t| t.settlement_batch_id.is_none())
+            .map(|t| t.id)
+            .collect()
+    }
+
+    /// Poll submitted batches and liquidation penalties until their transaction
+    /// succeeds, fails or expires.
+    pub async fn start_confirmation_loop(&self) -> Result<()> {
+        info!("Starting settlement confirmation tracker");
+        let mut interval = interval(Duration::from_secs(self.config.confirmation_poll_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.track_submitted_batches().await {
+                error!("Settlement confirmation tracking error: {}", e);
+            }
+
+            if let Err(e) = self.track_liquidation_penalties().await {
+                error!("Liquidation penalty tracking error: {}", e);
+            }
+        }
+    }
+
+    async fn track_submitted_batches(&self) -> Result<()> {
+        let batches = self.database.get_settlement_batches_by_status(SettlementStatus::Submitted).await?;
+
+        for mut batch in batches {
+            if let Err(e) = self.track_submitted_batch(&mut batch).await {
+                warn!("Failed to check settlement batch {}: {}", batch.id, e);
+            }
+        }
+
+        // Batches left Pending by a restart between creation and submission
+        let stale = self.database.get_settlement_batches_by_status(SettlementStatus::Pending).await?;
+        for mut batch in stale {
+            if self.confirmation_expired(&batch) {
+                if let Err(e) = self.fail_batch(&mut batch, "batch abandoned before submission", None).await {
+                    warn!("Failed to requeue stale settlement batch {}: {}", batch.id, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn track_submitted_batch(&self, batch: &mut SettlementBatch) -> Result<()> {
+        let status = match batch.transaction_hash.clone() {
+            Some(tx_hash) => self.chain_client.check_transaction_status(&tx_hash).await?,
+            // Submission timed out locally; the transaction may still land
+            None => TransactionStatus::Pending,
+        };
+
+        match status {
+            TransactionStatus::Success { vm_status, gas_used } => {
+                batch.status = SettlementStatus::Confirmed;
+                batch.vm_status = Some(vm_status);
+                batch.gas_used = Some(gas_used);
+                self.database.update_settlement_batch(batch).await?;
+                info!("Settlement batch {} confirmed with tx: {:?} (gas used {})", batch.id, batch.transaction_hash, gas_used);
+            }
+            TransactionStatus::Failed { vm_status, gas_used } => {
+                let error = self.chain_client.decode_vm_status(&vm_status);
+                batch.vm_status = Some(vm_status);
+                batch.gas_used = Some(gas_used);
+                self.fail_batch(batch, &error.to_string(), Some(&error)).await?;
+            }
+            TransactionStatus::Pending => {
+                if self.confirmation_expired(batch) {
+                    // An expired transaction leaves a gap in its signer's sequence
+                    self.signers.resync_all().await;
+                    batch.vm_status = Some("expired".to_string());
+                    self.fail_batch(batch, "transaction expired", None).await?;
+                } else {
+                    debug!("Settlement batch {} tx {:?} still pending", batch.id, batch.transaction_hash);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Past this point the batch transaction has expired and can no longer land.
+    fn confirmation_expired(&self, batch: &SettlementBatch) -> bool {
+        let submitted_at = batch.submitted_at.unwrap_or(batch.created_at);
+        chrono::Utc::now() > submitted_at + chrono::Duration::seconds(self.config.confirmation_timeout_secs as i64)
+    }
+
+    /// Requeue the trades of a batch that did not settle, unless the batch id is
+    /// already recorded on-chain, in which case it is reconciled as confirmed.
+    ///
+    /// A contract abort decides what happens next: settlement pauses on errors
+    /// only an operator can fix, and a lone trade that aborts the same way on
+    /// every attempt is dead-lettered straight away.
+    async fn fail_batch(&self, batch: &mut SettlementBatch, reason: &str, error: Option<&ChainError>) -> Result<()> {
+        if self.reconcile_applied(batch).await? {
+            return Ok(());
+        }
+
+        warn!("Settlement batch {} failed: {}", batch.id, reason);
+        batch.status = SettlementStatus::Failed;
+        self.database.update_settlement_batch(batch).await?;
+
+        if let Some(error) = error.filter(|e| e.needs_operator()) {
+            if !self.is_paused() {
+                error!("Pausing settlement, batch {} failed with: {}", batch.id, error);
+                self.pause();
+            }
+        }
+        let give_up = error.is_some_and(|e| !e.is_retryable() && !e.needs_operator());
+        // Without a contract abort the transaction may still be in flight
+        self.schedule_retry(batch, reason, give_up, error.is_none()).await
+    }
+
+    async fn reconcile_applied(&self, batch: &mut SettlementBatch) -> Result<bool> {
+        let applied = self.chain_client.is_settlement_batch_applied(batch.id).await?;
+
+        if applied {
+            batch.status = SettlementStatus::Confirmed;
+            self.database.update_settlement_batch(batch).await?;
+            info!("Settlement batch {} already applied on-chain, marked confirmed", batch.id);
         }
+        Ok(applied)
     }
 
     async fn process_settlement_batch(&self) -> Result<()> {
@@ -55,14 +290,45 @@ impl SettlementService {
         }
 
         // Group trades by market and create settlement batches
-        let mut batches = self.create_settlement_batches(pending_trades).await?;
+        let batches = self.create_settlement_batches(pending_trades).await?;
 
-        for batch in &mut batches {
-            if let Err(e) = self.settle_batch(batch).await {
-                error!("Failed to settle batch {}: {}", batch.id, e);
-                // Mark batch as failed
-                batch.status = SettlementStatus::Failed;
-                self.database.update_settlement_batch(batch).await?;
+        // Each signer works through the batches of its own markets, in parallel with the others
+        let mut queues: Vec<VecDeque<SettlementBatch>> = vec![VecDeque::new(); self.signers.signers().len()];
+        for batch in batches {
+            let market_id = batch.trades[0].market_id;
+            queues[self.signers.index_for_market(market_id)].push_back(batch);
+        }
+
+        let results = futures::future::join_all(
+            self.signers.signers().iter().zip(queues)
+                .filter(|(_, queue)| !queue.is_empty())
+                .map(|(signer, queue)| async move {
+                    (signer.address, self.settle_queue(signer.clone(), queue).await)
+                }),
+        ).await;
+
+        for (address, result) in results {
+            if let Err(e) = result {
+                error!("Settlement through signer {} failed: {}", address, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn settle_queue(&self, signer: Arc<SettlementSigner>, mut queue: VecDeque<SettlementBatch>) -> Result<()> {
+        while let Some(mut batch) = queue.pop_front() {
+            match self.settle_batch(&signer, &mut batch).await {
+                Ok(SettleOutcome::Submitted) => {}
+                Ok(SettleOutcome::Oversized(reason)) => {
+                    for half in self.split_oversized(&mut batch, &reason).await?.into_iter().rev() {
+                        queue.push_front(half);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to settle batch {}: {}", batch.id, e);
+                    self.fail_batch(&mut batch, &e.to_string(), e.downcast_ref::<ChainError>()).await?;
+                }
             }
         }
 
@@ -70,61 +336,217 @@ impl SettlementService {
     }
 
     async fn create_settlement_batches(&self, trades: Vec<Trade>) -> Result<Vec<SettlementBatch>> {
+        let trade_ids: Vec<Uuid> = trades.iter().map(|t| t.id).collect();
+        let retry_groups: HashMap<Uuid, (Uuid, Option<Uuid>)> = self.database
+            .get_settlement_retries(&trade_ids)
+            .await?
+            .into_iter()
+            .map(|r| (r.trade_id, (r.retry_group, r.batch_id)))
+            .collect();
+
+        // A group resubmitted under its batch id must stay exactly what was first
+        // submitted under it, so its trades are not held out of band again
+        let (pinned, unpinned): (Vec<Trade>, Vec<Trade>) = trades
+            .into_iter()
+            .partition(|t| matches!(retry_groups.get(&t.id), Some((_, Some(_)))));
+        let mut trades = pinned;
+        trades.extend(self.hold_out_of_band_trades(unpinned).await?);
+
+        // Group by market, keeping retried trades with the rest of their retry group
+        let mut groups: Vec<(u64, Option<(Uuid, Option<Uuid>)>, Vec<Trade>)> = Vec::new();
+        for trade in trades {
+            let retry_group = retry_groups.get(&trade.id).copied();
+            match groups.iter_mut().find(|(m, g, _)| *m == trade.market_id && *g == retry_group) {
+                Some((_, _, group)) => group.push(trade),
+                None => groups.push((trade.market_id, retry_group, vec![trade])),
+            }
+        }
+
+        let batch_size = self.target_batch_size().await;
         let mut batches = Vec::new();
-        let mut current_batch_trades = Vec::new();
-        let mut current_market_id: Option<u64> = None;
+        for (_, retry_group, group) in groups {
+            // A batch that may still land is resubmitted as it was, under its own id
+            if let Some((_, Some(batch_id))) = retry_group {
+                batches.push(self.create_batch_from_trades(group, Some(batch_id)).await?);
+                continue;
+            }
+            for chunk in group.chunks(batch_size) {
+                batches.push(self.create_batch_from_trades(chunk.to_vec(), None).await?);
+            }
+        }
+
+        Ok(batches)
+    }
+
+    /// Keep trades priced outside their market's oracle band out of settlement,
+    /// holding them for review. Trades an operator has released settle as priced.
+    async fn hold_out_of_band_trades(&self, trades: Vec<Trade>) -> Result<Vec<Trade>> {
+        let trade_ids: Vec<Uuid> = trades.iter().map(|t| t.id).collect();
+        let released: HashSet<Uuid> = self.database
+            .get_settlement_holds(&trade_ids)
+            .await?
+            .into_iter()
+            .filter(|h| h.released_at.is_some())
+            .map(|h| h.trade_id)
+            .collect();
 
+        let mut bands: HashMap<u64, Option<PriceBand>> = HashMap::new();
+        let mut settleable = Vec::with_capacity(trades.len());
         for trade in trades {
-            // Group by market and batch size
-            if current_market_id != Some(trade.market_id) 
-                || current_batch_trades.len() >= self.config.batch_size {
-                
-                if !current_batch_trades.is_empty() {
-                    batches.push(self.create_batch_from_trades(current_batch_trades).await?);
-                    current_batch_trades = Vec::new();
+            let band = match bands.get(&trade.market_id) {
+                Some(band) => *band,
+                None => {
+                    let band = match self.price_band(trade.market_id).await {
+                        Ok(band) => Some(band),
+                        Err(e) => {
+                            warn!("Not settling market {} this round: {}", trade.market_id, e);
+                            None
+                        }
+                    };
+                    bands.insert(trade.market_id, band);
+                    band
                 }
-                current_market_id = Some(trade.market_id);
+            };
+            let Some(band) = band else {
+                continue;
+            };
+
+            if released.contains(&trade.id) || band.contains(trade.price) {
+                settleable.push(trade);
+                continue;
             }
 
-            current_batch_trades.push(trade);
+            warn!("Holding trade {} for review: price {} outside [{}, {}] around oracle price {}",
+                trade.id, trade.price, band.min_price, band.max_price, band.reference_price);
+            self.database.insert_settlement_hold(&SettlementHold {
+                trade_id: trade.id,
+                reference_price: band.reference_price,
+                min_price: band.min_price,
+                max_price: band.max_price,
+                held_at: chrono::Utc::now(),
+                released_at: None,
+            }).await?;
         }
 
-        // Add remaining trades
-        if !current_batch_trades.is_empty() {
-            batches.push(self.create_batch_from_trades(current_batch_trades).await?);
+        Ok(settleable)
+    }
+
+    /// Settlement price bounds of a market: its oracle price plus or minus the market tolerance.
+    async fn price_band(&self, market_id: u64) -> Result<PriceBand> {
+        let price = self.price_service.get_price(market_id).await
+            .ok_or_else(|| anyhow::anyhow!("no oracle price for market {}", market_id))?;
+
+        let age = chrono::Utc::now() - price.updated_at;
+        if age > chrono::Duration::seconds(self.config.max_reference_price_age_secs as i64) {
+            return Err(anyhow::anyhow!("oracle price is {}s old", age.num_seconds()));
         }
 
-        Ok(batches)
+        let tolerance = self.markets.iter()
+            .find(|m| m.market_id == market_id)
+            .and_then(|m| m.settlement_price_tolerance)
+            .unwrap_or(self.config.max_price_slippage);
+        let tolerance: Decimal = FromPrimitive::from_f64(tolerance)
+            .unwrap_or(Decimal::new(5, 2)); // 5%
+
+        Ok(PriceBand {
+            reference_price: price.index_price,
+            min_price: (price.index_price * (Decimal::ONE - tolerance)).max(Decimal::ZERO),
+            max_price: price.index_price * (Decimal::ONE + tolerance),
+            oracle_timestamp: price.updated_at.timestamp() as u64,
+        })
     }
 
-    async fn create_batch_from_trades(&self, trades: Vec<Trade>) -> Result<SettlementBatch> {
+    /// Put the trades of a failed batch back into the queue.
+    ///
+    /// The batch is split in two so a bad fill ends up alone after a few
+    /// rounds; each half waits out an exponential backoff. A batch that `may_land`
+    /// is instead resubmitted whole under its own id, so a late landing of the
+    /// first transaction and the retry cannot both apply. Trades that have
+    /// used up `max_attempts`, or that failed alone with `give_up`, are
+    /// dead-lettered for an operator to inspect.
+    async fn schedule_retry(&self, batch: &SettlementBatch, error: &str, give_up: bool, may_land: bool) -> Result<()> {
+        let trade_ids: Vec<Uuid> = batch.trades.iter().map(|t| t.id).collect();
+        let attempts: HashMap<Uuid, u32> = self.database
+            .get_settlement_retries(&trade_ids)
+            .await?
+            .into_iter()
+            .map(|r| (r.trade_id, r.attempts))
+            .collect();
+
+        self.database.release_settlement_batch_trades(batch.id).await?;
+
+        let now = chrono::Utc::now();
+        let (part_size, batch_id) = if may_land {
+            (batch.trades.len().max(1), Some(batch.id))
+        } else {
+            (batch.trades.len().div_ceil(2).max(1), None)
+        };
+        for part in batch.trades.chunks(part_size) {
+            let retry_group = Uuid::new_v4();
+            // The group becomes due, and is dead-lettered, as one, so a group that
+            // may land is never picked up in part
+            let group_attempt = part.iter()
+                .map(|t| attempts.get(&t.id).copied().unwrap_or(0) + 1)
+                .max()
+                .unwrap_or(1);
+            let group_exhausted = may_land && group_attempt >= self.config.max_attempts;
+            for trade in part {
+                let attempt = attempts.get(&trade.id).copied().unwrap_or(0) + 1;
+                let dead_lettered = attempt >= self.config.max_attempts || group_exhausted || (give_up && part.len() == 1);
+                let retry = SettlementRetry {
+                    trade_id: trade.id,
+                    retry_group,
+                    batch_id,
+                    attempts: attempt,
+                    next_attempt_at: now + self.retry_delay(group_attempt),
+                    last_error: Some(error.to_string()),
+                    dead_lettered_at: dead_lettered.then_some(now),
+                    updated_at: now,
+                };
+                self.database.upsert_settlement_retry(&retry).await?;
+
+                if dead_lettered {
+                    warn!("Trade {} dead-lettered after {} settlement attempts: {}", trade.id, attempt, error);
+                }
+            }
+        }
+
+        info!("Requeued {} trades from failed settlement batch {}", batch.trades.len(), batch.id);
+        Ok(())
+    }
+
+    fn retry_delay(&self, attempt: u32) -> chrono::Duration {
+        let factor = 1u64 << attempt.saturating_sub(1).min(32);
+        let secs = self.config.retry_base_delay_secs
+            .saturating_mul(factor)
+            .min(self.config.retry_max_delay_secs);
+        chrono::Duration::seconds(secs as i64)
+    }
+
+    async fn create_batch_from_trades(&self, trades: Vec<Trade>, id: Option<Uuid>) -> Result<SettlementBatch> {
         if trades.is_empty() {
             return Err(anyhow::anyhow!("Cannot create batch from empty trades"));
         }
 
         let market_id = trades[0].market_id;
         
-        // Calculate price bounds with slippage protection
-        let prices: Vec<_> = trades.iter().map(|t| t.price).collect();
-        let min_price = prices.iter().min().unwrap().clone();
-        let max_price = prices.iter().max().unwrap().clone();
-        
-        let slippage = FromPrimitive::from_f64(self.config.max_price_slippage)
-            .unwrap_or(rust_decimal::Decimal::new(5, 2)); // 5%
-        
-        let price_range = max_price - min_price;
-        let adjusted_min = min_price - (price_range * slippage);
-        let adjusted_max = max_price + (price_range * slippage);
+        // Bound prices by the oracle band, widened only for trades released from review
+        let band = self.price_band(market_id).await?;
+        let min_price = trades.iter().map(|t| t.price).fold(band.min_price, Decimal::min);
+        let max_price = trades.iter().map(|t| t.price).fold(band.max_price, Decimal::max);
 
         let batch = SettlementBatch {
-            id: Uuid::new_v4(),
+            id: id.unwrap_or_else(Uuid::new_v4),
             trades,
-            oracle_timestamp: chrono::Utc::now().timestamp() as u64,
-            min_price: adjusted_min.max(rust_decimal::Decimal::ZERO),
-            max_price: adjusted_max,
+            oracle_timestamp: band.oracle_timestamp,
+            min_price,
+            max_price,
             expiry_timestamp: (chrono::Utc::now() + chrono::Duration::seconds(300)).timestamp() as u64, // 5 min expiry
             status: SettlementStatus::Pending,
             transaction_hash: None,
+            vm_status: None,
+            gas_used: None,
+            submitted_at: None,
             created_at: chrono::Utc::now(),
         };
 
@@ -136,40 +558,521 @@ impl SettlementService {
         Ok(batch)
     }
 
-    async fn settle_batch(&self, batch: &mut SettlementBatch) -> Result<()> {
-        info!("Settling batch {} with {} trades", batch.id, batch.trades.len());
+    async fn settle_batch(&self, signer: &SettlementSigner, batch: &mut SettlementBatch) -> Result<SettleOutcome> {
+        info!("Settling batch {} with {} trades through {}", batch.id, batch.trades.len(), signer.address);
+
+        let net_deltas = if self.config.netting_enabled {
+            let deltas = netting::net_trades(batch.id, &batch.trades, SETTLEMENT_FEE_BPS)?;
+            netting::verify_netting(&batch.trades, &deltas, SETTLEMENT_FEE_BPS)?;
+            debug!("Netted {} trades of batch {} into {} deltas", batch.trades.len(), batch.id, deltas.len());
+            Some(deltas)
+        } else {
+            None
+        };
+
+        // Size check and gas simulation before anything is committed to this batch
+        let payload_bytes = self.chain_client
+            .settlement_payload_size(batch, net_deltas.as_deref())?;
+        if payload_bytes > self.config.max_payload_bytes && batch.trades.len() > 1 {
+            return Ok(SettleOutcome::Oversized(format!("payload of {} bytes", payload_bytes)));
+        }
+
+        let estimate = self.chain_client
+            .simulate_settlement_batch(signer, batch, net_deltas.as_deref(), self.config.max_gas_amount)
+            .await?;
+        if !estimate.success {
+            if estimate.is_out_of_gas() && batch.trades.len() > 1 {
+                return Ok(SettleOutcome::Oversized(estimate.vm_status));
+            }
+            let error = self.chain_client.decode_vm_status(&estimate.vm_status);
+            warn!("Settlement simulation of batch {} failed: {}", batch.id, error);
+            return Err(error.into());
+        }
+
+        self.sizing.lock().await.observe(batch.trades.len(), estimate.gas_used, payload_bytes);
+        let max_gas_amount = ((estimate.gas_used as f64 * (1.0 + self.config.gas_headroom)).ceil() as u64)
+            .clamp(estimate.gas_used, self.config.max_gas_amount);
 
         // Mark trades as part of this batch
         for trade in &batch.trades {
             self.database.update_trade_settlement_batch(trade.id, batch.id).await?;
         }
 
+        if let Some(deltas) = &net_deltas {
+            self.database.insert_net_deltas(deltas).await?;
+        }
+
         // Submit to blockchain with timeout
-        let settlement_future = async {
-            let client = self.aptos_client.lock().await;
-            client.submit_settlement_batch(batch).await
-        };
+        let settlement_future = self.chain_client
+            .submit_settlement_batch(signer, batch, net_deltas.as_deref(), max_gas_amount);
 
         match timeout(Duration::from_secs(30), settlement_future).await {
             Ok(Ok(transaction_hash)) => {
                 batch.transaction_hash = Some(transaction_hash.clone());
-                batch.status = SettlementStatus::Confirmed;
-                self.database.update_settlement_batch(batch).await?;
-                info!("Settlement batch {} confirmed with tx: {}", batch.id, transaction_hash);
-                Ok(())
-            }
-            Ok(Err(e)) => {
-                warn!("Settlement batch {} failed: {}", batch.id, e);
-                batch.status = SettlementStatus::Failed;
+                batch.status = SettlementStatus::Submitted;
+                batch.submitted_at = Some(chrono::Utc::now());
                 self.database.update_settlement_batch(batch).await?;
-                Err(e)
+                info!("Settlement batch {} submitted with tx: {}", batch.id, transaction_hash);
+                Ok(SettleOutcome::Submitted)
             }
+            Ok(Err(e)) => Err(e),
             Err(_) => {
-                warn!("Settlement batch {} timed out", batch.id);
-                batch.status = SettlementStatus::Failed;
+                // The transaction may still land, so leave the outcome to the
+                // confirmation tracker, which checks the batch id on-chain. Its
+                // sequence number stays taken until the tracker sees it expire.
+                warn!("Settlement batch {} timed out, awaiting reconciliation", batch.id);
+                batch.status = SettlementStatus::Submitted;
+                batch.submitted_at = Some(chrono::Utc::now());
                 self.database.update_settlement_batch(batch).await?;
-                Err(anyhow::anyhow!("Settlement timeout"))
+                Ok(SettleOutcome::Submitted)
+            }
+        }
+    }
+
+    /// Submit queued liquidation penalties whose liquidation fills have all settled,
+    /// so the penalty is taken from the balance those fills left. A penalty goes
+    /// through the signer of its market.
+    async fn charge_liquidation_penalties(&self) -> Result<()> {
+        let penalties = self.database.get_active_liquidation_penalties().await?;
+
+        for penalty in penalties.iter().filter(|p| p.status == PenaltyStatus::Queued) {
+            if !self.database.order_trades_settled(penalty.order_id).await? {
+                debug!("Liquidation penalty {} waits for its fills to settle", penalty.liquidation_id);
+                continue;
+            }
+
+            let signer = &self.signers.signers()[self.signers.index_for_market(penalty.market_id)];
+            match self.chain_client.submit_liquidation_penalty(signer, penalty).await {
+                Ok(tx_hash) => {
+                    self.database.mark_liquidation_penalty_submitted(penalty.liquidation_id, &tx_hash).await?;
+                    info!("Liquidation penalty {} of {} for {} submitted with tx: {}",
+                        penalty.liquidation_id, penalty.amount, penalty.user_address, tx_hash);
+                }
+                Err(e) => {
+                    self.retry_liquidation_penalty(penalty, &e.to_string(), e.downcast_ref::<ChainError>()).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn track_liquidation_penalties(&self) -> Result<()> {
+        let penalties = self.database.get_active_liquidation_penalties().await?;
+
+        for penalty in penalties.iter().filter(|p| p.status == PenaltyStatus::Submitted) {
+            let status = match penalty.tx_hash.as_deref() {
+                Some(tx_hash) => self.chain_client.check_transaction_status(tx_hash).await?,
+                None => TransactionStatus::Pending,
+            };
+
+            match status {
+                TransactionStatus::Success { .. } => self.credit_liquidation_penalty(penalty).await?,
+                TransactionStatus::Failed { vm_status, .. } => {
+                    let error = self.chain_client.decode_vm_status(&vm_status);
+                    self.retry_liquidation_penalty(penalty, &error.to_string(), Some(&error)).await?;
+                }
+                TransactionStatus::Pending => {
+                    let timeout = chrono::Duration::seconds(self.config.confirmation_timeout_secs as i64);
+                    if chrono::Utc::now() - penalty.updated_at > timeout {
+                        // An expired transaction leaves a gap in its signer's sequence
+                        self.signers.resync_all().await;
+                        self.retry_liquidation_penalty(penalty, "transaction expired", None).await?;
+                    }
+                }
             }
         }
+
+        Ok(())
+    }
+
+    /// Queue a penalty that was not charged for another attempt, or fail it when
+    /// the contract rejects it outright or it has used up `max_attempts`.
+    async fn retry_liquidation_penalty(
+        &self,
+        penalty: &LiquidationPenalty,
+        reason: &str,
+        error: Option<&ChainError>,
+    ) -> Result<()> {
+        // An earlier submission may have landed after all
+        if self.chain_client.is_settlement_batch_applied(penalty.liquidation_id).await? {
+            return self.credit_liquidation_penalty(penalty).await;
+        }
+
+        // Submitted penalties counted their attempt when they went out
+        let attempts = match penalty.status {
+            PenaltyStatus::Queued => penalty.attempts + 1,
+            _ => penalty.attempts,
+        };
+        let retryable = error.is_none_or(ChainError::is_retryable);
+        if !retryable || attempts >= self.config.max_attempts {
+            warn!("Liquidation penalty {} of {} for {} failed: {}",
+                penalty.liquidation_id, penalty.amount, penalty.user_address, reason);
+            return self.database.fail_liquidation_penalty(penalty.liquidation_id, reason).await;
+        }
+
+        warn!("Liquidation penalty {} not charged, will retry: {}", penalty.liquidation_id, reason);
+        self.database.requeue_liquidation_penalty(penalty.liquidation_id, reason).await
+    }
+
+    /// Credit a penalty the chain has charged to the insurance fund, once.
+    async fn credit_liquidation_penalty(&self, penalty: &LiquidationPenalty) -> Result<()> {
+        if !self.database.charge_liquidation_penalty(penalty.liquidation_id).await? {
+            return Ok(());
+        }
+
+        let amount = Decimal::from(penalty.amount) / Decimal::from(USDC_UNIT);
+        self.insurance_fund
+            .credit(InsuranceEntryKind::LiquidationPenalty, penalty.market_id, amount, Some(penalty.liquidation_id))
+            .await?;
+        info!("Liquidation penalty {} of {} for {} charged", penalty.liquidation_id, amount, penalty.user_address);
+        Ok(())
+    }
+
+    /// Replace a batch that does not fit in one transaction with two halves.
+    async fn split_oversized(&self, batch: &mut SettlementBatch, reason: &str) -> Result<Vec<SettlementBatch>> {
+        info!("Splitting settlement batch {} of {} trades: {}", batch.id, batch.trades.len(), reason);
+
+        // Not Failed: its trades now belong to the halves, so it must not be retried or abandoned
+        batch.status = SettlementStatus::Split;
+        batch.vm_status = Some(reason.to_string());
+        self.database.update_settlement_batch(batch).await?;
+
+        let half = batch.trades.len().div_ceil(2);
+        let mut halves = Vec::new();
+        for part in batch.trades.chunks(half) {
+            halves.push(self.create_batch_from_trades(part.to_vec(), None).await?);
+        }
+        Ok(halves)
+    }
+
+    /// Trades per batch that should fit under the gas ceiling, from recent simulations.
+    async fn target_batch_size(&self) -> usize {
+        let sizing = self.sizing.lock().await;
+        let by_gas = sizing.gas_per_trade
+            .map(|g| self.config.max_gas_amount as f64 / (g * (1.0 + self.config.gas_headroom)))
+            .unwrap_or(f64::MAX);
+        let by_bytes = sizing.bytes_per_trade
+            .map(|b| self.config.max_payload_bytes as f64 / b)
+            .unwrap_or(f64::MAX);
+
+        (by_gas.min(by_bytes).floor() as usize).clamp(1, self.config.batch_size.max(1))
+    }
+}
+
+/// Allowed settlement price range of a market around its oracle price.
+#[derive(Debug, Clone, Copy)]
+struct PriceBand {
+    reference_price: Decimal,
+    min_price: Decimal,
+    max_price: Decimal,
+    oracle_timestamp: u64,
+}
+
+impl PriceBand {
+    fn contains(&self, price: Decimal) -> bool {
+        price >= self.min_price && price <= self.max_price
+    }
+}
+
+enum SettleOutcome {
+    Submitted,
+    /// Too much gas or payload for one transaction; nothing was submitted
+    Oversized(String),
+}
+
+/// Running per-trade gas and payload cost, learned from simulations.
+#[derive(Default)]
+struct BatchSizing {
+    gas_per_trade: Option<f64>,
+    bytes_per_trade: Option<f64>,
+}
+
+impl BatchSizing {
+    fn observe(&mut self, trades: usize, gas_used: u64, payload_bytes: usize) {
+        if trades == 0 {
+            return;
+        }
+        let gas = gas_used as f64 / trades as f64;
+        let bytes = payload_bytes as f64 / trades as f64;
+        // Exponential moving average, weighted towards recent batches
+        self.gas_per_trade = Some(self.gas_per_trade.map_or(gas, |g| 0.7 * g + 0.3 * gas));
+        self.bytes_per_trade = Some(self.bytes_per_trade.map_or(bytes, |b| 0.7 * b + 0.3 * bytes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        matching_engine::MatchingEngine,
+        mock_chain::testing::{mock_chain, test_config, ScratchDatabase},
+        models::OrderSide,
+        pricing::MockPriceSource,
+        redis_client::RedisClient,
+    };
+    use tokio::sync::RwLock;
+
+    const MARKET_ID: u64 = 1;
+
+    /// A settlement service over a scratch database and the mock chain.
+    struct Harness {
+        service: SettlementService,
+        database: Arc<Database>,
+        scratch: ScratchDatabase,
+    }
+
+    /// Creates a scratch database on the server in `TEST_DATABASE_URL`; the
+    /// mock chain commits instantly and aborts every `fail_every`-th transaction.
+    async fn harness(fail_every: u64) -> Harness {
+        harness_with(|config| config.aptos.mock_chain.fail_every = fail_every).await
+    }
+
+    /// A harness whose defaults above are then adjusted by `configure`.
+    async fn harness_with(configure: impl FnOnce(&mut Config)) -> Harness {
+        let mut config = test_config();
+        let redis_url = std::env::var("TEST_REDIS_URL").unwrap_or(config.redis.url.clone());
+        let scratch = ScratchDatabase::create("settlement").await;
+        let database = scratch.database.clone();
+
+        // Failed trades are due again straight away
+        config.settlement.retry_base_delay_secs = 0;
+        configure(&mut config);
+
+        let redis = RedisClient::new(&redis_url).await.unwrap();
+        let matching_engine = MatchingEngine::new(database.clone(), Arc::new(RwLock::new(redis))).await.unwrap();
+        let price_service = Arc::new(PriceService::new(
+            Arc::new(MockPriceSource::new(HashMap::from([(MARKET_ID, Decimal::from(100))]))),
+            Arc::new(RwLock::new(matching_engine)),
+            config.pricing.clone(),
+        ));
+        price_service.update_prices().await.unwrap();
+
+        let (chain, signers) = mock_chain(&config.aptos).await;
+        let service = SettlementService::new(
+            Arc::new(chain),
+            signers,
+            database.clone(),
+            price_service,
+            Arc::new(InsuranceFund::new(database.clone(), config.insurance.clone()).await.unwrap()),
+            config.markets.clone(),
+            config.settlement.clone(),
+        ).await.unwrap();
+
+        Harness { service, database, scratch }
+    }
+
+    impl Harness {
+        async fn insert_trade(&self) -> Trade {
+            let trade = Trade {
+                id: Uuid::new_v4(),
+                market_id: MARKET_ID,
+                taker_order_id: Uuid::new_v4(),
+                maker_order_id: Uuid::new_v4(),
+                taker_address: "0xa11ce".to_string(),
+                maker_address: "0xb0b".to_string(),
+                size: Decimal::ONE,
+                price: Decimal::from(100),
+                side: OrderSide::Buy,
+                created_at: chrono::Utc::now(),
+                settlement_batch_id: None,
+            };
+            self.database.insert_trade(&trade).await.unwrap();
+            trade
+        }
+
+        /// One settlement pass followed by one confirmation pass.
+        async fn settle_round(&self) {
+            self.service.process_settlement_batch().await.unwrap();
+            self.service.track_submitted_batches().await.unwrap();
+        }
+
+        /// Queue a penalty of `amount` on the taker of `trade`, as if its order were a liquidation.
+        async fn insert_penalty(&self, trade: &Trade, amount: u64) -> LiquidationPenalty {
+            let now = chrono::Utc::now();
+            let penalty = LiquidationPenalty {
+                liquidation_id: Uuid::new_v4(),
+                order_id: trade.taker_order_id,
+                user_address: trade.taker_address.clone(),
+                market_id: trade.market_id,
+                amount,
+                status: PenaltyStatus::Queued,
+                tx_hash: None,
+                error: None,
+                attempts: 0,
+                created_at: now,
+                updated_at: now,
+            };
+            self.database.insert_liquidation_penalty(&penalty).await.unwrap();
+            penalty
+        }
+
+        /// One penalty submission pass followed by one confirmation pass.
+        async fn penalty_round(&self) {
+            self.service.charge_liquidation_penalties().await.unwrap();
+            self.service.track_liquidation_penalties().await.unwrap();
+        }
+
+        async fn batches(&self, status: SettlementStatus) -> Vec<SettlementBatch> {
+            self.database.get_settlement_batches_by_status(status).await.unwrap()
+        }
+
+        async fn teardown(self) {
+            let Harness { service, database, scratch } = self;
+            drop(service);
+            drop(database);
+            scratch.drop().await;
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres in TEST_DATABASE_URL and Redis"]
+    async fn settles_pending_trades_in_a_confirmed_batch() {
+        let h = harness(0).await;
+        let trades = [h.insert_trade().await, h.insert_trade().await];
+
+        h.settle_round().await;
+
+        let confirmed = h.batches(SettlementStatus::Confirmed).await;
+        assert_eq!(confirmed.len(), 1);
+        assert!(confirmed[0].transaction_hash.is_some());
+        let mut settled: Vec<Uuid> = confirmed[0].trades.iter().map(|t| t.id).collect();
+        let mut expected: Vec<Uuid> = trades.iter().map(|t| t.id).collect();
+        settled.sort();
+        expected.sort();
+        assert_eq!(settled, expected);
+        assert!(h.database.get_pending_trades().await.unwrap().is_empty());
+
+        h.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres in TEST_DATABASE_URL and Redis"]
+    async fn requeues_trades_of_a_failed_batch() {
+        let h = harness(1).await;
+        let trade = h.insert_trade().await;
+
+        h.settle_round().await;
+
+        assert!(h.batches(SettlementStatus::Confirmed).await.is_empty());
+        let failed = h.batches(SettlementStatus::Failed).await;
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].vm_status.as_deref(), Some("Injected mock chain failure"));
+
+        let retries = h.database.get_settlement_retries(&[trade.id]).await.unwrap();
+        assert_eq!(retries.len(), 1);
+        assert_eq!(retries[0].attempts, 1);
+        assert!(retries[0].dead_lettered_at.is_none());
+        let pending = h.database.get_pending_trades().await.unwrap();
+        assert_eq!(pending.iter().map(|t| t.id).collect::<Vec<_>>(), vec![trade.id]);
+
+        h.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres in TEST_DATABASE_URL and Redis"]
+    async fn retries_a_failed_trade_until_it_settles() {
+        // Every second transaction aborts: the first batch lands, the second fails
+        let h = harness(2).await;
+        h.insert_trade().await;
+        h.settle_round().await;
+        let retried = h.insert_trade().await;
+        h.settle_round().await;
+        assert_eq!(h.batches(SettlementStatus::Failed).await.len(), 1);
+
+        h.settle_round().await;
+
+        let confirmed = h.batches(SettlementStatus::Confirmed).await;
+        assert_eq!(confirmed.len(), 2);
+        assert!(confirmed.iter().any(|b| b.trades.iter().any(|t| t.id == retried.id)));
+        assert!(h.database.get_pending_trades().await.unwrap().is_empty());
+
+        h.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres in TEST_DATABASE_URL and Redis"]
+    async fn resubmits_an_expired_batch_under_its_own_id() {
+        // The batch is given up on while its transaction is still pending
+        let h = harness_with(|config| {
+            config.aptos.mock_chain.confirmation_delay_ms = 200;
+            config.settlement.confirmation_timeout_secs = 0;
+        }).await;
+        let trade = h.insert_trade().await;
+
+        h.settle_round().await;
+        let failed = h.batches(SettlementStatus::Failed).await;
+        assert_eq!(failed.len(), 1);
+        let retries = h.database.get_settlement_retries(&[trade.id]).await.unwrap();
+        assert_eq!(retries[0].batch_id, Some(failed[0].id));
+
+        // The first transaction lands, so the resubmission is rejected as already applied
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        h.settle_round().await;
+
+        let confirmed = h.batches(SettlementStatus::Confirmed).await;
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].id, failed[0].id);
+        assert!(h.database.get_pending_trades().await.unwrap().is_empty());
+
+        h.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres in TEST_DATABASE_URL and Redis"]
+    async fn dead_letters_a_trade_after_max_attempts() {
+        let h = harness(1).await;
+        let trade = h.insert_trade().await;
+
+        for _ in 0..h.service.config.max_attempts {
+            h.settle_round().await;
+        }
+
+        let retries = h.database.get_settlement_retries(&[trade.id]).await.unwrap();
+        assert_eq!(retries[0].attempts, h.service.config.max_attempts);
+        assert!(retries[0].dead_lettered_at.is_some());
+        assert!(h.database.get_pending_trades().await.unwrap().is_empty());
+
+        h.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres in TEST_DATABASE_URL and Redis"]
+    async fn charges_a_liquidation_penalty_once_its_fills_settle() {
+        let h = harness(0).await;
+        let trade = h.insert_trade().await;
+        h.service.chain_client.deposit_funds(&trade.taker_address, 5 * USDC_UNIT).await.unwrap();
+        h.insert_penalty(&trade, USDC_UNIT).await;
+
+        h.penalty_round().await;
+        let queued = h.database.get_active_liquidation_penalties().await.unwrap();
+        assert_eq!(queued[0].status, PenaltyStatus::Queued);
+        assert_eq!(h.service.insurance_fund.balance().await, Decimal::ZERO);
+
+        h.settle_round().await;
+        h.penalty_round().await;
+
+        assert!(h.database.get_active_liquidation_penalties().await.unwrap().is_empty());
+        assert_eq!(h.service.insurance_fund.balance().await, Decimal::ONE);
+        assert_eq!(h.service.chain_client.get_user_collateral(&trade.taker_address).await.unwrap(), 4 * USDC_UNIT);
+
+        h.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres in TEST_DATABASE_URL and Redis"]
+    async fn does_not_credit_a_penalty_the_account_cannot_pay() {
+        let h = harness(0).await;
+        let trade = h.insert_trade().await;
+        h.insert_penalty(&trade, USDC_UNIT).await;
+
+        h.settle_round().await;
+        h.penalty_round().await;
+
+        assert!(h.database.get_active_liquidation_penalties().await.unwrap().is_empty());
+        assert_eq!(h.service.insurance_fund.balance().await, Decimal::ZERO);
+
+        h.teardown().await;
     }
 }
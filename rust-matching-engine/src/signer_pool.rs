@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use aptos_rust_sdk_types::api_types::address::AccountAddress;
+use std::{str::FromStr, sync::Arc};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::{
+    chain::normalize_address,
+    config::{AptosConfig, KeySource},
+    keys::{self, TransactionSigner},
+};
+
+/// An account that submits transactions with a locally tracked sequence number.
+///
+/// The next sequence number is taken before a transaction is submitted, so
+/// several can be in flight at once and one that times out keeps its number;
+/// it is dropped and re-read from chain after a rejection or once every
+/// transaction in flight has expired.
+#[derive(Debug)]
+pub struct SettlementSigner {
+    pub address: AccountAddress,
+    pub(crate) signer: Arc<dyn TransactionSigner>,
+    /// Next sequence number to use, `None` until synced from chain
+    pub(crate) next_sequence: Mutex<Option<u64>>,
+}
+
+impl SettlementSigner {
+    async fn load(address: &str, key: &KeySource, chain_id: u8) -> Result<Self> {
+        Ok(Self {
+            address: AccountAddress::from_str(address)
+                .with_context(|| format!("Invalid settlement signer address {}", address))?,
+            signer: keys::load_signer(key, address, chain_id).await
+                .with_context(|| format!("Failed to load key for settlement signer {}", address))?,
+            next_sequence: Mutex::new(None),
+        })
+    }
+
+    /// Forget the local sequence number so the next submission re-reads it from chain.
+    pub async fn resync(&self) {
+        if self.next_sequence.lock().await.take().is_some() {
+            warn!("Signer {} sequence number will resync from chain", self.address);
+        }
+    }
+}
+
+/// Settlement signers, with every market pinned to one of them.
+///
+/// A market's batches always go through the same account, so its sequence
+/// numbers keep them in order on-chain while other markets settle in parallel.
+///
+/// The admin account is loaded once and shared with the chain client, which
+/// sends funding, oracle, unfreeze and withdrawal transactions through it, so
+/// when the admin also settles every admin transaction draws from one counter.
+pub struct SignerPool {
+    admin: Arc<SettlementSigner>,
+    signers: Vec<Arc<SettlementSigner>>,
+}
+
+impl SignerPool {
+    /// The configured settlement signers, or the admin account alone when none are set.
+    pub async fn from_config(config: &AptosConfig) -> Result<Self> {
+        let admin = Arc::new(
+            SettlementSigner::load(&config.admin_address, &config.admin_key, config.chain_id).await?,
+        );
+
+        let mut signers = Vec::new();
+        if config.settlement_signers.is_empty() {
+            signers.push(admin.clone());
+        }
+        for s in &config.settlement_signers {
+            if normalize_address(&s.address) == normalize_address(&config.admin_address) {
+                signers.push(admin.clone());
+                continue;
+            }
+            signers.push(Arc::new(SettlementSigner::load(&s.address, &s.key, config.chain_id).await?));
+        }
+
+        info!("Settlement signer pool loaded with {} signers", signers.len());
+        Ok(Self { admin, signers })
+    }
+
+    /// The admin account, for every transaction the admin signs.
+    pub fn admin(&self) -> &Arc<SettlementSigner> {
+        &self.admin
+    }
+
+    pub fn signers(&self) -> &[Arc<SettlementSigner>] {
+        &self.signers
+    }
+
+    pub fn index_for_market(&self, market_id: u64) -> usize {
+        (market_id % self.signers.len() as u64) as usize
+    }
+
+    /// Resync every account of the pool, the admin included.
+    pub async fn resync_all(&self) {
+        self.admin.resync().await;
+        for signer in &self.signers {
+            signer.resync().await;
+        }
+    }
+}
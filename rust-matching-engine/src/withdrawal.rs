@@ -0,0 +1,303 @@
+use anyhow::Result;
+use chrono::Utc;
+use rust_decimal::Decimal;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::interval;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    chain::{usdc_amount, ChainClient, TransactionStatus},
+    collateral::CollateralLedger,
+    config::WithdrawalConfig,
+    database::Database,
+    margin::MarginEngine,
+    models::{Withdrawal, WithdrawalStatus},
+    pricing::PriceService,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WithdrawalError {
+    #[error("requested {requested} USDC but only {withdrawable} USDC is withdrawable")]
+    InsufficientEquity { requested: Decimal, withdrawable: Decimal },
+    #[error("withdrawal {0} was already requested")]
+    Duplicate(Uuid),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Queues withdrawals of vault collateral and submits them through
+/// `vault_coin::withdraw_for`.
+///
+/// A request is accepted only if it fits in the account's withdrawable
+/// equity; its amount is then held in the collateral ledger so it cannot back
+/// new orders, and released once the withdrawal completes or fails. The
+/// check is repeated right before submission, since positions may have lost
+/// value while the request was queued.
+///
+/// The transaction's hash is recorded before it is submitted, so a lost
+/// submission response is settled by looking the hash up rather than by
+/// withdrawing again. Only once it has expired uncommitted is another signed.
+pub struct WithdrawalService {
+    chain_client: Arc<dyn ChainClient>,
+    database: Arc<Database>,
+    collateral_ledger: Arc<CollateralLedger>,
+    margin_engine: Arc<RwLock<MarginEngine>>,
+    price_service: Arc<PriceService>,
+    config: WithdrawalConfig,
+    /// Serializes the withdrawable check with queuing, so concurrent requests cannot both pass it
+    request_lock: Mutex<()>,
+}
+
+impl WithdrawalService {
+    pub async fn new(
+        chain_client: Arc<dyn ChainClient>,
+        database: Arc<Database>,
+        collateral_ledger: Arc<CollateralLedger>,
+        margin_engine: Arc<RwLock<MarginEngine>>,
+        price_service: Arc<PriceService>,
+        config: WithdrawalConfig,
+    ) -> Result<Self> {
+        let active = database.get_active_withdrawals().await?;
+        for withdrawal in &active {
+            collateral_ledger.hold(&withdrawal.user_address, usdc_amount(withdrawal.amount)).await;
+        }
+        info!("Withdrawal service loaded {} pending withdrawals", active.len());
+
+        Ok(Self {
+            chain_client,
+            database,
+            collateral_ledger,
+            margin_engine,
+            price_service,
+            config,
+            request_lock: Mutex::new(()),
+        })
+    }
+
+    /// Collateral the user can still withdraw in whole USDC, after pending withdrawals.
+    pub async fn withdrawable(&self, user_address: &str) -> Result<Decimal> {
+        Ok(self.remaining_equity(user_address).await?.max(Decimal::ZERO))
+    }
+
+    /// Check the amount against withdrawable equity and queue the withdrawal
+    /// under `id`, which must not have been used before.
+    pub async fn request_withdrawal(&self, id: Uuid, user_address: &str, amount: u64) -> Result<Withdrawal, WithdrawalError> {
+        let _guard = self.request_lock.lock().await;
+
+        if self.database.get_withdrawal(id).await?.is_some() {
+            return Err(WithdrawalError::Duplicate(id));
+        }
+
+        let withdrawable = self.withdrawable(user_address).await?;
+        let requested = usdc_amount(amount);
+        if requested > withdrawable {
+            return Err(WithdrawalError::InsufficientEquity { requested, withdrawable });
+        }
+
+        let now = Utc::now();
+        let withdrawal = Withdrawal {
+            id,
+            user_address: user_address.to_string(),
+            amount,
+            status: WithdrawalStatus::Queued,
+            tx_hash: None,
+            sequence_number: None,
+            error: None,
+            attempts: 0,
+            created_at: now,
+            updated_at: now,
+        };
+        self.database.insert_withdrawal(&withdrawal).await?;
+        self.collateral_ledger.hold(user_address, requested).await;
+
+        info!("Queued withdrawal {} of {} for {}", withdrawal.id, amount, user_address);
+        Ok(withdrawal)
+    }
+
+    pub async fn start_processing_loop(&self) -> Result<()> {
+        info!("Starting withdrawal processing loop");
+        let mut interval = interval(Duration::from_secs(self.config.process_interval_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+            self.process_withdrawals().await;
+        }
+    }
+
+    /// Submit queued withdrawals and check on submitted ones.
+    async fn process_withdrawals(&self) {
+        let withdrawals = match self.database.get_active_withdrawals().await {
+            Ok(withdrawals) => withdrawals,
+            Err(e) => {
+                warn!("Failed to load pending withdrawals: {}", e);
+                return;
+            }
+        };
+
+        for withdrawal in withdrawals {
+            let result = match withdrawal.status {
+                WithdrawalStatus::Queued => self.submit(&withdrawal).await,
+                WithdrawalStatus::Submitted => self.check_confirmation(&withdrawal).await,
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                warn!("Failed to process withdrawal {}: {}", withdrawal.id, e);
+            }
+        }
+    }
+
+    async fn submit(&self, withdrawal: &Withdrawal) -> Result<()> {
+        // The held amount is already out of the balance, so it is still covered while this stays non-negative
+        if self.remaining_equity(&withdrawal.user_address).await? < Decimal::ZERO {
+            return self.fail(withdrawal, "withdrawable equity fell below the requested amount").await;
+        }
+
+        let transaction = match self.chain_client.sign_withdrawal(&withdrawal.user_address, withdrawal.amount).await {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                if withdrawal.attempts + 1 >= self.config.max_attempts {
+                    return self.fail(withdrawal, &e.to_string()).await;
+                }
+                warn!("Withdrawal {} could not be signed, will retry: {}", withdrawal.id, e);
+                return self.database.record_withdrawal_attempt(withdrawal.id, &e.to_string()).await;
+            }
+        };
+
+        // Recorded before it can reach the chain; unrecorded, it is never submitted
+        self.database.mark_withdrawal_submitted(withdrawal.id, &transaction.tx_hash, transaction.sequence_number).await?;
+
+        // Whether it arrived is left to `check_confirmation`, which looks the hash up
+        if let Err(e) = self.chain_client.submit_prepared(&transaction).await {
+            warn!("Withdrawal {} submission of {} returned an error, checking its hash: {}",
+                withdrawal.id, transaction.tx_hash, e);
+            return Ok(());
+        }
+        info!("Submitted withdrawal {} of {} for {}: {}",
+            withdrawal.id, withdrawal.amount, withdrawal.user_address, transaction.tx_hash);
+        Ok(())
+    }
+
+    async fn check_confirmation(&self, withdrawal: &Withdrawal) -> Result<()> {
+        let Some(tx_hash) = withdrawal.tx_hash.as_deref() else {
+            return self.fail(withdrawal, "submitted without a transaction hash").await;
+        };
+
+        match self.chain_client.check_transaction_status(tx_hash).await? {
+            TransactionStatus::Success { .. } => {
+                self.database.complete_withdrawal(withdrawal.id).await?;
+                // Sync before releasing, so the withdrawn amount never shows as available again
+                if let Err(e) = self.collateral_ledger.sync_user(&withdrawal.user_address).await {
+                    warn!("Failed to sync collateral after withdrawal for {}: {}", withdrawal.user_address, e);
+                }
+                self.collateral_ledger.release(&withdrawal.user_address, usdc_amount(withdrawal.amount)).await;
+                info!("Withdrawal {} of {} for {} completed", withdrawal.id, withdrawal.amount, withdrawal.user_address);
+                Ok(())
+            }
+            TransactionStatus::Failed { vm_status, .. } => {
+                self.fail(withdrawal, &self.chain_client.decode_vm_status(&vm_status).to_string()).await
+            }
+            TransactionStatus::Pending => {
+                // Past the timeout the transaction has expired and can no longer commit
+                let timeout = chrono::Duration::seconds(self.config.confirmation_timeout_secs as i64);
+                if Utc::now() - withdrawal.updated_at <= timeout {
+                    return Ok(());
+                }
+                let reason = format!("transaction {} was not committed in time", tx_hash);
+                if withdrawal.attempts >= self.config.max_attempts {
+                    return self.fail(withdrawal, &reason).await;
+                }
+                warn!("Withdrawal {} will be signed again: {}", withdrawal.id, reason);
+                self.database.requeue_withdrawal(withdrawal.id, &reason).await
+            }
+        }
+    }
+
+    /// Withdrawable equity with pending withdrawals already taken out; negative
+    /// once they are no longer covered.
+    async fn remaining_equity(&self, user_address: &str) -> Result<Decimal> {
+        let collateral = self.collateral_ledger.balance(user_address).await?;
+        let mark_prices: HashMap<u64, Decimal> = self.price_service
+            .get_all_prices()
+            .await
+            .into_iter()
+            .map(|p| (p.market_id, p.mark_price))
+            .collect();
+
+        Ok(self.margin_engine.read().await.withdrawable(user_address, collateral, &mark_prices))
+    }
+
+    async fn fail(&self, withdrawal: &Withdrawal, reason: &str) -> Result<()> {
+        self.database.fail_withdrawal(withdrawal.id, reason).await?;
+        self.collateral_ledger.release(&withdrawal.user_address, usdc_amount(withdrawal.amount)).await;
+        warn!("Withdrawal {} of {} for {} failed: {}", withdrawal.id, withdrawal.amount, withdrawal.user_address, reason);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chain::USDC_UNIT,
+        matching_engine::MatchingEngine,
+        mock_chain::testing::{mock_chain, test_config, ScratchDatabase},
+        pricing::MockPriceSource,
+        redis_client::RedisClient,
+    };
+
+    const USER: &str = "0xa11ce";
+
+    #[tokio::test]
+    #[ignore = "needs Postgres in TEST_DATABASE_URL and Redis"]
+    async fn lost_submission_response_does_not_withdraw_twice() {
+        let mut config = test_config();
+        // Every admin transaction commits, but its submission response is lost
+        config.aptos.mock_chain.lose_response_every = 1;
+        let redis_url = std::env::var("TEST_REDIS_URL").unwrap_or(config.redis.url.clone());
+        let scratch = ScratchDatabase::create("withdrawal").await;
+        let database = scratch.database.clone();
+
+        let (chain, _) = mock_chain(&config.aptos).await;
+        let chain = Arc::new(chain);
+        chain.deposit_funds(USER, 1_000 * USDC_UNIT).await.unwrap();
+
+        let redis = RedisClient::new(&redis_url).await.unwrap();
+        let matching_engine = MatchingEngine::new(database.clone(), Arc::new(RwLock::new(redis))).await.unwrap();
+        let price_service = Arc::new(PriceService::new(
+            Arc::new(MockPriceSource::new(HashMap::new())),
+            Arc::new(RwLock::new(matching_engine)),
+            config.pricing.clone(),
+        ));
+        let service = WithdrawalService::new(
+            chain.clone(),
+            database.clone(),
+            Arc::new(CollateralLedger::new(chain.clone(), database.clone(), config.collateral.clone()).await.unwrap()),
+            Arc::new(RwLock::new(MarginEngine::new(&config.markets))),
+            price_service,
+            config.withdrawal.clone(),
+        ).await.unwrap();
+
+        let withdrawal = service.request_withdrawal(Uuid::new_v4(), USER, 400 * USDC_UNIT).await.unwrap();
+
+        // The submission errors, yet the recorded hash is what gets checked
+        service.process_withdrawals().await;
+        let submitted = database.get_withdrawal(withdrawal.id).await.unwrap().unwrap();
+        assert_eq!(submitted.status, WithdrawalStatus::Submitted);
+        assert!(submitted.tx_hash.is_some());
+        assert!(submitted.sequence_number.is_some());
+
+        service.process_withdrawals().await;
+        service.process_withdrawals().await;
+        let completed = database.get_withdrawal(withdrawal.id).await.unwrap().unwrap();
+        assert_eq!(completed.status, WithdrawalStatus::Completed);
+        assert_eq!(completed.tx_hash, submitted.tx_hash);
+        assert_eq!(completed.attempts, 1);
+        assert_eq!(chain.get_user_collateral(USER).await.unwrap(), 600 * USDC_UNIT);
+
+        drop(service);
+        drop(database);
+        scratch.drop().await;
+    }
+}